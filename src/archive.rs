@@ -0,0 +1,1019 @@
+use crate::part::part_path;
+use crate::stats::Stats;
+use crate::telemetry;
+use crate::throttle::{Throttle, ThrottledReader, ThrottledWriter};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// The archive containers understood by this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guesses the format from a file name (`.tar.gz`, `.tgz` or `.zip`).
+    pub fn from_path(path: &str) -> Option<ArchiveFormat> {
+        let lower = path.to_ascii_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if lower.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// The file extension, without a leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// Tuning knobs shared by the streaming archive and compression helpers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    /// Size of the buffers used when streaming data, in bytes.
+    pub buffer_size: usize,
+    /// Maximum combined read and write bandwidth in bytes per second. `None` means unlimited.
+    pub rate_limit: Option<u64>,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            rate_limit: None,
+        }
+    }
+}
+
+impl ArchiveOptions {
+    /// Returns options limited to `bytes_per_second` of I/O.
+    pub fn with_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.rate_limit = Some(bytes_per_second);
+        self
+    }
+
+    /// Returns options streaming through buffers of `buffer_size` bytes.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    fn throttle(&self) -> Option<Throttle> {
+        self.rate_limit.filter(|&r| r > 0).map(Throttle::new)
+    }
+}
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek> WriteSeek for T {}
+
+fn open_input(
+    path: &Path,
+    options: &ArchiveOptions,
+    throttle: &Option<Throttle>,
+) -> io::Result<Box<dyn ReadSeek>> {
+    let reader = io::BufReader::with_capacity(options.buffer_size.max(1), fs::File::open(path)?);
+    Ok(match throttle {
+        Some(throttle) => Box::new(ThrottledReader::new(reader, throttle.clone())),
+        None => Box::new(reader),
+    })
+}
+
+fn create_output(
+    path: &Path,
+    options: &ArchiveOptions,
+    throttle: &Option<Throttle>,
+) -> io::Result<Box<dyn WriteSeek>> {
+    let file = fs::File::create(path)?;
+    Ok(match throttle {
+        Some(throttle) => Box::new(io::BufWriter::with_capacity(
+            options.buffer_size.max(1),
+            ThrottledWriter::new(file, throttle.clone()),
+        )),
+        None => Box::new(io::BufWriter::with_capacity(
+            options.buffer_size.max(1),
+            file,
+        )),
+    })
+}
+
+fn strip_archive_extension(path: &str) -> &str {
+    let lower = path.to_ascii_lowercase();
+    for ext in [".tar.gz", ".tgz", ".zip"] {
+        if lower.ends_with(ext) {
+            return &path[..path.len() - ext.len()];
+        }
+    }
+    path
+}
+
+fn unknown_format(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported archive format: {}", path),
+    )
+}
+
+/// Converts an archive from one container format to another.
+///
+/// Entries are streamed straight from the source archive into the new one, so nothing
+/// is extracted to disk. The new archive is written next to `src` with the extension of
+/// `dest_format`, e.g. `logs.tar.gz` becomes `logs.zip`. Converting an archive to the
+/// format it is already in, which would overwrite it, returns `ErrorKind::InvalidInput`.
+///
+/// The new archive is built in a `.part` file and renamed into place once complete, so
+/// a failed conversion leaves no partial archive behind. Zip has no hard links, so a
+/// hard link in a tar.gz becomes a copy of the file it links to.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the path of the source archive. Its format is detected from the extension.
+/// * `dest_format` - The format of the archive to produce.
+///
+/// # Returns
+///
+/// * `std::io::Result<String>` - A Result containing the path of the new archive, or an error.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::ArchiveFormat;
+///
+/// let zip = bbq::convert_archive("/path/to/logs.tar.gz", ArchiveFormat::Zip).unwrap();
+/// assert_eq!(zip, "/path/to/logs.zip");
+/// ```
+pub fn convert_archive(src: &str, dest_format: ArchiveFormat) -> io::Result<String> {
+    convert_archive_with_options(src, dest_format, &ArchiveOptions::default())
+}
+
+/// Converts an archive from one container format to another, honoring `options`.
+///
+/// See [`convert_archive`].
+pub fn convert_archive_with_options(
+    src: &str,
+    dest_format: ArchiveFormat,
+    options: &ArchiveOptions,
+) -> io::Result<String> {
+    let src_format = ArchiveFormat::from_path(src).ok_or_else(|| unknown_format(src))?;
+    let dest = format!(
+        "{}.{}",
+        strip_archive_extension(src),
+        dest_format.extension()
+    );
+    let same = dest == src
+        || fs::canonicalize(&dest).is_ok_and(|d| fs::canonicalize(src).is_ok_and(|s| d == s));
+    if same {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is already a {:?} archive", src, dest_format),
+        ));
+    }
+    let part = part_path(Path::new(&dest));
+    if let Err(e) = convert_into(src, src_format, &part, dest_format, options) {
+        let _ = fs::remove_file(&part);
+        return Err(e);
+    }
+    fs::rename(&part, &dest)?;
+    Ok(dest)
+}
+
+fn convert_into(
+    src: &str,
+    src_format: ArchiveFormat,
+    dest: &Path,
+    dest_format: ArchiveFormat,
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let throttle = options.throttle();
+    let open = || open_input(Path::new(src), options, &throttle);
+    let mut output = create_output(dest, options, &throttle)?;
+    match (src_format, dest_format) {
+        (ArchiveFormat::TarGz, ArchiveFormat::Zip) => tar_gz_to_zip(open, output)?,
+        (ArchiveFormat::Zip, ArchiveFormat::TarGz) => zip_to_tar_gz(open()?, output)?,
+        _ => {
+            copy_with_buffer(&mut open()?, &mut output, options.buffer_size)?;
+            output.flush()?;
+        }
+    }
+    fs::File::open(dest)?.sync_all()
+}
+
+// Streams the tar.gz `open` returns into a zip. The tar.gz is read again for every
+// level of hard links, writing the content of each linked file under the next of its
+// link names, since the data of an entry can't be read twice from one stream.
+fn tar_gz_to_zip<R, W, F>(mut open: F, output: W) -> io::Result<()>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut() -> io::Result<R>,
+{
+    let mut archive = tar::Archive::new(GzDecoder::new(open()?));
+    let mut zip = zip::ZipWriter::new(output);
+    // The names linked to each regular file, with the zip options of each link.
+    let mut links: HashMap<String, Vec<(String, zip::write::SimpleFileOptions)>> = HashMap::new();
+    let mut files = HashSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut options = zip::write::SimpleFileOptions::default()
+            .unix_permissions(header.mode().unwrap_or(0o644))
+            .large_file(header.size().unwrap_or(0) >= u32::MAX as u64);
+        if let Some(mtime) = header.mtime().ok().and_then(zip_datetime) {
+            options = options.last_modified_time(mtime);
+        }
+        let entry_type = header.entry_type();
+        if entry_type.is_dir() {
+            zip.add_directory(name, options).map_err(io::Error::other)?;
+        } else if entry_type.is_symlink() {
+            let target = entry
+                .link_name()?
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            zip.add_symlink(name, target, options)
+                .map_err(io::Error::other)?;
+        } else if entry_type == tar::EntryType::Link {
+            let target = entry
+                .link_name()?
+                .map(|t| t.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            links.entry(target).or_default().push((name, options));
+        } else if entry_type.is_file() {
+            zip.start_file(name.as_str(), options)
+                .map_err(io::Error::other)?;
+            io::copy(&mut entry, &mut zip)?;
+            files.insert(name);
+        }
+    }
+    if let Some(target) = links.keys().find(|target| !files.contains(*target)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "hard link to {}, which is not a file in the archive",
+                target
+            ),
+        ));
+    }
+    let levels = links.values().map(Vec::len).max().unwrap_or(0);
+    for level in 0..levels {
+        let mut archive = tar::Archive::new(GzDecoder::new(open()?));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if let Some((link, options)) = links.get(&name).and_then(|names| names.get(level)) {
+                zip.start_file(link.as_str(), *options)
+                    .map_err(io::Error::other)?;
+                io::copy(&mut entry, &mut zip)?;
+            }
+        }
+    }
+    zip.finish().map_err(io::Error::other)?.flush()
+}
+
+fn zip_to_tar_gz<R: Read + Seek, W: Write>(input: R, output: W) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(input).map_err(io::Error::other)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(io::Error::other)?;
+        let name = file.name().to_string();
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(file.unix_mode().unwrap_or(0o644) & 0o7777);
+        header.set_mtime(file.last_modified().map(unix_time).unwrap_or(0));
+        if file.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            builder.append_data(&mut header, &name, io::empty())?;
+        } else if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, &name, target)?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size());
+            builder.append_data(&mut header, &name, &mut file)?;
+        }
+    }
+    builder.into_inner()?.finish()?.flush()
+}
+
+/// Archives a directory into `name.tar.gz` without shelling out to `tar`.
+///
+/// Unlike [`archive_dir`](crate::archive_dir), this streams the files itself, so it can
+/// honor the buffer size and rate limit in `options`. Entries are stored under the
+/// directory's own name, as `tar czf name.tar.gz dir` would.
+///
+/// # Arguments
+///
+/// * `dir` - The path of the directory to be archived.
+/// * `name` - The name of the tar.gz file, without the extension.
+/// * `options` - Buffering and throttling options.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::ArchiveOptions;
+///
+/// // Back up in the background at no more than 5 MiB/s.
+/// let options = ArchiveOptions::default().with_rate_limit(5 * 1024 * 1024);
+/// bbq::archive_dir_with_options("/var/lib/app", "/backups/app", &options).unwrap();
+/// ```
+pub fn archive_dir_with_options(dir: &str, name: &str, options: &ArchiveOptions) -> io::Result<()> {
+    archive_dir_with_stats(dir, name, options).map(|_| ())
+}
+
+/// Like [`archive_dir_with_options`], but reports what the run cost.
+///
+/// # Returns
+///
+/// * `std::io::Result<Stats>` - A Result containing the files archived, the bytes read from them, the size of the archive written, and the time taken.
+pub fn archive_dir_with_stats(
+    dir: &str,
+    name: &str,
+    options: &ArchiveOptions,
+) -> io::Result<Stats> {
+    let started = Instant::now();
+    let dir = Path::new(dir);
+    let _span = telemetry::enter("archive", dir);
+    let base = dir
+        .file_name()
+        .map(Path::new)
+        .unwrap_or_else(|| Path::new("."));
+    let dest = format!("{}.tar.gz", name);
+    let throttle = options.throttle();
+    let output = create_output(Path::new(&dest), options, &throttle)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+    builder.follow_symlinks(false);
+    let mut stats = Stats::default();
+    append_tree(&mut builder, dir, base, options, &throttle, &mut stats)?;
+    builder.into_inner()?.finish()?.flush()?;
+    stats.bytes_written = fs::metadata(&dest)?.len();
+    stats.duration = started.elapsed();
+    telemetry::finished(&stats);
+    Ok(stats)
+}
+
+fn append_tree<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+    options: &ArchiveOptions,
+    throttle: &Option<Throttle>,
+    stats: &mut Stats,
+) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    stats.files_visited += 1;
+    if metadata.is_dir() {
+        builder.append_dir(name, path)?;
+        let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            append_tree(
+                builder,
+                &entry.path(),
+                &name.join(entry.file_name()),
+                options,
+                throttle,
+                stats,
+            )?;
+        }
+    } else if metadata.is_file() {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        let mut input = open_input(path, options, throttle)?;
+        builder.append_data(&mut header, name, &mut input)?;
+        stats.bytes_read += metadata.len();
+        telemetry::file_archived(path, metadata.len());
+    } else if metadata.file_type().is_symlink() {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        builder.append_link(&mut header, name, fs::read_link(path)?)?;
+    }
+    Ok(())
+}
+
+/// The block size [`archive_dir_aligned`] aligns file data to when none is given.
+pub const DEFAULT_ARCHIVE_BLOCK_SIZE: u64 = 4096;
+
+/// Archives a directory into an uncompressed tar at `dest` laid out for block-level
+/// deduplication.
+///
+/// Entries are written in sorted path order, with their owners cleared, so two runs over
+/// the same tree produce the same bytes. The data of every file starts on a multiple of
+/// `block_size` within the archive, so a file that didn't change between nightly
+/// archives occupies identical blocks in both, whatever changed before it. The gaps are
+/// filled with pax comment records, which every tar reader ignores.
+///
+/// The archive is left uncompressed: compressing it whole would undo the alignment. Feed
+/// it to a deduplicating store as is, or compress it with `gzip --rsyncable` or `zstd
+/// --rsyncable`.
+///
+/// # Arguments
+///
+/// * `dir` - The path of the directory to be archived. Entries are stored under its name.
+/// * `dest` - The path of the tar file to create.
+/// * `block_size` - The alignment, in bytes. A multiple of 512, such as [`DEFAULT_ARCHIVE_BLOCK_SIZE`].
+///
+/// # Returns
+///
+/// * `std::io::Result<Stats>` - A Result containing the files archived, the bytes read from them and the size of the archive. A `block_size` that isn't a multiple of 512 returns `ErrorKind::InvalidInput`.
+///
+/// # Example
+///
+/// ```no_run
+/// let stats = bbq::archive_dir_aligned("/srv/data", "/backups/data.tar", bbq::DEFAULT_ARCHIVE_BLOCK_SIZE).unwrap();
+/// println!("{} files in {} bytes", stats.files_visited, stats.bytes_written);
+/// ```
+pub fn archive_dir_aligned(dir: &str, dest: &str, block_size: u64) -> io::Result<Stats> {
+    if block_size == 0 || !block_size.is_multiple_of(TAR_BLOCK) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("block size {} is not a multiple of 512", block_size),
+        ));
+    }
+    let started = Instant::now();
+    let dir = Path::new(dir);
+    let _span = telemetry::enter("archive", dir);
+    let base = dir
+        .file_name()
+        .map(Path::new)
+        .unwrap_or_else(|| Path::new("."));
+    let mut entries = Vec::new();
+    collect_sorted(dir, base, &mut entries)?;
+
+    let output = CountingWriter {
+        inner: io::BufWriter::new(fs::File::create(dest)?),
+        count: 0,
+    };
+    let mut builder = tar::Builder::new(output);
+    let mut stats = Stats::default();
+    for (path, name) in entries {
+        let metadata = fs::symlink_metadata(&path)?;
+        stats.files_visited += 1;
+        let mut header = tar::Header::new_ustar();
+        header.set_metadata_in_mode(&metadata, tar::HeaderMode::Deterministic);
+        header.set_mtime(
+            metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_secs()),
+        );
+        let name = name.to_string_lossy().replace('\\', "/");
+        let mut records = Vec::new();
+        if header.set_path(&name).is_err() {
+            records.push(pax_record("path", name.as_bytes()));
+            let short = &name.as_bytes()[..name.len().min(100)];
+            header.as_old_mut().name = [0; 100];
+            header.as_old_mut().name[..short.len()].copy_from_slice(short);
+        }
+        if metadata.is_file() {
+            let position = builder.get_ref().count;
+            write_pax_padding(&mut builder, position, block_size, records)?;
+            header.set_cksum();
+            builder.append(&header, fs::File::open(&path)?)?;
+            stats.bytes_read += metadata.len();
+            telemetry::file_archived(&path, metadata.len());
+            continue;
+        }
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&path)?;
+            let target = target.to_string_lossy();
+            if header.set_link_name(target.as_ref()).is_err() {
+                records.push(pax_record("linkpath", target.as_bytes()));
+            }
+        }
+        if !records.is_empty() {
+            append_pax(&mut builder, &records.concat())?;
+        }
+        header.set_cksum();
+        builder.append(&header, io::empty())?;
+    }
+    builder.into_inner()?.inner.into_inner()?.sync_all()?;
+    stats.bytes_written = fs::metadata(dest)?.len();
+    stats.duration = started.elapsed();
+    telemetry::finished(&stats);
+    Ok(stats)
+}
+
+const TAR_BLOCK: u64 = 512;
+
+// The smallest pax record that can carry padding: "16 comment=" plus at least four bytes.
+const MIN_COMMENT_RECORD: u64 = 16;
+
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Lists `path` and everything under it, directories before their contents and siblings
+// in byte order, without following symlinks.
+fn collect_sorted(
+    path: &Path,
+    name: &Path,
+    entries: &mut Vec<(PathBuf, PathBuf)>,
+) -> io::Result<()> {
+    entries.push((path.to_path_buf(), name.to_path_buf()));
+    if fs::symlink_metadata(path)?.is_dir() {
+        let mut children = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        children.sort_by_key(|e| e.file_name());
+        for child in children {
+            collect_sorted(&child.path(), &name.join(child.file_name()), entries)?;
+        }
+    }
+    Ok(())
+}
+
+// Formats a pax record, "<length> <key>=<value>\n", where the length counts itself.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest + 1;
+    while length != rest + length.to_string().len() {
+        length = rest + length.to_string().len();
+    }
+    let mut record = format!("{} {}=", length, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+fn append_pax<W: Write>(builder: &mut tar::Builder<W>, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_path("PaxHeaders/entry")?;
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append(&header, data)
+}
+
+// Writes a pax header holding `records`, padded with a comment so that the data of the
+// entry after it starts on a multiple of `block_size`. Writes nothing if the data would
+// already be aligned and there are no records.
+fn write_pax_padding<W: Write>(
+    builder: &mut tar::Builder<W>,
+    position: u64,
+    block_size: u64,
+    records: Vec<Vec<u8>>,
+) -> io::Result<()> {
+    let mut data = records.concat();
+    if data.is_empty() && (position + TAR_BLOCK).is_multiple_of(block_size) {
+        return Ok(());
+    }
+    // With a pax entry of `padded` data bytes, the file's data starts at
+    // position + 512 (pax header) + padded + 512 (file header).
+    let needed = data.len() as u64 + MIN_COMMENT_RECORD;
+    let mut padded = (block_size - (position + 2 * TAR_BLOCK) % block_size) % block_size;
+    while padded < needed {
+        padded += block_size;
+    }
+    let comment = padded - data.len() as u64;
+    let filler = comment as usize - "comment=\n ".len() - comment.to_string().len();
+    data.extend(pax_record("comment", &vec![b'0'; filler]));
+    debug_assert_eq!(data.len() as u64, padded);
+    append_pax(builder, &data)
+}
+
+// Writes `files` into a new tar.gz at `dest`, naming entries relative to `base`. The
+// archive is built in a part file, synced, and renamed into place, so `dest` is never
+// left half-written.
+pub(crate) fn write_tar_gz(
+    dest: &Path,
+    base: &Path,
+    files: &[PathBuf],
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let part = part_path(dest);
+    if let Err(e) = build_tar_gz(&part, base, files, options) {
+        let _ = fs::remove_file(&part);
+        return Err(e);
+    }
+    fs::rename(&part, dest)
+}
+
+fn build_tar_gz(
+    dest: &Path,
+    base: &Path,
+    files: &[PathBuf],
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let throttle = options.throttle();
+    let output = create_output(dest, options, &throttle)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+    for file in files {
+        let name = file
+            .strip_prefix(base)
+            .ok()
+            .filter(|n| !n.as_os_str().is_empty())
+            .or_else(|| file.file_name().map(Path::new))
+            .unwrap_or(file);
+        let metadata = fs::metadata(file)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        let mut input = open_input(file, options, &throttle)?;
+        builder.append_data(&mut header, name, &mut input)?;
+        telemetry::file_archived(file, metadata.len());
+    }
+    builder.into_inner()?.finish()?.flush()?;
+    fs::File::open(dest)?.sync_all()
+}
+
+/// The buffer size used by the streaming helpers when none is given.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+fn copy_with_buffer<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    buffer_size: usize,
+) -> io::Result<u64> {
+    let mut buffer = vec![0u8; buffer_size.max(1)];
+    let mut total = 0u64;
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..n])?;
+        total += n as u64;
+    }
+}
+
+/// Compresses a single file with gzip.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the file to compress.
+/// * `dest` - A string slice that holds the name of the `.gz` file to create.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of uncompressed bytes read from `src`, or an error.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::gzip_file("/var/log/app.log.1", "/var/log/app.log.1.gz").unwrap();
+/// bbq::remove_file("/var/log/app.log.1").unwrap();
+/// ```
+pub fn gzip_file(src: &str, dest: &str) -> io::Result<u64> {
+    gzip_file_with_buffer(src, dest, DEFAULT_BUFFER_SIZE)
+}
+
+/// Compresses a single file with gzip, streaming through a buffer of `buffer_size` bytes.
+///
+/// See [`gzip_file`].
+pub fn gzip_file_with_buffer(src: &str, dest: &str, buffer_size: usize) -> io::Result<u64> {
+    gzip_file_with_options(
+        src,
+        dest,
+        &ArchiveOptions::default().with_buffer_size(buffer_size),
+    )
+}
+
+/// Compresses a single file with gzip, honoring the buffer size and rate limit in `options`.
+///
+/// See [`gzip_file`].
+pub fn gzip_file_with_options(src: &str, dest: &str, options: &ArchiveOptions) -> io::Result<u64> {
+    let throttle = options.throttle();
+    let mut input = open_input(Path::new(src), options, &throttle)?;
+    let output = create_output(Path::new(dest), options, &throttle)?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    let total = copy_with_buffer(&mut input, &mut encoder, options.buffer_size)?;
+    encoder.finish()?.flush()?;
+    Ok(total)
+}
+
+/// Decompresses a single gzip file.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the `.gz` file to decompress.
+/// * `dest` - A string slice that holds the name of the file to create.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of decompressed bytes written to `dest`, or an error.
+pub fn gunzip_file(src: &str, dest: &str) -> io::Result<u64> {
+    gunzip_file_with_buffer(src, dest, DEFAULT_BUFFER_SIZE)
+}
+
+/// Decompresses a single gzip file, streaming through a buffer of `buffer_size` bytes.
+///
+/// See [`gunzip_file`].
+pub fn gunzip_file_with_buffer(src: &str, dest: &str, buffer_size: usize) -> io::Result<u64> {
+    gunzip_file_with_options(
+        src,
+        dest,
+        &ArchiveOptions::default().with_buffer_size(buffer_size),
+    )
+}
+
+/// Decompresses a single gzip file, honoring the buffer size and rate limit in `options`.
+///
+/// See [`gunzip_file`].
+pub fn gunzip_file_with_options(
+    src: &str,
+    dest: &str,
+    options: &ArchiveOptions,
+) -> io::Result<u64> {
+    let throttle = options.throttle();
+    let mut decoder = GzDecoder::new(open_input(Path::new(src), options, &throttle)?);
+    let mut output = create_output(Path::new(dest), options, &throttle)?;
+    let total = copy_with_buffer(&mut decoder, &mut output, options.buffer_size)?;
+    output.flush()?;
+    Ok(total)
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn unix_time(time: zip::DateTime) -> u64 {
+    let days = days_from_civil(time.year() as i64, time.month() as u32, time.day() as u32);
+    let secs =
+        days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+    secs.max(0) as u64
+}
+
+fn zip_datetime(secs: u64) -> Option<zip::DateTime> {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month as u8,
+        day as u8,
+        (rem / 3600) as u8,
+        (rem % 3600 / 60) as u8,
+        (rem % 60) as u8,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_tar_gz(path: &Path) {
+        let file = fs::File::create(path).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let data = b"hello";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o640);
+        header.set_mtime(1_700_000_000);
+        builder
+            .append_data(&mut header, "logs/a.log", &data[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_convert_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("bundle.tar.gz");
+        build_tar_gz(&src);
+
+        let zip_path = convert_archive(src.to_str().unwrap(), ArchiveFormat::Zip).unwrap();
+        assert!(zip_path.ends_with("bundle.zip"));
+        let mut zip = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        let mut text = String::new();
+        zip.by_name("logs/a.log")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello");
+
+        fs::remove_file(&src).unwrap();
+        let back = convert_archive(&zip_path, ArchiveFormat::TarGz).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(back).unwrap()));
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.header().mtime().unwrap(), 1_700_000_000);
+        assert_eq!(entry.header().mode().unwrap() & 0o777, 0o640);
+        let mut text = String::new();
+        entry.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn test_convert_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("linked.tar.gz");
+        let file = fs::File::create(&src).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "data/a.log", &b"hello"[..])
+            .unwrap();
+        for name in ["data/b.log", "data/c.log"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            header.set_mode(0o644);
+            builder
+                .append_link(&mut header, name, "data/a.log")
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let zip_path = convert_archive(src.to_str().unwrap(), ArchiveFormat::Zip).unwrap();
+        let mut zip = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        for name in ["data/a.log", "data/b.log", "data/c.log"] {
+            let mut text = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, "hello");
+        }
+    }
+
+    #[test]
+    fn test_convert_keeps_existing_dest_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("broken.tar.gz");
+        fs::write(&src, b"not a tar.gz").unwrap();
+        let dest = dir.path().join("broken.zip");
+        fs::write(&dest, b"previous").unwrap();
+
+        assert!(convert_archive(src.to_str().unwrap(), ArchiveFormat::Zip).is_err());
+        assert_eq!(fs::read(&dest).unwrap(), b"previous");
+        assert!(!dir.path().join("broken.zip.part").exists());
+    }
+
+    #[test]
+    fn test_convert_to_same_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("bundle.tar.gz");
+        build_tar_gz(&src);
+        let before = fs::read(&src).unwrap();
+
+        let err = convert_archive(src.to_str().unwrap(), ArchiveFormat::TarGz).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&src).unwrap(), before);
+    }
+
+    #[test]
+    fn test_archive_dir_aligned() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("data");
+        let long = "d".repeat(120);
+        fs::create_dir_all(src.join(&long)).unwrap();
+        fs::write(src.join("b.bin"), vec![7u8; 5000]).unwrap();
+        fs::write(src.join("a.txt"), "hello").unwrap();
+        fs::write(src.join(&long).join("c.txt"), "deep").unwrap();
+        let first = dir.path().join("first.tar");
+        let second = dir.path().join("second.tar");
+        let src = src.to_str().unwrap();
+
+        archive_dir_aligned(src, first.to_str().unwrap(), 4096).unwrap();
+        archive_dir_aligned(src, second.to_str().unwrap(), 4096).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+
+        let mut archive = tar::Archive::new(fs::File::open(&first).unwrap());
+        let mut files = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            if entry.header().entry_type().is_file() {
+                assert_eq!(entry.raw_file_position() % 4096, 0);
+                files.push(entry.path().unwrap().to_string_lossy().into_owned());
+            }
+        }
+        let deep = format!("data/{}/c.txt", long);
+        assert_eq!(
+            files,
+            vec!["data/a.txt".to_string(), "data/b.bin".into(), deep]
+        );
+        assert_eq!(
+            archive_dir_aligned(src, first.to_str().unwrap(), 1000)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        for days in [-1, 0, 11016, 19675, 50000] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("app.log");
+        let packed = dir.path().join("app.log.gz");
+        let restored = dir.path().join("restored.log");
+        let data = "line\n".repeat(10_000);
+        fs::write(&plain, &data).unwrap();
+
+        let read =
+            gzip_file_with_buffer(plain.to_str().unwrap(), packed.to_str().unwrap(), 1024).unwrap();
+        assert_eq!(read, data.len() as u64);
+        assert!(fs::metadata(&packed).unwrap().len() < read);
+
+        gunzip_file(packed.to_str().unwrap(), restored.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(restored).unwrap(), data);
+    }
+
+    #[test]
+    fn test_archive_dir_with_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("data");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/a.txt"), "a").unwrap();
+        let name = dir.path().join("backup");
+        let options = ArchiveOptions::default().with_rate_limit(1024 * 1024);
+        archive_dir_with_options(src.to_str().unwrap(), name.to_str().unwrap(), &options).unwrap();
+
+        let file = fs::File::open(dir.path().join("backup.tar.gz")).unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.contains(&"data/sub/a.txt".to_string()));
+    }
+
+    #[test]
+    fn test_archive_dir_with_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("data");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.txt"), "hello").unwrap();
+        let name = dir.path().join("backup");
+        let stats = archive_dir_with_stats(
+            src.to_str().unwrap(),
+            name.to_str().unwrap(),
+            &ArchiveOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(stats.files_visited, 2);
+        assert_eq!(stats.bytes_read, 5);
+        assert_eq!(
+            stats.bytes_written,
+            fs::metadata(dir.path().join("backup.tar.gz"))
+                .unwrap()
+                .len()
+        );
+    }
+}
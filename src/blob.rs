@@ -0,0 +1,180 @@
+use crate::chunk::{Chunker, ChunkerConfig};
+use crate::hash::sha256_hex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A reference to one chunk of a [`ChunkedFile`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    /// The hex SHA-256 of the chunk, which is also its key in the blob store.
+    pub hash: String,
+    pub size: u64,
+}
+
+/// The recipe for reassembling a file from blobs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkedFile {
+    pub size: u64,
+    pub chunks: Vec<ChunkRef>,
+}
+
+/// A content-addressed store of blobs, keyed by their SHA-256.
+///
+/// Blobs live under `root/ab/abcdef...`. Storing the same content twice is a no-op, so
+/// storing files as content-defined chunks dedups unchanged data between backups.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{BlobStore, ChunkerConfig};
+///
+/// let store = BlobStore::open("/backups/blobs").unwrap();
+/// let recipe = store.put_file("/srv/vm/disk.img", &ChunkerConfig::default()).unwrap();
+/// store.restore_file(&recipe, "/tmp/disk.img").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    /// Opens the store at `root`, creating the directory if needed.
+    pub fn open(root: &str) -> io::Result<BlobStore> {
+        fs::create_dir_all(root)?;
+        Ok(BlobStore {
+            root: PathBuf::from(root),
+        })
+    }
+
+    /// The directory the blobs are stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn blob_path(&self, hash: &str) -> io::Result<PathBuf> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid blob hash: {}", hash),
+            ));
+        }
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+
+    /// Returns true if a blob with the given hash is stored.
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blob_path(hash).map(|p| p.is_file()).unwrap_or(false)
+    }
+
+    /// Stores `data` and returns its hash. Existing blobs are not rewritten.
+    pub fn put(&self, data: &[u8]) -> io::Result<String> {
+        let hash = sha256_hex(data);
+        let path = self.blob_path(&hash)?;
+        if path.is_file() {
+            return Ok(hash);
+        }
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir)?;
+        // Write under a temporary name and rename, so a crash never leaves a truncated
+        // blob under a valid hash.
+        let tmp = dir.join(format!(".{}.tmp", hash));
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(hash)
+    }
+
+    /// Reads the blob with the given hash.
+    ///
+    /// A blob whose content no longer matches its hash returns an `ErrorKind::InvalidData` error.
+    pub fn get(&self, hash: &str) -> io::Result<Vec<u8>> {
+        let data = fs::read(self.blob_path(hash)?)?;
+        if sha256_hex(&data) != hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("blob {} is corrupt", hash),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Splits `file` into content-defined chunks and stores each one.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A string slice that holds the name of the file to store.
+    /// * `config` - The chunk size limits.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<ChunkedFile>` - A Result containing the recipe needed to restore the file.
+    pub fn put_file(&self, file: &str, config: &ChunkerConfig) -> io::Result<ChunkedFile> {
+        let mut recipe = ChunkedFile {
+            size: 0,
+            chunks: Vec::new(),
+        };
+        for chunk in Chunker::new(fs::File::open(file)?, *config)? {
+            let chunk = chunk?;
+            recipe.size += chunk.len() as u64;
+            recipe.chunks.push(ChunkRef {
+                hash: self.put(&chunk)?,
+                size: chunk.len() as u64,
+            });
+        }
+        Ok(recipe)
+    }
+
+    /// Reassembles a file stored with [`BlobStore::put_file`] at `dest`.
+    pub fn restore_file(&self, recipe: &ChunkedFile, dest: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(dest)?);
+        for chunk in &recipe.chunks {
+            file.write_all(&self.get(&chunk.hash)?)?;
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_file_dedups_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::open(dir.path().join("blobs").to_str().unwrap()).unwrap();
+        let config = ChunkerConfig {
+            min_size: 256,
+            avg_size: 1024,
+            max_size: 4096,
+        };
+        let mut seed = 7u64;
+        let mut data: Vec<u8> = (0..50_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (seed >> 56) as u8
+            })
+            .collect();
+        let file = dir.path().join("db");
+        fs::write(&file, &data).unwrap();
+        let first = store.put_file(file.to_str().unwrap(), &config).unwrap();
+
+        data[25_000] ^= 0xff;
+        fs::write(&file, &data).unwrap();
+        let second = store.put_file(file.to_str().unwrap(), &config).unwrap();
+        let new_chunks = second
+            .chunks
+            .iter()
+            .filter(|c| !first.chunks.contains(c))
+            .count();
+        assert!(new_chunks <= 2);
+
+        let restored = dir.path().join("restored");
+        store
+            .restore_file(&second, restored.to_str().unwrap())
+            .unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), data);
+    }
+}
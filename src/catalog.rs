@@ -0,0 +1,143 @@
+use crate::hash::hash_file;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// One backup run recorded in a [`Catalog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupRecord {
+    pub time: SystemTime,
+    /// The directory that was backed up.
+    pub source: String,
+    /// The archive the backup was written to.
+    pub archive: String,
+    /// The size of the archive, in bytes.
+    pub size: u64,
+    /// The SHA-256 of the manifest saved alongside the backup, if any.
+    pub manifest_hash: Option<String>,
+}
+
+impl BackupRecord {
+    /// Describes a backup of `source` that was just written to `archive`.
+    ///
+    /// The archive size is read from disk, and the manifest (if given) is hashed so
+    /// restore tooling can check it is the one that belongs to this backup.
+    pub fn new(source: &str, archive: &str, manifest: Option<&str>) -> io::Result<BackupRecord> {
+        Ok(BackupRecord {
+            time: SystemTime::now(),
+            source: source.to_string(),
+            archive: archive.to_string(),
+            size: fs::metadata(archive)?.len(),
+            manifest_hash: manifest.map(hash_file).transpose()?,
+        })
+    }
+}
+
+/// A history of backup runs, stored as a JSON file.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{BackupRecord, Catalog};
+///
+/// let mut catalog = Catalog::open("/backups/catalog.json").unwrap();
+/// bbq::archive_dir("/srv/data", "/backups/data-2024-06-01").unwrap();
+/// let record = BackupRecord::new("/srv/data", "/backups/data-2024-06-01.tar.gz", None).unwrap();
+/// catalog.record(record).unwrap();
+///
+/// let latest = catalog.latest_for("/srv/data").unwrap();
+/// println!("restore from {}", latest.archive);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    path: PathBuf,
+    records: Vec<BackupRecord>,
+}
+
+impl Catalog {
+    /// Opens the catalog stored in `file`, or starts an empty one if it doesn't exist yet.
+    pub fn open(file: &str) -> io::Result<Catalog> {
+        let records = match fs::read(file) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Catalog {
+            path: PathBuf::from(file),
+            records,
+        })
+    }
+
+    /// Every recorded run, in the order they were recorded.
+    pub fn records(&self) -> &[BackupRecord] {
+        &self.records
+    }
+
+    /// Adds a run to the catalog and saves it.
+    ///
+    /// The file is replaced atomically, so a crash never leaves a half-written catalog.
+    pub fn record(&mut self, record: BackupRecord) -> io::Result<()> {
+        self.records.push(record);
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.records).map_err(io::Error::other)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// The runs that backed up `dir`, oldest first.
+    pub fn history(&self, dir: &str) -> Vec<&BackupRecord> {
+        let mut history: Vec<&BackupRecord> = self
+            .records
+            .iter()
+            .filter(|r| same_dir(&r.source, dir))
+            .collect();
+        history.sort_by_key(|r| r.time);
+        history
+    }
+
+    /// The most recent run that backed up `dir`.
+    pub fn latest_for(&self, dir: &str) -> Option<&BackupRecord> {
+        self.history(dir).pop()
+    }
+}
+
+fn same_dir(a: &str, b: &str) -> bool {
+    Path::new(a).components().eq(Path::new(b).components())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("a.tar.gz");
+        fs::write(&archive, "data").unwrap();
+        let file = dir.path().join("catalog.json");
+
+        let mut catalog = Catalog::open(file.to_str().unwrap()).unwrap();
+        let mut older = BackupRecord::new("/srv/data", archive.to_str().unwrap(), None).unwrap();
+        older.time -= Duration::from_secs(3600);
+        let mut newer = older.clone();
+        newer.time += Duration::from_secs(60);
+        newer.archive = "b.tar.gz".to_string();
+        catalog.record(newer).unwrap();
+        catalog.record(older).unwrap();
+
+        let catalog = Catalog::open(file.to_str().unwrap()).unwrap();
+        assert_eq!(catalog.history("/srv/data/").len(), 2);
+        assert_eq!(catalog.latest_for("/srv/data").unwrap().archive, "b.tar.gz");
+        assert_eq!(catalog.history("/srv/data")[0].size, 4);
+        assert!(catalog.latest_for("/srv/other").is_none());
+    }
+}
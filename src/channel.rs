@@ -0,0 +1,180 @@
+use crate::filter::{compile_globs, glob_matches};
+use crate::find::FindOptions;
+use crate::info::FileInfo;
+use crate::walk::{Walk, WalkOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+/// The sending half of a channel that [`walk_into`] and [`find_into`] hand their results to.
+///
+/// Implemented for `std::sync::mpsc` senders, and for `crossbeam_channel::Sender` with the
+/// `crossbeam-channel` feature. A bounded channel gives backpressure: the traversal waits
+/// while the workers are behind.
+pub trait ResultSender<T> {
+    /// Sends `item`, blocking if the channel is full. Returns false once the receiving
+    /// side has hung up.
+    fn send_result(&self, item: T) -> bool;
+}
+
+impl<T> ResultSender<T> for mpsc::Sender<T> {
+    fn send_result(&self, item: T) -> bool {
+        self.send(item).is_ok()
+    }
+}
+
+impl<T> ResultSender<T> for mpsc::SyncSender<T> {
+    fn send_result(&self, item: T) -> bool {
+        self.send(item).is_ok()
+    }
+}
+
+#[cfg(feature = "crossbeam-channel")]
+impl<T> ResultSender<T> for crossbeam_channel::Sender<T> {
+    fn send_result(&self, item: T) -> bool {
+        self.send(item).is_ok()
+    }
+}
+
+/// Walks `dir` like [`Walk::with_options`], sending every entry into `sender` as soon as
+/// it is found, so workers on the other end can start before the walk is done.
+///
+/// Entries that can't be read are skipped, unless `options.stop_on_error` is set. The
+/// walk stops early, without an error, if the receiver hangs up. The sender is only
+/// borrowed: drop it afterwards to let the workers see the end of the channel.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to walk.
+/// * `options` - How deep to walk and which entries to include.
+/// * `sender` - Where to send the entries.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of entries sent.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{walk_into, WalkOptions};
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::sync_channel(256);
+/// let worker = std::thread::spawn(move || {
+///     rx.into_iter().filter(|info: &bbq::FileInfo| info.file_type == "File").count()
+/// });
+/// walk_into("/srv/data", &WalkOptions::default(), &tx).unwrap();
+/// drop(tx);
+/// println!("{} files", worker.join().unwrap());
+/// ```
+pub fn walk_into<S>(dir: &str, options: &WalkOptions, sender: &S) -> io::Result<u64>
+where
+    S: ResultSender<FileInfo> + ?Sized,
+{
+    std::fs::read_dir(dir)?;
+    let mut sent = 0;
+    for entry in Walk::with_options(dir, options) {
+        let info = match entry {
+            Ok(info) => info,
+            Err(e) if options.stop_on_error => return Err(e),
+            Err(_) => continue,
+        };
+        if !sender.send_result(info) {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+/// Finds the files under `dir` whose path relative to `dir` matches the glob `pattern`,
+/// like [`find_with`](crate::find_with), sending each match into `sender` as it is found.
+///
+/// Stops once `options.limit` files have been sent, or early, without an error, if the
+/// receiver hangs up.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of files sent. An invalid pattern returns `ErrorKind::InvalidInput`.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{find_into, FindOptions};
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel::<std::path::PathBuf>();
+/// let hasher = std::thread::spawn(move || {
+///     for path in rx {
+///         println!("{:?}", bbq::hash_file(path.to_str().unwrap()));
+///     }
+/// });
+/// find_into("/var/log/app", "**/*.gz", &FindOptions::default(), &tx).unwrap();
+/// drop(tx);
+/// hasher.join().unwrap();
+/// ```
+pub fn find_into<S>(dir: &str, pattern: &str, options: &FindOptions, sender: &S) -> io::Result<u64>
+where
+    S: ResultSender<PathBuf> + ?Sized,
+{
+    let pattern = compile_globs(&[pattern.to_string()])?.remove(0);
+    let root = Path::new(dir);
+    let walk_options = WalkOptions {
+        max_depth: options.max_depth,
+        include_hidden: options.include_hidden,
+        ..Default::default()
+    };
+    std::fs::read_dir(dir)?;
+    let mut sent = 0;
+    for info in Walk::with_options(dir, &walk_options).flatten() {
+        if options.limit.is_some_and(|limit| sent >= limit as u64) {
+            break;
+        }
+        let eligible =
+            info.file_type == "File" || (options.include_dirs && info.file_type == "Directory");
+        let path = PathBuf::from(info.file_path);
+        if !eligible || !glob_matches(&pattern, path.strip_prefix(root).unwrap_or(&path)) {
+            continue;
+        }
+        if !sender.send_result(path) {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_walk_and_find_into() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a.log"), "x").unwrap();
+        fs::write(dir.path().join("sub/b.log"), "x").unwrap();
+        fs::write(dir.path().join("sub/c.txt"), "x").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        assert_eq!(walk_into(root, &WalkOptions::default(), &tx).unwrap(), 4);
+        drop(tx);
+        assert_eq!(rx.iter().count(), 4);
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        let worker = std::thread::spawn(move || rx.iter().collect::<Vec<PathBuf>>());
+        assert_eq!(
+            find_into(root, "**/*.log", &FindOptions::default(), &tx).unwrap(),
+            2
+        );
+        drop(tx);
+        assert_eq!(worker.join().unwrap().len(), 2);
+
+        // A receiver that hung up ends the walk without an error.
+        let (tx, rx) = mpsc::channel::<FileInfo>();
+        drop(rx);
+        assert_eq!(walk_into(root, &WalkOptions::default(), &tx).unwrap(), 0);
+    }
+}
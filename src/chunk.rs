@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+
+// Random values for the gear hash, generated with splitmix64 so the table (and therefore
+// every chunk boundary) is identical across builds and platforms.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut seed: u64 = 0x6262_7163_6463_6463;
+    let mut i = 0;
+    while i < 256 {
+        seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Chunk size limits for content-defined chunking, in bytes.
+///
+/// Chunks are never smaller than `min_size` (except the last one) or larger than
+/// `max_size`, and average roughly `avg_size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkerConfig {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        ChunkerConfig {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+impl ChunkerConfig {
+    fn validate(&self) -> io::Result<()> {
+        if self.min_size == 0 || self.min_size > self.avg_size || self.avg_size > self.max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunk sizes must satisfy 0 < min_size <= avg_size <= max_size",
+            ));
+        }
+        Ok(())
+    }
+
+    // Finds the end of the first chunk in `data`, which is either at least `max_size`
+    // bytes long or the rest of the input.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        // Normalized chunking: a stricter mask before the average size and a looser one
+        // after it keeps chunk sizes close to the average.
+        let bits = usize::BITS - 1 - self.avg_size.leading_zeros();
+        let mask_strict = !0u64 << (64 - (bits + 1).min(63));
+        let mask_loose = !0u64 << (64 - bits.saturating_sub(1).max(1));
+        let normal = self.avg_size.min(data.len());
+        let end = self.max_size.min(data.len());
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            let mask = if i < normal { mask_strict } else { mask_loose };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+}
+
+/// Splits a stream into content-defined chunks (FastCDC-style gear hashing).
+///
+/// Boundaries depend on the content around them, not on offsets, so inserting or
+/// removing bytes in a large file only changes the chunks near the edit. Storing chunks
+/// by hash, as [`BlobStore::put_file`](crate::BlobStore::put_file) does, then dedups
+/// everything else.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{Chunker, ChunkerConfig};
+///
+/// let file = std::fs::File::open("/srv/db/data.db").unwrap();
+/// for chunk in Chunker::new(file, ChunkerConfig::default()).unwrap() {
+///     println!("{} bytes", chunk.unwrap().len());
+/// }
+/// ```
+pub struct Chunker<R> {
+    reader: R,
+    config: ChunkerConfig,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    /// Creates a chunker reading from `reader`.
+    ///
+    /// Inconsistent sizes in `config` return an `ErrorKind::InvalidInput` error.
+    pub fn new(reader: R, config: ChunkerConfig) -> io::Result<Self> {
+        config.validate()?;
+        Ok(Chunker {
+            reader,
+            config,
+            buffer: Vec::with_capacity(config.max_size),
+            eof: false,
+        })
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut block = [0u8; 8192];
+        while !self.eof && self.buffer.len() < self.config.max_size {
+            match self.reader.read(&mut block) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.buffer.extend_from_slice(&block[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let cut = self.config.cut_point(&self.buffer);
+        let rest = self.buffer.split_off(cut);
+        Some(Ok(std::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_survive_insertion() {
+        let config = ChunkerConfig {
+            min_size: 256,
+            avg_size: 1024,
+            max_size: 4096,
+        };
+        let mut seed = 1u64;
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (seed >> 56) as u8
+            })
+            .collect();
+        let mut edited = data.clone();
+        edited.splice(50_000..50_000, b"inserted".iter().copied());
+
+        let chunks = |data: &[u8]| -> Vec<Vec<u8>> {
+            Chunker::new(data, config)
+                .unwrap()
+                .map(|c| c.unwrap())
+                .collect()
+        };
+        let before = chunks(&data);
+        let after = chunks(&edited);
+        assert_eq!(before.concat(), data);
+        assert!(before.iter().all(|c| c.len() <= 4096));
+        let shared = after.iter().filter(|c| before.contains(c)).count();
+        assert!(shared + 3 >= after.len());
+    }
+}
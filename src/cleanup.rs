@@ -0,0 +1,1463 @@
+use crate::archive::{write_tar_gz, ArchiveOptions};
+use crate::copy::move_across_devices;
+use crate::disk::ensure_writable;
+use crate::filter::{compile_globs, glob_matches, OwnerFilter};
+use crate::info::{get_files, get_size, select_old_files};
+use crate::journal::{move_creating_parents, JournalRecord, JournalWriter};
+use crate::lock::{is_lock_file, DirLock};
+use crate::manifest::{EntryKind, Manifest};
+use crate::part::is_fresh_part_file;
+use crate::recycle::move_to_trash;
+use crate::retention::{DeletionOrder, RetentionPolicy, RetentionVictim};
+use crate::sequence::expand_sequence;
+use crate::shred::shred_file;
+use crate::stats::Stats;
+use crate::telemetry;
+use crate::walk::{walk_with, SymlinkPolicy, Visit, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Archives the oldest files of a directory and then removes them.
+///
+/// The files selected are exactly the ones [`remove_old_files`](crate::remove_old_files)
+/// would delete for the same `keep`. They are written to a tar.gz at `archive_dest`
+/// (entries named relative to `dir`), the archive is synced to disk, and only then are
+/// the originals removed. If archiving fails, nothing is deleted.
+///
+/// A `{seq}` in `archive_dest` is replaced with the next number from a counter kept next
+/// to the archives (see [`next_sequence`](crate::next_sequence)), so repeated runs, even
+/// from different processes, never overwrite each other's archives. Without `{seq}`, an
+/// existing archive at `archive_dest` is never overwritten: an `ErrorKind::AlreadyExists`
+/// error is returned before anything is deleted. Use [`archive_old_files_with`] to
+/// replace it instead.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `keep` - The maximum size (in bytes) that the directory should be.
+/// * `archive_dest` - A string slice that holds the path of the tar.gz archive to create, optionally containing `{seq}`.
+///
+/// # Returns
+///
+/// * `std::io::Result<(Option<String>, Vec<String>)>` - A Result containing the path of the archive (`None` when nothing had to be removed) and the names of the files that were removed.
+///
+/// # Example
+///
+/// ```no_run
+/// let (archive, removed) =
+///     bbq::archive_old_files("/var/log/app", 1024 * 1024 * 100, "/backups/app-old.tar.gz").unwrap();
+/// println!("archived {} files into {:?}", removed.len(), archive);
+/// ```
+pub fn archive_old_files(
+    dir: &str,
+    keep: u64,
+    archive_dest: &str,
+) -> io::Result<(Option<String>, Vec<String>)> {
+    archive_old_files_with(dir, keep, archive_dest, false)
+}
+
+/// Like [`archive_old_files`], replacing an existing archive at `archive_dest` if
+/// `overwrite` is set.
+///
+/// # Example
+///
+/// ```no_run
+/// // The archive only ever holds the latest batch.
+/// bbq::archive_old_files_with("/var/log/app", 1024 * 1024 * 100, "/backups/app-last.tar.gz", true)
+///     .unwrap();
+/// ```
+pub fn archive_old_files_with(
+    dir: &str,
+    keep: u64,
+    archive_dest: &str,
+    overwrite: bool,
+) -> io::Result<(Option<String>, Vec<String>)> {
+    let files: Vec<PathBuf> = select_old_files(dir, keep)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    if files.is_empty() {
+        return Ok((None, vec![]));
+    }
+    ensure_writable(dir)?;
+    let archive_dest = expand_sequence(archive_dest)?;
+    if !overwrite && fs::symlink_metadata(&archive_dest).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", archive_dest),
+        ));
+    }
+    write_tar_gz(
+        Path::new(&archive_dest),
+        Path::new(dir),
+        &files,
+        &ArchiveOptions::default(),
+    )?;
+    let mut removed_files = Vec::new();
+    for file in files {
+        fs::remove_file(&file)?;
+        removed_files.push(file.to_string_lossy().into_owned());
+    }
+    Ok((Some(archive_dest), removed_files))
+}
+
+/// Removes every file under `dir` (including subdirectories) last modified more than `max_age` ago.
+///
+/// Unlike [`remove_old_files`](crate::remove_old_files), the directory size plays no
+/// role: a file is removed purely because of its age. Symlinks are never removed.
+/// A file that can't be removed is left in place and reported as failed through the
+/// `tracing` events, and the rest are still removed.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `max_age` - Files whose modification time is older than this are removed.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were removed. Files that failed to be removed are not listed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// // Delete everything older than 30 days.
+/// let removed = bbq::remove_files_older_than("/var/log/app", Duration::from_secs(30 * 24 * 3600)).unwrap();
+/// ```
+pub fn remove_files_older_than(dir: &str, max_age: Duration) -> io::Result<Vec<String>> {
+    ensure_writable(dir)?;
+    let _span = telemetry::enter("remove_files_older_than", Path::new(dir));
+    let cutoff = SystemTime::now()
+        .checked_sub(max_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut removed_files = Vec::new();
+    for file in get_files(Path::new(dir))? {
+        let metadata = match fs::symlink_metadata(&file) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        if metadata.modified().is_ok_and(|modified| modified < cutoff) {
+            match fs::remove_file(&file) {
+                Ok(()) => {
+                    telemetry::file_removed(&file, metadata.len());
+                    removed_files.push(file.to_string_lossy().into_owned());
+                }
+                Err(e) => telemetry::file_failed(&file, &e.to_string()),
+            }
+        }
+    }
+    Ok(removed_files)
+}
+
+/// Keeps only the `n` most recently modified files in a directory and removes the rest.
+///
+/// Only files directly inside `dir` are considered; subdirectories and symlinks are left alone.
+/// A file that can't be removed is left in place and reported as failed through the
+/// `tracing` events, and the rest are still removed.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `n` - The number of files to keep.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were removed, oldest first. Files that failed to be removed are not listed.
+///
+/// # Example
+///
+/// ```no_run
+/// // Hourly dumps: keep the last day's worth.
+/// let removed = bbq::keep_latest_n("/var/backups/dumps", 24).unwrap();
+/// ```
+pub fn keep_latest_n(dir: &str, n: usize) -> io::Result<Vec<String>> {
+    ensure_writable(dir)?;
+    let _span = telemetry::enter("keep_latest_n", Path::new(dir));
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        // Files removed by someone else while the directory is listed are passed over.
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => continue,
+        };
+        files.push((path, metadata.modified()?, metadata.len()));
+    }
+    // Newest first; ties broken by name so repeated runs are deterministic.
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+    let mut removed_files = Vec::new();
+    for (file, _, size) in files.into_iter().skip(n).rev() {
+        match fs::remove_file(&file) {
+            Ok(()) => {
+                telemetry::file_removed(&file, size);
+                removed_files.push(file.to_string_lossy().into_owned());
+            }
+            Err(e) => telemetry::file_failed(&file, &e.to_string()),
+        }
+    }
+    Ok(removed_files)
+}
+
+/// Purges a safety-net area such as a trash or quarantine directory by age and size.
+///
+/// Files older than `max_age` are removed first, then the oldest remaining files are
+/// removed until the area fits in `max_size` bytes. Either limit may be `None`.
+/// Directories left empty are removed as well, so the area doesn't fill up with
+/// empty structure.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the trash or quarantine directory.
+/// * `max_age` - The maximum age of files to keep.
+/// * `max_size` - The maximum size (in bytes) of the whole area.
+///
+/// # Returns
+///
+/// * `std::io::Result<(u64, Vec<String>)>` - A Result containing the size of the area after purging and the names of the files that were removed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let week = Duration::from_secs(7 * 24 * 3600);
+/// let (size, purged) = bbq::purge_dir("/var/quarantine", Some(week), Some(10 * 1024 * 1024 * 1024)).unwrap();
+/// println!("quarantine holds {} bytes after purging {} files", size, purged.len());
+/// ```
+pub fn purge_dir(
+    dir: &str,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+) -> io::Result<(u64, Vec<String>)> {
+    let policy = RetentionPolicy {
+        max_age,
+        max_size,
+        ..Default::default()
+    };
+    let removed_files = policy.apply(dir)?;
+    remove_empty_dirs(dir)?;
+    Ok((get_size(dir)?, removed_files))
+}
+
+/// Removes every empty directory under `dir`, bottom-up.
+///
+/// A directory that only contains empty directories counts as empty, so whole empty
+/// trees go at once. Directories containing files or symlinks are kept, and `dir` itself
+/// is never removed.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the paths of the directories that were removed, children before parents.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::remove_old_files("/var/log/app", 1024 * 1024 * 100).unwrap();
+/// let removed = bbq::remove_empty_dirs("/var/log/app").unwrap();
+/// ```
+pub fn remove_empty_dirs(dir: &str) -> io::Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_empty_subdirs(&entry.path(), &mut removed)?;
+        }
+    }
+    Ok(removed)
+}
+
+/// The empty entries found by [`find_empty`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmptyEntries {
+    /// Zero-byte files.
+    pub files: Vec<String>,
+    /// Directories holding nothing but empty files and empty directories, children
+    /// before parents.
+    pub dirs: Vec<String>,
+}
+
+/// Finds the zero-byte files and the empty directories under `dir`, and optionally removes them.
+///
+/// A directory counts as empty when everything in it is empty, so a directory holding
+/// only zero-byte files or empty directories is listed along with them, and removing
+/// leaves no empty structure behind. Symlinks are never followed or removed, and keep
+/// their directory from being empty. `dir` itself is never listed.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `remove` - Whether to remove what is found, or only list it.
+///
+/// # Returns
+///
+/// * `std::io::Result<EmptyEntries>` - A Result containing the empty files and directories, removed if `remove` was set.
+///
+/// # Example
+///
+/// ```no_run
+/// let empties = bbq::find_empty("/srv/uploads/staging", true).unwrap();
+/// println!("removed {} files and {} directories", empties.files.len(), empties.dirs.len());
+/// ```
+pub fn find_empty(dir: &str, remove: bool) -> io::Result<EmptyEntries> {
+    if remove {
+        ensure_writable(dir)?;
+    }
+    let mut found = EmptyEntries::default();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        find_empty_entry(&entry.path(), &entry.file_type()?, remove, &mut found)?;
+    }
+    Ok(found)
+}
+
+// Returns true if `path` is empty, collecting it and everything empty under it.
+fn find_empty_entry(
+    path: &Path,
+    file_type: &fs::FileType,
+    remove: bool,
+    found: &mut EmptyEntries,
+) -> io::Result<bool> {
+    if file_type.is_file() {
+        if fs::symlink_metadata(path)?.len() > 0 {
+            return Ok(false);
+        }
+        if remove {
+            fs::remove_file(path)?;
+        }
+        found.files.push(path.to_string_lossy().into_owned());
+        return Ok(true);
+    }
+    if !file_type.is_dir() {
+        return Ok(false);
+    }
+    let mut empty = true;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        empty &= find_empty_entry(&entry.path(), &entry.file_type()?, remove, found)?;
+    }
+    if empty {
+        if remove {
+            fs::remove_dir(path)?;
+        }
+        found.dirs.push(path.to_string_lossy().into_owned());
+    }
+    Ok(empty)
+}
+
+/// The full configuration of a cleanup: a retention policy plus optional restrictions.
+///
+/// Configs are serializable so they can live in the same config files as the rest of a
+/// deployment, and be compared with [`diff_cleanup_plans`] before a change is rolled out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupConfig {
+    pub policy: RetentionPolicy,
+    /// Only files belonging to this owner are eligible for deletion.
+    #[serde(default)]
+    pub owner: Option<OwnerFilter>,
+    /// Report what would be removed without touching the filesystem.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Whether files in subdirectories are eligible too, or only files directly in the
+    /// directory. Defaults to `true`. Subdirectories emptied by a recursive run are removed.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+    /// Globs for files that are never deleted, such as `README` or `.keep` markers.
+    /// They match like the policy's globs, but hold regardless of the policy.
+    #[serde(default)]
+    pub protected: Vec<String>,
+    /// Move files to the system trash instead of deleting them. Requires the `trash`
+    /// feature; without it, a run fails with `ErrorKind::Unsupported` before touching anything.
+    #[serde(default)]
+    pub trash: bool,
+    /// After the run, remove every empty directory left under the cleaned directory,
+    /// including ones that were already empty before.
+    #[serde(default)]
+    pub prune_empty_dirs: bool,
+    /// Move files into this directory, keeping their paths relative to the cleaned
+    /// directory, instead of deleting them. Each run gets its own batch subdirectory
+    /// named after the time it ran, for [`purge_quarantine`] to remove once the grace
+    /// period is over. On the same filesystem as the cleaned directory files are simply
+    /// renamed; on another one they are copied and then removed, which is slower.
+    #[serde(default)]
+    pub quarantine: Option<String>,
+    /// Record the planned deletions in this journal file before deleting anything, and
+    /// each one as it is done, for [`resume_cleanup`](crate::resume_cleanup) and
+    /// [`rollback_cleanup`](crate::rollback_cleanup). Dry runs write no journal.
+    #[serde(default)]
+    pub journal: Option<String>,
+    /// Overwrite files this many times before unlinking them, with [`shred_file`]. Takes
+    /// precedence over `trash`; files moved elsewhere are not shredded.
+    ///
+    /// [`shred_file`]: crate::shred_file
+    #[serde(default)]
+    pub shred: Option<u32>,
+    /// What to do with symlinks. Cleanups never delete through links, so only
+    /// `SymlinkPolicy::Skip` (the default) and `SymlinkPolicy::Report`, which lists every
+    /// link in the report's `skipped`, are accepted; `Follow` fails with
+    /// `ErrorKind::InvalidInput` before touching anything.
+    #[serde(default = "default_symlinks")]
+    pub symlinks: SymlinkPolicy,
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+fn default_symlinks() -> SymlinkPolicy {
+    SymlinkPolicy::Skip
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        CleanupConfig {
+            policy: RetentionPolicy::default(),
+            owner: None,
+            dry_run: false,
+            recursive: default_recursive(),
+            protected: Vec::new(),
+            trash: false,
+            prune_empty_dirs: false,
+            quarantine: None,
+            journal: None,
+            shred: None,
+            symlinks: default_symlinks(),
+        }
+    }
+}
+
+/// What a [`CleanupConfig::run_with_hook`] hook wants done with a file about to be deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Go ahead and delete (or trash) it.
+    Delete,
+    /// Leave it in place, e.g. because another process still has it open.
+    Skip,
+    /// Move it to the given path instead of deleting it.
+    MoveTo(PathBuf),
+}
+
+/// The outcome of [`CleanupConfig::run`], suitable for audit logs.
+///
+/// Every file the policy selected ends up in exactly one of `removed`, `redirected`,
+/// `skipped` or `errors`. The run's duration is in `stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupReport {
+    /// The files that were removed, or would have been in a dry run, in deletion order.
+    pub removed: Vec<String>,
+    /// The bytes freed by the files in `removed`, or that would have been in a dry run.
+    pub bytes_freed: u64,
+    pub dry_run: bool,
+    /// Files moved elsewhere instead of deleted, by a hook or into the quarantine, with
+    /// their new paths.
+    #[serde(default)]
+    pub redirected: Vec<(String, String)>,
+    /// The bytes in `redirected`. They leave the directory, but are only freed on disk
+    /// if they went to another filesystem.
+    #[serde(default)]
+    pub bytes_moved: u64,
+    /// Files that were selected but left alone, with the reason. With
+    /// `SymlinkPolicy::Report`, every symlink is listed here too.
+    #[serde(default)]
+    pub skipped: Vec<(String, String)>,
+    /// Files that could not be removed or moved, and entries that could not be scanned,
+    /// with the error. The run carries on past them, so one unremovable file or
+    /// unreadable subdirectory doesn't stop the rest of the cleanup.
+    #[serde(default)]
+    pub errors: Vec<(String, String)>,
+    /// Empty directories that were removed.
+    #[serde(default)]
+    pub removed_dirs: Vec<String>,
+    #[serde(default)]
+    pub stats: Stats,
+}
+
+impl CleanupConfig {
+    /// Creates a config applying `policy` to every file.
+    pub fn new(policy: RetentionPolicy) -> Self {
+        CleanupConfig {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Restricts the cleanup to files belonging to `owner`.
+    pub fn owner(mut self, owner: OwnerFilter) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// Only reports what would be removed when run, without deleting anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Chooses which files are evicted first; see [`RetentionPolicy::order`].
+    pub fn order(mut self, order: DeletionOrder) -> Self {
+        self.policy.order = order;
+        self
+    }
+
+    /// Never deletes files matching `pattern`. May be called several times.
+    pub fn protect(mut self, pattern: &str) -> Self {
+        self.protected.push(pattern.to_string());
+        self
+    }
+
+    /// Moves files to the system trash instead of deleting them.
+    pub fn trash(mut self, trash: bool) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    /// Removes all empty directories under the cleaned directory after the run.
+    pub fn prune_empty_dirs(mut self, prune: bool) -> Self {
+        self.prune_empty_dirs = prune;
+        self
+    }
+
+    /// Moves files into batches under `dir` instead of deleting them.
+    pub fn quarantine(mut self, dir: &str) -> Self {
+        self.quarantine = Some(dir.to_string());
+        self
+    }
+
+    /// Keeps a journal of the run in `file`, replacing any earlier journal there.
+    pub fn journal(mut self, file: &str) -> Self {
+        self.journal = Some(file.to_string());
+        self
+    }
+
+    /// Overwrites deleted files `passes` times before unlinking them.
+    pub fn shred(mut self, passes: u32) -> Self {
+        self.shred = Some(passes);
+        self
+    }
+
+    /// Chooses what to do with symlinks; see the `symlinks` field.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Chooses whether files in subdirectories are eligible for removal.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Computes which files the cleanup would remove from `dir`, without removing anything.
+    ///
+    /// Entries that vanish or can't be read while `dir` is scanned are left out, as in
+    /// [`CleanupConfig::run`].
+    pub fn plan(&self, dir: &str) -> io::Result<Vec<RetentionVictim>> {
+        self.plan_snapshot(&Manifest::scan_lenient(dir)?.0)
+    }
+
+    /// Computes which files the cleanup would remove from the tree recorded in `snapshot`.
+    ///
+    /// `.part` files modified within [`PART_FILE_GRACE`](crate::PART_FILE_GRACE) are never
+    /// selected, since a writer may still be filling them.
+    pub fn plan_snapshot(&self, snapshot: &Manifest) -> io::Result<Vec<RetentionVictim>> {
+        let protected = compile_globs(&self.protected)?;
+        // A quarantine inside the cleaned directory must not be cleaned itself.
+        let quarantined = self
+            .quarantine
+            .as_ref()
+            .and_then(|q| Path::new(q).strip_prefix(&snapshot.root).ok());
+        self.policy.plan_matching(snapshot, &|entry| {
+            !is_lock_file(Path::new(&entry.path))
+                && !is_fresh_part_file(Path::new(&entry.path), entry.modified)
+                && (self.recursive || !entry.path.contains('/'))
+                && quarantined.is_none_or(|q| !Path::new(&entry.path).starts_with(q))
+                && !protected
+                    .iter()
+                    .any(|p| glob_matches(p, Path::new(&entry.path)))
+                && self
+                    .owner
+                    .is_none_or(|owner| owner.matches_ids(entry.uid, entry.gid))
+        })
+    }
+
+    /// Runs the cleanup on `dir`.
+    ///
+    /// In a dry run, the report lists exactly the files a real run would delete right
+    /// now, so a destructive cleanup can be previewed first. A config with only
+    /// `policy.max_size` set behaves like [`remove_old_files`](crate::remove_old_files).
+    ///
+    /// A real run holds the directory's [`DirLock`] throughout; if another process is
+    /// cleaning the directory, it fails with an [`AlreadyRunning`](crate::AlreadyRunning)
+    /// error instead.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<CleanupReport>` - A Result containing the files removed, skipped and failed, and the bytes freed. Entries that vanish during the scan are left out and unreadable ones reported in `errors`; only failing to list the directory itself or to write the journal is an error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bbq::{CleanupConfig, RetentionPolicy};
+    ///
+    /// let config = CleanupConfig::new(RetentionPolicy::new().max_size(10_000)).dry_run(true);
+    /// let report = config.run("/var/log/app").unwrap();
+    /// println!("would free {} bytes from {} files", report.bytes_freed, report.removed.len());
+    /// ```
+    pub fn run(&self, dir: &str) -> io::Result<CleanupReport> {
+        self.run_with_hook(dir, |_, _| Decision::Delete)
+    }
+
+    /// Runs the cleanup on `dir`, asking `hook` about each file before it is deleted.
+    ///
+    /// The hook sees the file's current metadata and can log it, veto the deletion, or
+    /// redirect the file elsewhere. It is called in dry runs too, so a preview reflects its
+    /// decisions. Files that vanish before their turn are reported as skipped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bbq::{CleanupConfig, Decision, RetentionPolicy};
+    ///
+    /// let config = CleanupConfig::new(RetentionPolicy::new().max_files(100));
+    /// let report = config
+    ///     .run_with_hook("/var/spool/jobs", |path, _| {
+    ///         if path.extension().is_some_and(|e| e == "lock") {
+    ///             Decision::Skip
+    ///         } else {
+    ///             Decision::Delete
+    ///         }
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn run_with_hook<F>(&self, dir: &str, mut hook: F) -> io::Result<CleanupReport>
+    where
+        F: FnMut(&Path, &fs::Metadata) -> Decision,
+    {
+        if self.trash && self.shred.is_none() && !self.dry_run && !cfg!(feature = "trash") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "moving files to the trash requires the `trash` feature",
+            ));
+        }
+        if self.symlinks == SymlinkPolicy::Follow {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cleanups never follow symlinks",
+            ));
+        }
+        let started = Instant::now();
+        let _span = telemetry::enter(
+            if self.dry_run {
+                "cleanup (dry run)"
+            } else {
+                "cleanup"
+            },
+            Path::new(dir),
+        );
+        let _lock = if self.dry_run {
+            None
+        } else {
+            ensure_writable(dir)?;
+            Some(DirLock::acquire(dir)?)
+        };
+        let mut report = CleanupReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+        // Files come and go while a live directory is cleaned, so entries that vanish or
+        // can't be read don't fail the run.
+        let (snapshot, scan_errors) = Manifest::scan_lenient(dir)?;
+        for (path, error) in scan_errors {
+            telemetry::file_failed(Path::new(&path), &error);
+            report.errors.push((path, error));
+        }
+        report.stats.files_visited = snapshot
+            .entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::File)
+            .count() as u64;
+        if self.symlinks == SymlinkPolicy::Report {
+            for entry in &snapshot.entries {
+                if entry.kind == EntryKind::Symlink && (self.recursive || !entry.path.contains('/'))
+                {
+                    let path = Path::new(dir).join(&entry.path);
+                    telemetry::file_skipped(&path, "symlink");
+                    report
+                        .skipped
+                        .push((path.to_string_lossy().into_owned(), "symlink".to_string()));
+                }
+            }
+        }
+        let victims = self.plan_snapshot(&snapshot)?;
+        let batch = match &self.quarantine {
+            Some(q) if !victims.is_empty() => {
+                Some(new_quarantine_batch(Path::new(q), self.dry_run)?)
+            }
+            _ => None,
+        };
+        let quarantine_dest = |path: &Path| {
+            batch
+                .as_ref()
+                .map(|batch| batch.join(path.strip_prefix(dir).unwrap_or(path)))
+        };
+        let mut journal = match &self.journal {
+            Some(file) if !self.dry_run => {
+                let mut writer = JournalWriter::create(file)?;
+                writer.write(&JournalRecord::Started {
+                    dir: dir.to_string(),
+                })?;
+                for victim in &victims {
+                    writer.write(&JournalRecord::Planned {
+                        path: victim.path.to_string_lossy().into_owned(),
+                        size: victim.size,
+                        dest: quarantine_dest(&victim.path)
+                            .map(|dest| dest.to_string_lossy().into_owned()),
+                    })?;
+                }
+                Some(writer)
+            }
+            _ => None,
+        };
+        for victim in victims {
+            let name = victim.path.to_string_lossy().into_owned();
+            let metadata = match fs::symlink_metadata(&victim.path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    if let Some(journal) = &mut journal {
+                        journal.write(&JournalRecord::Skipped { path: name.clone() })?;
+                    }
+                    telemetry::file_skipped(&victim.path, &e.to_string());
+                    report.skipped.push((name, e.to_string()));
+                    continue;
+                }
+            };
+            let outcome = match (hook(&victim.path, &metadata), quarantine_dest(&victim.path)) {
+                (Decision::Delete, Some(dest)) => self
+                    .quarantine_file(&victim.path, &dest)
+                    .map(|_| Some(dest)),
+                (Decision::Delete, None) => self.delete(&victim.path).map(|_| None),
+                (Decision::Skip, _) => {
+                    if let Some(journal) = &mut journal {
+                        journal.write(&JournalRecord::Skipped { path: name.clone() })?;
+                    }
+                    telemetry::file_skipped(&victim.path, "vetoed by hook");
+                    report.skipped.push((name, "vetoed by hook".to_string()));
+                    continue;
+                }
+                (Decision::MoveTo(dest), _) => {
+                    self.relocate(&victim.path, &dest).map(|_| Some(dest))
+                }
+            };
+            let moved_to = match outcome {
+                Ok(moved_to) => moved_to.map(|dest| dest.to_string_lossy().into_owned()),
+                Err(e) => {
+                    telemetry::file_failed(&victim.path, &e.to_string());
+                    report.errors.push((name, e.to_string()));
+                    continue;
+                }
+            };
+            if let Some(journal) = &mut journal {
+                journal.write(&JournalRecord::Done {
+                    path: name.clone(),
+                    dest: moved_to.clone(),
+                })?;
+            }
+            match moved_to {
+                Some(dest) => {
+                    telemetry::file_moved(&victim.path, Path::new(&dest), victim.size);
+                    report.redirected.push((name, dest));
+                    report.bytes_moved += victim.size;
+                }
+                None => {
+                    telemetry::file_removed(&victim.path, victim.size);
+                    report.removed.push(name);
+                    report.bytes_freed += victim.size;
+                }
+            }
+            if self.recursive && !self.dry_run {
+                remove_emptied_parents(Path::new(dir), &victim.path);
+            }
+        }
+        if self.prune_empty_dirs && !self.dry_run {
+            match remove_empty_dirs(dir) {
+                Ok(removed_dirs) => report.removed_dirs = removed_dirs,
+                Err(e) => report.errors.push((dir.to_string(), e.to_string())),
+            }
+        }
+        report.stats.duration = started.elapsed();
+        telemetry::finished(&report.stats);
+        Ok(report)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        if self.dry_run {
+            Ok(())
+        } else if let Some(passes) = self.shred {
+            shred_file(&path.to_string_lossy(), passes)
+        } else if self.trash {
+            move_to_trash(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    fn relocate(&self, path: &Path, dest: &Path) -> io::Result<()> {
+        if self.dry_run {
+            Ok(())
+        } else {
+            match fs::rename(path, dest) {
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                    move_across_devices(&path.to_string_lossy(), &dest.to_string_lossy())
+                }
+                result => result,
+            }
+        }
+    }
+
+    fn quarantine_file(&self, path: &Path, dest: &Path) -> io::Result<()> {
+        if self.dry_run {
+            Ok(())
+        } else {
+            move_creating_parents(path, dest)
+        }
+    }
+}
+
+// The name of a quarantine batch directory: the UTC time of the run that filled it, down
+// to the nanosecond. Batches written before nanoseconds were added lack the fraction.
+const QUARANTINE_BATCH_FORMAT: &str = "%Y%m%dT%H%M%S%.9fZ";
+const LEGACY_QUARANTINE_BATCH_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Creates a new, empty batch directory in `quarantine`. Creating it with `create_dir`
+// makes sure no two runs ever share a batch, even ones cleaning different directories.
+fn new_quarantine_batch(quarantine: &Path, dry_run: bool) -> io::Result<PathBuf> {
+    loop {
+        let name = chrono::Utc::now().format(QUARANTINE_BATCH_FORMAT);
+        let batch = quarantine.join(name.to_string());
+        if dry_run {
+            return Ok(batch);
+        }
+        fs::create_dir_all(quarantine)?;
+        match fs::create_dir(&batch) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|_| batch),
+        }
+    }
+}
+
+/// Permanently removes the batches in a [`CleanupConfig::quarantine`] directory that are
+/// older than `grace`.
+///
+/// Only directories named like batches are looked at; anything else in the quarantine
+/// directory is left alone.
+///
+/// # Arguments
+///
+/// * `quarantine` - A string slice that holds the name of the quarantine directory.
+/// * `grace` - How long quarantined files are kept before they are purged.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the batch directories that were removed, oldest first.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// // Keep evicted files recoverable for a week.
+/// let purged = bbq::purge_quarantine("/var/quarantine/app", Duration::from_secs(7 * 86_400)).unwrap();
+/// ```
+pub fn purge_quarantine(quarantine: &str, grace: Duration) -> io::Result<Vec<String>> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut batches = Vec::new();
+    for entry in fs::read_dir(quarantine)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let parsed = [QUARANTINE_BATCH_FORMAT, LEGACY_QUARANTINE_BATCH_FORMAT]
+            .iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(&name, format).ok());
+        let Some(time) = parsed else {
+            continue;
+        };
+        let expired = (now - time).to_std().is_ok_and(|age| age >= grace);
+        if expired && entry.file_type()?.is_dir() {
+            batches.push((time, entry.path()));
+        }
+    }
+    batches.sort();
+    let mut purged = Vec::new();
+    for (_, batch) in batches {
+        fs::remove_dir_all(&batch)?;
+        purged.push(batch.to_string_lossy().into_owned());
+    }
+    Ok(purged)
+}
+
+/// The difference between what two [`CleanupConfig`]s would remove from the same snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupPlanDiff {
+    /// Files only the first config would remove.
+    pub only_a: Vec<RetentionVictim>,
+    /// Files only the second config would remove.
+    pub only_b: Vec<RetentionVictim>,
+    /// Files both configs would remove, with the reasons given by the second config.
+    pub both: Vec<RetentionVictim>,
+    /// The bytes the first config would remove in total.
+    pub bytes_a: u64,
+    /// The bytes the second config would remove in total.
+    pub bytes_b: u64,
+}
+
+impl CleanupPlanDiff {
+    /// Returns true if both configs would remove exactly the same files.
+    pub fn is_empty(&self) -> bool {
+        self.only_a.is_empty() && self.only_b.is_empty()
+    }
+}
+
+/// Compares what two cleanup configs would remove from `dir`, without removing anything.
+///
+/// The directory is scanned once and both configs are planned against that single
+/// snapshot, so the diff reflects the configs alone and not files changing in between.
+/// This is meant for reviewing a config change before rolling it out.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `a` - The current config.
+/// * `b` - The proposed config.
+///
+/// # Returns
+///
+/// * `std::io::Result<CleanupPlanDiff>` - A Result containing the victims each config adds over the other, oldest first.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{CleanupConfig, RetentionPolicy};
+///
+/// let current = CleanupConfig::new(RetentionPolicy::new().max_files(100));
+/// let proposed = CleanupConfig::new(RetentionPolicy::new().max_files(50));
+/// let diff = bbq::diff_cleanup_plans("/var/log/app", &current, &proposed).unwrap();
+/// println!("{} more files, {} more bytes", diff.only_b.len(), diff.bytes_b - diff.bytes_a);
+/// ```
+pub fn diff_cleanup_plans(
+    dir: &str,
+    a: &CleanupConfig,
+    b: &CleanupConfig,
+) -> io::Result<CleanupPlanDiff> {
+    let snapshot = Manifest::scan_lenient(dir)?.0;
+    let plan_a = a.plan_snapshot(&snapshot)?;
+    let plan_b = b.plan_snapshot(&snapshot)?;
+    let paths_a: HashSet<&PathBuf> = plan_a.iter().map(|v| &v.path).collect();
+    let paths_b: HashSet<&PathBuf> = plan_b.iter().map(|v| &v.path).collect();
+
+    let mut diff = CleanupPlanDiff {
+        bytes_a: plan_a.iter().map(|v| v.size).sum(),
+        bytes_b: plan_b.iter().map(|v| v.size).sum(),
+        ..Default::default()
+    };
+    diff.only_a = plan_a
+        .iter()
+        .filter(|v| !paths_b.contains(&v.path))
+        .cloned()
+        .collect();
+    for victim in &plan_b {
+        if paths_a.contains(&victim.path) {
+            diff.both.push(victim.clone());
+        } else {
+            diff.only_b.push(victim.clone());
+        }
+    }
+    Ok(diff)
+}
+
+// Removes the now-empty directories between a deleted `file` and `root`, stopping at the
+// first one that still has content. `root` itself is never removed.
+pub(crate) fn remove_emptied_parents(root: &Path, file: &Path) {
+    let mut dir = file.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// The leftovers [`gc_temp_files`] is usually given: temporary and partial files, core
+/// dumps (`core` and `core.<pid>`), and vim and emacs swap, backup and lock files.
+pub const DEFAULT_TEMP_PATTERNS: &[&str] = &[
+    "*.tmp",
+    "*.temp",
+    "*.part",
+    "core",
+    "core.[0-9]*",
+    "*.swp",
+    "*.swo",
+    "*~",
+    ".#*",
+    "#*#",
+];
+
+/// Removes the orphaned temporary files under `dir`: those matching any of `patterns`
+/// and last modified more than `older_than` ago.
+///
+/// Patterns are globs matched like [`RetentionPolicy::include`]: a pattern without a `/`
+/// matches the file name at any depth. Only files are removed; symlinks and directories
+/// are left alone. A file that can't be removed is recorded in the report's `errors` and
+/// the rest are still collected.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `patterns` - The globs of the files to collect, such as [`DEFAULT_TEMP_PATTERNS`].
+/// * `older_than` - How long a file must have been left untouched. Keep it longer than any writer could take, so files still being written survive.
+///
+/// # Returns
+///
+/// * `std::io::Result<CleanupReport>` - A Result containing the files removed and the bytes freed. An invalid pattern returns `ErrorKind::InvalidInput`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let report = bbq::gc_temp_files("/srv/app", bbq::DEFAULT_TEMP_PATTERNS, Duration::from_secs(86_400)).unwrap();
+/// println!("freed {} bytes in {} files", report.bytes_freed, report.removed.len());
+/// ```
+pub fn gc_temp_files(
+    dir: &str,
+    patterns: &[&str],
+    older_than: Duration,
+) -> io::Result<CleanupReport> {
+    let started = Instant::now();
+    let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+    let patterns = compile_globs(&patterns)?;
+    ensure_writable(dir)?;
+    let _span = telemetry::enter("gc_temp_files", Path::new(dir));
+    let cutoff = SystemTime::now()
+        .checked_sub(older_than)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let root = Path::new(dir);
+    let mut report = CleanupReport::default();
+    let options = WalkOptions {
+        symlinks: SymlinkPolicy::Skip,
+        ..Default::default()
+    };
+    walk_with::<(), _>(dir, &options, |info| {
+        report.stats.files_visited += 1;
+        let path = Path::new(&info.file_path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let stale = info.modified_time.is_some_and(|time| time < cutoff);
+        if info.file_type == "File"
+            && stale
+            && patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, relative))
+        {
+            match fs::remove_file(path) {
+                Ok(()) => {
+                    telemetry::file_removed(path, info.size);
+                    report.bytes_freed += info.size;
+                    report.removed.push(info.file_path.clone());
+                }
+                Err(e) => {
+                    telemetry::file_failed(path, &e.to_string());
+                    report.errors.push((info.file_path.clone(), e.to_string()));
+                }
+            }
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    report.stats.duration = started.elapsed();
+    telemetry::finished(&report.stats);
+    Ok(report)
+}
+
+// Removes `dir` and its subdirectories, bottom-up, if they contain no files.
+fn remove_empty_subdirs(dir: &Path, removed: &mut Vec<String>) -> io::Result<bool> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            empty &= remove_empty_subdirs(&entry.path(), removed)?;
+        } else {
+            empty = false;
+        }
+    }
+    if empty {
+        fs::remove_dir(dir)?;
+        removed.push(dir.to_string_lossy().into_owned());
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_aged(path: &Path, size: usize, age_secs: u64) {
+        fs::write(path, vec![b'x'; size]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::create_dir_all(dir.path().join("c")).unwrap();
+        fs::write(dir.path().join("a/b/zero"), "").unwrap();
+        fs::write(dir.path().join("c/data"), "x").unwrap();
+        fs::write(dir.path().join("c/zero"), "").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = |rel: &str| dir.path().join(rel).to_string_lossy().into_owned();
+
+        let empties = find_empty(root, false).unwrap();
+        let mut files = empties.files.clone();
+        files.sort();
+        assert_eq!(files, [path("a/b/zero"), path("c/zero")]);
+        assert_eq!(empties.dirs, [path("a/b"), path("a")]);
+        assert!(dir.path().join("a/b/zero").exists());
+
+        assert_eq!(find_empty(root, true).unwrap(), empties);
+        assert!(!dir.path().join("a").exists());
+        assert!(!dir.path().join("c/zero").exists());
+        assert!(dir.path().join("c/data").exists());
+    }
+
+    #[test]
+    fn test_archive_old_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        write_aged(&data.join("old.log"), 100, 300);
+        write_aged(&data.join("mid.log"), 100, 200);
+        write_aged(&data.join("new.log"), 100, 100);
+        let dest = dir.path().join("old.tar.gz");
+
+        let (archive, removed) =
+            archive_old_files(data.to_str().unwrap(), 150, dest.to_str().unwrap()).unwrap();
+        assert_eq!(archive.as_deref(), dest.to_str());
+        assert_eq!(removed.len(), 2);
+        assert!(data.join("new.log").exists());
+        assert!(!data.join("old.log").exists());
+
+        let file = fs::File::open(&dest).unwrap();
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut names: Vec<String> = tar
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["mid.log", "old.log"]);
+    }
+
+    #[test]
+    fn test_archive_old_files_keeps_existing_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        write_aged(&data.join("first.log"), 100, 300);
+        write_aged(&data.join("new.log"), 100, 100);
+        let dest = dir.path().join("old.tar.gz");
+        let (data, dest) = (data.to_str().unwrap(), dest.to_str().unwrap());
+        archive_old_files(data, 150, dest).unwrap();
+        let archived = fs::read(dest).unwrap();
+
+        write_aged(&Path::new(data).join("second.log"), 100, 200);
+        let err = archive_old_files(data, 150, dest).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(dest).unwrap(), archived);
+        assert!(Path::new(data).join("second.log").exists());
+
+        let (_, removed) = archive_old_files_with(data, 150, dest, true).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!Path::new(&format!("{}.part", dest)).exists());
+    }
+
+    #[test]
+    fn test_remove_files_older_than() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        write_aged(&dir.path().join("nested/old.log"), 10, 3600);
+        write_aged(&dir.path().join("new.log"), 10, 0);
+
+        let removed =
+            remove_files_older_than(dir.path().to_str().unwrap(), Duration::from_secs(60)).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].ends_with("old.log"));
+        assert!(dir.path().join("new.log").exists());
+    }
+
+    #[test]
+    fn test_gc_temp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        write_aged(&dir.path().join("upload.part"), 10, 7200);
+        write_aged(&dir.path().join("src/.main.rs.swp"), 5, 7200);
+        write_aged(&dir.path().join("core.4242"), 100, 7200);
+        write_aged(&dir.path().join("core.js"), 1, 7200);
+        write_aged(&dir.path().join("fresh.tmp"), 1, 0);
+
+        let report = gc_temp_files(
+            dir.path().to_str().unwrap(),
+            DEFAULT_TEMP_PATTERNS,
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(report.bytes_freed, 115);
+        assert!(dir.path().join("core.js").exists());
+        assert!(dir.path().join("fresh.tmp").exists());
+    }
+
+    #[test]
+    fn test_keep_latest_n() {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, name) in ["a.dump", "b.dump", "c.dump", "d.dump"].iter().enumerate() {
+            write_aged(&dir.path().join(name), 1, 400 - i as u64 * 100);
+        }
+        let removed = keep_latest_n(dir.path().to_str().unwrap(), 2).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(removed[0].ends_with("a.dump"));
+        assert!(removed[1].ends_with("b.dump"));
+        assert!(dir.path().join("c.dump").exists());
+        assert!(dir.path().join("d.dump").exists());
+    }
+
+    #[test]
+    fn test_purge_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        write_aged(&dir.path().join("a/b/ancient"), 10, 7200);
+        write_aged(&dir.path().join("older"), 100, 200);
+        write_aged(&dir.path().join("newer"), 100, 100);
+
+        let (size, removed) = purge_dir(
+            dir.path().to_str().unwrap(),
+            Some(Duration::from_secs(3600)),
+            Some(150),
+        )
+        .unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(size, 100);
+        assert!(dir.path().join("newer").exists());
+        assert!(!dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn test_diff_cleanup_plans() {
+        let dir = tempfile::tempdir().unwrap();
+        write_aged(&dir.path().join("a.log"), 10, 400);
+        write_aged(&dir.path().join("b.log"), 20, 300);
+        write_aged(&dir.path().join("c.log"), 30, 200);
+        write_aged(&dir.path().join("d.log"), 40, 100);
+
+        let current = CleanupConfig::new(RetentionPolicy::new().max_files(3));
+        let proposed = CleanupConfig::new(RetentionPolicy::new().max_files(2));
+        let diff = diff_cleanup_plans(dir.path().to_str().unwrap(), &current, &proposed).unwrap();
+        assert!(diff.only_a.is_empty());
+        assert_eq!(diff.both.len(), 1);
+        assert!(diff.both[0].path.ends_with("a.log"));
+        assert_eq!(diff.only_b.len(), 1);
+        assert!(diff.only_b[0].path.ends_with("b.log"));
+        assert_eq!((diff.bytes_a, diff.bytes_b), (10, 30));
+        assert!(dir.path().join("a.log").exists());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        write_aged(&dir.path().join("old.log"), 100, 300);
+        write_aged(&dir.path().join("new.log"), 100, 100);
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_size(150)).dry_run(true);
+        let report = config.run(dir.path().to_str().unwrap()).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.bytes_freed, 100);
+        assert_eq!(report.stats.files_visited, 2);
+        assert!(report.removed[0].ends_with("old.log"));
+        assert!(dir.path().join("old.log").exists());
+
+        let report = config
+            .dry_run(false)
+            .run(dir.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(!dir.path().join("old.log").exists());
+    }
+
+    #[test]
+    fn test_recursive_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("app/2024")).unwrap();
+        write_aged(&dir.path().join("app/2024/old.log"), 100, 300);
+        write_aged(&dir.path().join("top.log"), 100, 200);
+        write_aged(&dir.path().join("new.log"), 100, 100);
+        let policy = RetentionPolicy::new().max_size(150);
+
+        let top_level = CleanupConfig::new(policy.clone()).recursive(false);
+        let plan = top_level.plan(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(plan.len(), 2);
+        assert!(plan[0].path.ends_with("top.log"));
+
+        let report = CleanupConfig::new(policy)
+            .run(dir.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(report.removed.len(), 2);
+        assert!(!dir.path().join("app").exists());
+        assert!(dir.path().join("new.log").exists());
+    }
+
+    #[test]
+    fn test_protected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("cache")).unwrap();
+        write_aged(&dir.path().join("cache/.keep"), 0, 900);
+        write_aged(&dir.path().join("README"), 10, 800);
+        write_aged(&dir.path().join("cache/entry"), 10, 700);
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0))
+            .protect("README")
+            .protect(".keep");
+        let report = config.run(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert!(dir.path().join("README").exists());
+        assert!(dir.path().join("cache/.keep").exists());
+    }
+
+    #[cfg(not(feature = "trash"))]
+    #[test]
+    fn test_trash_requires_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        write_aged(&dir.path().join("old.log"), 10, 100);
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0)).trash(true);
+        let err = config.run(dir.path().to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(dir.path().join("old.log").exists());
+    }
+
+    #[test]
+    fn test_remove_empty_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+        fs::create_dir_all(dir.path().join("kept/empty")).unwrap();
+        fs::write(dir.path().join("kept/file"), "x").unwrap();
+
+        let removed = remove_empty_dirs(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(removed.len(), 4);
+        let position = |name: &str| removed.iter().position(|p| p.ends_with(name)).unwrap();
+        assert!(position("a/b/c") < position("a/b"));
+        assert!(!dir.path().join("a").exists());
+        assert!(dir.path().join("kept/file").exists());
+        assert!(!dir.path().join("kept/empty").exists());
+    }
+
+    #[test]
+    fn test_run_with_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        write_aged(&data.join("busy.log"), 10, 300);
+        write_aged(&data.join("keep-a-copy.log"), 10, 200);
+        write_aged(&data.join("plain.log"), 10, 100);
+        let saved = dir.path().join("saved.log");
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0));
+        let report = config
+            .run_with_hook(data.to_str().unwrap(), |path, _| {
+                match path.file_name().unwrap().to_str().unwrap() {
+                    "busy.log" => Decision::Skip,
+                    "keep-a-copy.log" => Decision::MoveTo(saved.clone()),
+                    _ => Decision::Delete,
+                }
+            })
+            .unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.redirected.len(), 1);
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!((report.bytes_freed, report.bytes_moved), (10, 10));
+        assert!(data.join("busy.log").exists());
+        assert!(saved.exists());
+        assert!(!data.join("plain.log").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_report_records_errors() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        write_aged(&locked.join("stuck.log"), 10, 300);
+        write_aged(&dir.path().join("free.log"), 10, 200);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+        // Root ignores directory permissions, so there is no error to observe.
+        if fs::File::create(locked.join("probe")).is_ok() {
+            return;
+        }
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0));
+        let report = config.run(dir.path().to_str().unwrap()).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].0.ends_with("stuck.log"));
+        assert_eq!(report.removed.len(), 1);
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("stuck.log"));
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("data");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.log"), "a").unwrap();
+        let quarantine = dir.path().join("quarantine");
+        let quarantine = quarantine.to_str().unwrap();
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0)).quarantine(quarantine);
+        let report = config.run(root.to_str().unwrap()).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!((report.bytes_freed, report.bytes_moved), (0, 1));
+        let moved = Path::new(&report.redirected[0].1);
+        assert!(moved.ends_with("sub/a.log"));
+        assert_eq!(fs::read_to_string(moved).unwrap(), "a");
+        fs::create_dir_all(Path::new(quarantine).join("not-a-batch")).unwrap();
+        fs::create_dir_all(Path::new(quarantine).join("20200101T000000Z")).unwrap();
+
+        // A second run straight away gets a batch of its own.
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.log"), "b").unwrap();
+        let second = config.run(root.to_str().unwrap()).unwrap();
+        assert_ne!(second.redirected[0].1, report.redirected[0].1);
+        assert_eq!(fs::read_to_string(moved).unwrap(), "a");
+
+        let day = Duration::from_secs(86_400);
+        assert_eq!(purge_quarantine(quarantine, day).unwrap().len(), 1);
+        assert_eq!(
+            purge_quarantine(quarantine, Duration::ZERO).unwrap().len(),
+            2
+        );
+        assert!(!moved.exists());
+        assert!(Path::new(quarantine).join("not-a-batch").exists());
+    }
+}
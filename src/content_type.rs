@@ -0,0 +1,71 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+// The most bytes read from a file to recognise its format.
+const SNIFF_LEN: u64 = 8192;
+
+/// Detects the content type of the file at `path`, such as `image/png` or `text/plain`.
+///
+/// The first bytes of the file are checked against known signatures first, so a PNG
+/// saved as `upload.bin` is still `image/png`. Files without a recognisable signature,
+/// such as text formats, fall back to the type registered for their extension.
+///
+/// # Arguments
+///
+/// * `path` - The file to inspect.
+///
+/// # Returns
+///
+/// * `Option<String>` - The MIME type, or `None` if neither the content nor the extension is recognised, or the file can't be read.
+///
+/// # Example
+///
+/// ```no_run
+/// let kind = bbq::detect_content_type(std::path::Path::new("/srv/uploads/photo"));
+/// assert_eq!(kind.as_deref(), Some("image/jpeg"));
+/// ```
+pub fn detect_content_type(path: &Path) -> Option<String> {
+    sniff(path)
+        .ok()
+        .flatten()
+        .or_else(|| guess_from_extension(path))
+}
+
+/// Returns the content type registered for the extension of `path`, without reading it.
+pub fn guess_from_extension(path: &Path) -> Option<String> {
+    mime_guess::from_path(path)
+        .first_raw()
+        .map(|mime| mime.to_string())
+}
+
+fn sniff(path: &Path) -> io::Result<Option<String>> {
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    fs::File::open(path)?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut head)?;
+    Ok(infer::get(&head).map(|kind| kind.mime_type().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_content_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let disguised = dir.path().join("upload.bin");
+        fs::write(&disguised, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let notes = dir.path().join("notes.txt");
+        fs::write(&notes, "plain text").unwrap();
+        let unknown = dir.path().join("blob");
+        fs::write(&unknown, "???").unwrap();
+
+        assert_eq!(
+            detect_content_type(&disguised).as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(detect_content_type(&notes).as_deref(), Some("text/plain"));
+        assert_eq!(detect_content_type(&unknown), None);
+    }
+}
@@ -0,0 +1,624 @@
+use crate::managed::is_protected_path;
+use crate::part::PART_SUFFIX;
+use crate::progress::CancelToken;
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The chunk size [`copy_file`] reads and writes with.
+pub const DEFAULT_COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How far [`copy_file`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CopyProgress {
+    pub bytes_copied: u64,
+    /// The size of the source when the copy started.
+    pub total_bytes: u64,
+    /// The time since the copy started.
+    pub elapsed: Duration,
+    /// The same totals as the other bulk operations report. Reads interrupted by a
+    /// signal and tried again count as retries.
+    #[serde(default)]
+    pub stats: Stats,
+}
+
+impl CopyProgress {
+    /// The average transfer speed so far, in bytes per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_copied as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// The share of the file copied so far, from 0.0 to 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            (self.bytes_copied as f64 / self.total_bytes as f64).min(1.0)
+        }
+    }
+}
+
+/// Options for [`copy_file_with_options`].
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// How many bytes to read and write at a time; `progress` is called after each chunk.
+    pub chunk_size: usize,
+    /// Stops the copy when cancelled, removing the partial destination.
+    pub cancel: CancelToken,
+    /// Also carries over the timestamps, the ownership and, with the `xattr` feature, the
+    /// extended attributes of the source, as [`copy_metadata`] does. Permission bits are
+    /// always copied.
+    pub preserve: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            chunk_size: DEFAULT_COPY_CHUNK_SIZE,
+            cancel: CancelToken::new(),
+            preserve: false,
+        }
+    }
+}
+
+/// Copies a file, calling `progress` after every chunk written.
+///
+/// Like `std::fs::copy`, `dest` is created or truncated and gets the permission bits of
+/// `src`. Copying a file onto itself is refused with `ErrorKind::InvalidInput`.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the source file.
+/// * `dest` - A string slice that holds the name of the destination file.
+/// * `progress` - Called with the running totals after each chunk of [`DEFAULT_COPY_CHUNK_SIZE`] bytes.
+///
+/// # Returns
+///
+/// * `std::io::Result<CopyProgress>` - A Result containing the totals copied.
+///
+/// # Example
+///
+/// ```no_run
+/// let done = bbq::copy_file("/srv/vm/disk.img", "/mnt/backup/disk.img", |p| {
+///     print!("\r{:.0}% at {:.1} MB/s", p.fraction() * 100.0, p.bytes_per_second() / 1e6);
+/// })
+/// .unwrap();
+/// ```
+pub fn copy_file<F>(src: &str, dest: &str, progress: F) -> io::Result<CopyProgress>
+where
+    F: FnMut(&CopyProgress),
+{
+    copy_file_with_options(src, dest, &CopyOptions::default(), progress)
+}
+
+/// Like [`copy_file`], with a chosen chunk size, cancellation, and metadata preservation.
+///
+/// # Returns
+///
+/// * `std::io::Result<CopyProgress>` - A Result containing the totals copied. Cancelling returns `ErrorKind::Interrupted`.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{copy_file_with_options, CopyOptions};
+///
+/// let options = CopyOptions { chunk_size: 8 * 1024 * 1024, preserve: true, ..Default::default() };
+/// copy_file_with_options("/srv/vm/disk.img", "/mnt/backup/disk.img", &options, |_| {}).unwrap();
+/// ```
+pub fn copy_file_with_options<F>(
+    src: &str,
+    dest: &str,
+    options: &CopyOptions,
+    mut progress: F,
+) -> io::Result<CopyProgress>
+where
+    F: FnMut(&CopyProgress),
+{
+    let started = Instant::now();
+    let mut reader = fs::File::open(src)?;
+    let metadata = reader.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file", src),
+        ));
+    }
+    if same_file(Path::new(src), Path::new(dest)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} and {} are the same file", src, dest),
+        ));
+    }
+    let mut writer = fs::File::create(dest)?;
+    let mut totals = CopyProgress {
+        total_bytes: metadata.len(),
+        stats: Stats {
+            files_visited: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let result = copy_chunks(
+        &mut reader,
+        &mut writer,
+        options,
+        &mut totals,
+        started,
+        &mut progress,
+    );
+    if let Err(e) = result {
+        drop(writer);
+        let _ = fs::remove_file(dest);
+        return Err(e);
+    }
+    if options.preserve {
+        apply_metadata(&reader, &metadata, &writer)?;
+    } else {
+        writer.set_permissions(metadata.permissions())?;
+    }
+    totals.elapsed = started.elapsed();
+    totals.stats.duration = totals.elapsed;
+    Ok(totals)
+}
+
+fn copy_chunks<F>(
+    reader: &mut fs::File,
+    writer: &mut fs::File,
+    options: &CopyOptions,
+    totals: &mut CopyProgress,
+    started: Instant,
+    mut progress: F,
+) -> io::Result<()>
+where
+    F: FnMut(&CopyProgress),
+{
+    let mut buffer = vec![0u8; options.chunk_size.max(1)];
+    loop {
+        options.cancel.check()?;
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return writer.flush(),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                totals.stats.retries += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        totals.bytes_copied += read as u64;
+        totals.stats.bytes_read += read as u64;
+        totals.stats.bytes_written += read as u64;
+        totals.elapsed = started.elapsed();
+        totals.stats.duration = totals.elapsed;
+        progress(totals);
+    }
+}
+
+/// Copies the permissions, timestamps, ownership and extended attributes of `src` onto `dest`.
+///
+/// Permissions and the modification and access times must be copied, or an error is
+/// returned. Ownership is only changed when permitted, which usually takes root, and is
+/// otherwise left as is. Extended attributes are copied with the `xattr` feature, on
+/// platforms and filesystems that support them; ones that can't be set are skipped. Both
+/// may be files or directories. Ownership and extended attributes are unix-only.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the file to copy the metadata from.
+/// * `dest` - A string slice that holds the name of the file to copy the metadata to.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. If the operation was successful, it will contain an empty tuple. If it was not successful, it will contain an error.
+///
+/// # Example
+///
+/// ```no_run
+/// std::fs::copy("/srv/app/run.sh", "/mnt/backup/run.sh").unwrap();
+/// bbq::copy_metadata("/srv/app/run.sh", "/mnt/backup/run.sh").unwrap();
+/// ```
+pub fn copy_metadata(src: &str, dest: &str) -> io::Result<()> {
+    let source = fs::File::open(src)?;
+    let metadata = source.metadata()?;
+    apply_metadata(&source, &metadata, &fs::File::open(dest)?)
+}
+
+// Ownership and xattrs first, since changing the owner can clear setuid bits, and the
+// times last, since the other changes could touch them.
+#[allow(unused_variables)]
+fn apply_metadata(source: &fs::File, metadata: &fs::Metadata, dest: &fs::File) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Err(e) = std::os::unix::fs::fchown(dest, Some(metadata.uid()), Some(metadata.gid()))
+        {
+            if e.kind() != io::ErrorKind::PermissionDenied {
+                return Err(e);
+            }
+        }
+    }
+    #[cfg(all(unix, feature = "xattr"))]
+    {
+        use xattr::FileExt;
+        if let Ok(names) = source.list_xattr() {
+            for name in names {
+                if let Ok(Some(value)) = source.get_xattr(&name) {
+                    let _ = dest.set_xattr(&name, &value);
+                }
+            }
+        }
+    }
+    dest.set_permissions(metadata.permissions())?;
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    dest.set_times(times)
+}
+
+/// Options for [`move_dir_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveDirOptions {
+    /// When the destination exists, move the contents of the source into it instead of
+    /// failing with `ErrorKind::AlreadyExists`.
+    #[serde(default)]
+    pub merge: bool,
+    /// When merging, replace files that exist at the destination instead of reporting
+    /// them as failed.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// The outcome of [`move_dir`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveDirReport {
+    /// Whether the whole directory was moved with a single rename. `moved` is then empty.
+    pub renamed: bool,
+    /// The files and symlinks moved one at a time, as paths relative to the source.
+    pub moved: Vec<String>,
+    /// The entries that could not be moved, as paths relative to the source, with the
+    /// error. They are still in the source, along with the directories holding them.
+    pub failed: Vec<(String, String)>,
+    /// The entries moved, and the bytes copied for those that had to be copied to
+    /// another filesystem.
+    #[serde(default)]
+    pub stats: Stats,
+}
+
+impl MoveDirReport {
+    /// Returns true if everything was moved and the source is gone.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Moves a directory and everything in it to `dest`, which must not exist yet.
+///
+/// The directory is renamed when it can be. When `dest` is on another filesystem, the
+/// tree is moved one file at a time instead, like [`move_file`](crate::move_file) does,
+/// keeping the metadata of files and directories, and each source directory is removed
+/// once it is empty. An entry that fails is recorded in the report and the rest are
+/// still moved, so a partly failed move can be finished by running it again with
+/// [`MoveDirOptions::merge`]. A protected directory (see [`is_protected_path`]) is
+/// refused with `ErrorKind::PermissionDenied`.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the directory to move.
+/// * `dest` - A string slice that holds the new name of the directory.
+///
+/// # Returns
+///
+/// * `std::io::Result<MoveDirReport>` - A Result containing what was moved and what failed. If `dest` exists, an `ErrorKind::AlreadyExists` error is returned.
+///
+/// # Example
+///
+/// ```no_run
+/// let report = bbq::move_dir("/srv/data/2023", "/mnt/archive/2023").unwrap();
+/// for (path, error) in &report.failed {
+///     eprintln!("not moved: {}: {}", path, error);
+/// }
+/// ```
+pub fn move_dir(src: &str, dest: &str) -> io::Result<MoveDirReport> {
+    move_dir_with_options(src, dest, &MoveDirOptions::default())
+}
+
+/// Like [`move_dir`], optionally merging into an existing destination.
+///
+/// When merging, the directories of the source are matched with those at `dest` by
+/// name, and the files are moved into them. Files that already exist at the destination
+/// are reported as failed, unless `overwrite` is set.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{move_dir_with_options, MoveDirOptions};
+///
+/// let options = MoveDirOptions { merge: true, overwrite: false };
+/// let report = move_dir_with_options("/srv/incoming", "/srv/library", &options).unwrap();
+/// assert!(report.is_complete(), "{:?}", report.failed);
+/// ```
+pub fn move_dir_with_options(
+    src: &str,
+    dest: &str,
+    options: &MoveDirOptions,
+) -> io::Result<MoveDirReport> {
+    let src_path = Path::new(src);
+    if !fs::symlink_metadata(src_path)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", src),
+        ));
+    }
+    if is_protected_path(src_path) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("refusing to move protected directory {}", src),
+        ));
+    }
+    let started = Instant::now();
+    let dest_path = Path::new(dest);
+    let mut report = MoveDirReport::default();
+    if fs::symlink_metadata(dest_path).is_ok() {
+        if !options.merge {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", dest),
+            ));
+        }
+    } else {
+        match fs::rename(src_path, dest_path) {
+            Ok(()) => {
+                report.renamed = true;
+                report.stats.duration = started.elapsed();
+                return Ok(report);
+            }
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+            Err(e) => return Err(e),
+        }
+    }
+    move_tree(src_path, dest_path, &PathBuf::new(), options, &mut report)?;
+    report.stats.duration = started.elapsed();
+    Ok(report)
+}
+
+// Moves the contents of `src` into `dest` one entry at a time, then removes `src` if
+// everything in it was moved. Only failures to read `src` itself are returned.
+fn move_tree(
+    src: &Path,
+    dest: &Path,
+    relative: &Path,
+    options: &MoveDirOptions,
+    report: &mut MoveDirReport,
+) -> io::Result<()> {
+    let created = match fs::create_dir(dest) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && dest.is_dir() => false,
+        Err(e) => return Err(e),
+    };
+    let failures = report.failed.len();
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), dest.join(entry.file_name()));
+        let rel = relative.join(entry.file_name());
+        let result = match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => move_tree(&from, &to, &rel, options, report),
+            Ok(_) => move_entry(&from, &to, options.overwrite).map(|copied| {
+                report.stats.files_visited += 1;
+                report.stats.bytes_read += copied;
+                report.stats.bytes_written += copied;
+                report.moved.push(rel.to_string_lossy().replace('\\', "/"));
+            }),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            report
+                .failed
+                .push((rel.to_string_lossy().replace('\\', "/"), e.to_string()));
+        }
+    }
+    if report.failed.len() > failures {
+        return Ok(());
+    }
+    if created {
+        copy_metadata(&src.to_string_lossy(), &dest.to_string_lossy())?;
+    }
+    fs::remove_dir(src)
+}
+
+// Returns the bytes copied, which is zero unless the entry had to be copied across
+// filesystems.
+fn move_entry(from: &Path, to: &Path, overwrite: bool) -> io::Result<u64> {
+    if fs::symlink_metadata(to).is_ok() {
+        if !overwrite {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+        if fs::symlink_metadata(to)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} is a directory", to.display()),
+            ));
+        }
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let size = fs::symlink_metadata(from)?.len();
+            move_across_devices(&from.to_string_lossy(), &to.to_string_lossy())?;
+            Ok(size)
+        }
+        result => result.map(|()| 0),
+    }
+}
+
+// Moves the file `src` to `dest` on another filesystem, where it can't be renamed: it is
+// copied with its metadata to a part file next to `dest`, synced, renamed into place, and
+// only then removed from `src`, so a failure never loses it.
+pub(crate) fn move_across_devices(src: &str, dest: &str) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(src)?;
+    if metadata.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a directory", src),
+        ));
+    }
+    #[cfg(unix)]
+    if metadata.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, dest)?;
+        return fs::remove_file(src);
+    }
+    let part = format!("{}{}", dest, PART_SUFFIX);
+    let options = CopyOptions {
+        preserve: true,
+        ..Default::default()
+    };
+    let copied = copy_file_with_options(src, &part, &options, |_| {})
+        .and_then(|_| fs::File::open(&part)?.sync_all())
+        .and_then(|_| fs::rename(&part, dest));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&part);
+        return Err(e);
+    }
+    fs::remove_file(src)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.bin");
+        let dest = dir.path().join("dest.bin");
+        let data: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+        fs::write(&src, &data).unwrap();
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+
+        let options = CopyOptions {
+            chunk_size: 1000,
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        let done =
+            copy_file_with_options(src, dest, &options, |p| seen.push(p.bytes_copied)).unwrap();
+        assert_eq!(seen, [1000, 2000, 2500]);
+        assert_eq!(done.bytes_copied, 2500);
+        assert_eq!(done.fraction(), 1.0);
+        assert_eq!(done.stats.files_visited, 1);
+        assert_eq!(
+            (done.stats.bytes_read, done.stats.bytes_written),
+            (2500, 2500)
+        );
+        assert_eq!(fs::read(dest).unwrap(), data);
+
+        let kind = copy_file(src, src, |_| {}).unwrap_err().kind();
+        assert_eq!(kind, io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read(src).unwrap(), data);
+
+        options.cancel.cancel();
+        let kind = copy_file_with_options(src, dest, &options, |_| {})
+            .unwrap_err()
+            .kind();
+        assert_eq!(kind, io::ErrorKind::Interrupted);
+        assert!(!Path::new(dest).exists());
+    }
+
+    #[test]
+    fn test_move_across_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.bin");
+        let dest = dir.path().join("dest.bin");
+        fs::write(&src, "data").unwrap();
+        let modified = std::time::SystemTime::now() - Duration::from_secs(600);
+        let file = fs::File::options().append(true).open(&src).unwrap();
+        file.set_modified(modified).unwrap();
+
+        move_across_devices(src.to_str().unwrap(), dest.to_str().unwrap()).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "data");
+        assert_eq!(fs::metadata(&dest).unwrap().modified().unwrap(), modified);
+        assert!(!dir.path().join("dest.bin.part").exists());
+    }
+
+    #[test]
+    fn test_move_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/a"), "a").unwrap();
+        let (src_str, dest_str) = (src.to_str().unwrap(), dest.to_str().unwrap());
+
+        assert!(move_dir(src_str, dest_str).unwrap().renamed);
+        assert_eq!(fs::read_to_string(dest.join("sub/a")).unwrap(), "a");
+
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/a"), "new a").unwrap();
+        fs::write(src.join("sub/b"), "b").unwrap();
+        let kind = move_dir(src_str, dest_str).unwrap_err().kind();
+        assert_eq!(kind, io::ErrorKind::AlreadyExists);
+
+        let mut options = MoveDirOptions {
+            merge: true,
+            overwrite: false,
+        };
+        let report = move_dir_with_options(src_str, dest_str, &options).unwrap();
+        assert_eq!(report.moved, ["sub/b"]);
+        assert_eq!(report.stats.files_visited, 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "sub/a");
+        assert!(src.join("sub/a").exists());
+
+        options.overwrite = true;
+        let report = move_dir_with_options(src_str, dest_str, &options).unwrap();
+        assert!(report.is_complete());
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("sub/a")).unwrap(), "new a");
+    }
+
+    #[test]
+    fn test_copy_preserves_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.sh");
+        let dest = dir.path().join("dest.sh");
+        fs::write(&src, "#!/bin/sh").unwrap();
+        let modified = std::time::SystemTime::now() - Duration::from_secs(86_400);
+        let file = fs::File::options().append(true).open(&src).unwrap();
+        file.set_modified(modified).unwrap();
+        let mut permissions = file.metadata().unwrap().permissions();
+        permissions.set_readonly(true);
+        file.set_permissions(permissions).unwrap();
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+
+        let options = CopyOptions {
+            preserve: true,
+            ..Default::default()
+        };
+        copy_file_with_options(src, dest, &options, |_| {}).unwrap();
+        let copied = fs::metadata(dest).unwrap();
+        assert_eq!(copied.modified().unwrap(), modified);
+        assert!(copied.permissions().readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(copied.mode(), fs::metadata(src).unwrap().mode());
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::cleanup::{CleanupConfig, CleanupReport};
+use crate::schedule::Schedule;
+use chrono::Local;
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The most a [`CleanupDaemon`] stretches its interval while its directory is read-only.
+pub const MAX_READ_ONLY_BACKOFF: u32 = 16;
+
+/// Runs a [`CleanupConfig`] against a directory periodically on a background thread.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{CleanupConfig, CleanupDaemon, RetentionPolicy};
+/// use std::time::Duration;
+///
+/// let config = CleanupConfig::new(RetentionPolicy::new().max_size(10 * 1024 * 1024 * 1024));
+/// let handle = CleanupDaemon::new("/var/log/app", config, Duration::from_secs(600)).start();
+/// for report in handle.reports().iter().take(3) {
+///     println!("{:?}", report.map(|r| r.bytes_freed));
+/// }
+/// handle.stop();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CleanupDaemon {
+    dir: String,
+    config: CleanupConfig,
+    interval: Duration,
+    schedule: Option<Schedule>,
+}
+
+impl CleanupDaemon {
+    /// Creates a daemon cleaning `dir` with `config` every `interval`.
+    pub fn new(dir: &str, config: CleanupConfig, interval: Duration) -> Self {
+        CleanupDaemon {
+            dir: dir.to_string(),
+            config,
+            interval,
+            schedule: None,
+        }
+    }
+
+    /// Only runs cleanups while `schedule` is open; a run that falls due outside its
+    /// windows waits for the next one to open.
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Starts the background thread. The first cleanup runs immediately.
+    ///
+    /// While the directory is on a read-only filesystem, each failed run doubles the wait
+    /// before the next one, up to [`MAX_READ_ONLY_BACKOFF`] times the interval, so a volume
+    /// remounted read-only produces a trickle of errors rather than a flood. The first
+    /// successful run restores the normal interval.
+    pub fn start(self) -> CleanupHandle {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let (sender, receiver) = mpsc::channel();
+        let signal = Arc::clone(&stop);
+        let mut backoff = 1;
+        let thread = thread::spawn(move || loop {
+            if let Some(schedule) = &self.schedule {
+                loop {
+                    let wait = schedule.until_open(Local::now().time());
+                    if wait.is_zero() {
+                        break;
+                    }
+                    if sleep_unless_stopped(&signal, wait.min(Duration::from_secs(60))) {
+                        return;
+                    }
+                }
+            }
+            let started = Instant::now();
+            let result = self.config.run(&self.dir);
+            backoff = match &result {
+                Err(e) if e.kind() == io::ErrorKind::ReadOnlyFilesystem => {
+                    (backoff * 2).min(MAX_READ_ONLY_BACKOFF)
+                }
+                _ => 1,
+            };
+            // Nobody listening is fine; the daemon keeps cleaning until it is stopped.
+            let _ = sender.send(result);
+            let wait = if backoff > 1 {
+                self.interval * backoff
+            } else {
+                self.interval.saturating_sub(started.elapsed())
+            };
+            if sleep_unless_stopped(&signal, wait) {
+                return;
+            }
+        });
+        CleanupHandle {
+            stop,
+            thread: Some(thread),
+            reports: receiver,
+        }
+    }
+}
+
+// Sleeps for `duration` or until a stop is requested; returns true when stopping.
+fn sleep_unless_stopped(signal: &(Mutex<bool>, Condvar), duration: Duration) -> bool {
+    let (stopped, condvar) = signal;
+    let guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+    let (guard, _) = condvar
+        .wait_timeout_while(guard, duration, |stopped| !*stopped)
+        .unwrap_or_else(|e| e.into_inner());
+    *guard
+}
+
+/// A running [`CleanupDaemon`]. Dropping the handle stops the daemon.
+#[derive(Debug)]
+pub struct CleanupHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+    reports: Receiver<io::Result<CleanupReport>>,
+}
+
+impl CleanupHandle {
+    /// The outcome of every run, in order.
+    pub fn reports(&self) -> &Receiver<io::Result<CleanupReport>> {
+        &self.reports
+    }
+
+    /// Stops the daemon, waiting for a run in progress to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let (stopped, condvar) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for CleanupHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retention::RetentionPolicy;
+    use std::fs;
+
+    #[test]
+    fn test_cleanup_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.log"), "a").unwrap();
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0));
+        let handle = CleanupDaemon::new(
+            dir.path().to_str().unwrap(),
+            config,
+            Duration::from_millis(20),
+        )
+        .start();
+
+        let first = handle.reports().recv().unwrap().unwrap();
+        assert_eq!(first.removed.len(), 1);
+        fs::write(dir.path().join("b.log"), "b").unwrap();
+        let removed = handle
+            .reports()
+            .iter()
+            .take(5)
+            .map(|r| r.unwrap().removed.len())
+            .find(|&n| n > 0);
+        assert_eq!(removed, Some(1));
+        let started = Instant::now();
+        handle.stop();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::hash::hash_file;
+use crate::manifest::{EntryKind, Manifest, ManifestEntry};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// How [`diff_dirs_with`] decides that a file present on both sides was modified.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CompareBy {
+    /// Different sizes or modification times. Fast, but a copy that didn't keep
+    /// modification times shows every file as modified.
+    #[default]
+    Metadata,
+    /// Different sizes or SHA-256 digests. Reads every file of the same size on both sides.
+    Content,
+}
+
+/// The differences between two directory trees, as paths relative to their roots with `/`
+/// separators, each list sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirDiff {
+    /// Files and symlinks only in the second tree.
+    pub added: Vec<String>,
+    /// Files and symlinks only in the first tree.
+    pub removed: Vec<String>,
+    /// Files and symlinks in both trees that differ, including a file replaced by a
+    /// symlink or the other way round.
+    pub modified: Vec<String>,
+}
+
+impl DirDiff {
+    /// Returns true if the trees hold the same files.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compares the files under `a` with those under `b` by size and modification time.
+///
+/// Directories themselves are not compared, only what they contain; symlinks are compared
+/// by target and never followed.
+///
+/// # Arguments
+///
+/// * `a` - The first directory, such as the source of a backup.
+/// * `b` - The second directory, such as the backup.
+///
+/// # Returns
+///
+/// * `std::io::Result<DirDiff>` - A Result containing what `b` has added, removed and modified relative to `a`.
+///
+/// # Example
+///
+/// ```no_run
+/// let diff = bbq::diff_dirs("/srv/data", "/mnt/backup/data").unwrap();
+/// for path in diff.removed.iter().chain(&diff.modified) {
+///     println!("backup is missing or stale: {}", path);
+/// }
+/// ```
+pub fn diff_dirs(a: &str, b: &str) -> io::Result<DirDiff> {
+    diff_dirs_with(a, b, CompareBy::Metadata)
+}
+
+/// Like [`diff_dirs`], deciding what was modified as `compare` says.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::CompareBy;
+///
+/// let diff = bbq::diff_dirs_with("/srv/data", "/mnt/backup/data", CompareBy::Content).unwrap();
+/// assert!(diff.is_empty(), "backup differs: {:?}", diff);
+/// ```
+pub fn diff_dirs_with(a: &str, b: &str, compare: CompareBy) -> io::Result<DirDiff> {
+    let (before, after) = (Manifest::scan(a)?, Manifest::scan(b)?);
+    let mut diff = DirDiff::default();
+    for entry in compared(&before) {
+        match after.get(&entry.path) {
+            None => diff.removed.push(entry.path.clone()),
+            Some(other) if other.kind == EntryKind::Dir => diff.removed.push(entry.path.clone()),
+            Some(other) => {
+                if differs(a, b, entry, other, compare)? {
+                    diff.modified.push(entry.path.clone());
+                }
+            }
+        }
+    }
+    for entry in compared(&after) {
+        let present = before
+            .get(&entry.path)
+            .is_some_and(|other| other.kind != EntryKind::Dir);
+        if !present {
+            diff.added.push(entry.path.clone());
+        }
+    }
+    Ok(diff)
+}
+
+fn compared(manifest: &Manifest) -> impl Iterator<Item = &ManifestEntry> {
+    manifest
+        .entries
+        .iter()
+        .filter(|entry| entry.kind != EntryKind::Dir)
+}
+
+fn differs(
+    a: &str,
+    b: &str,
+    left: &ManifestEntry,
+    right: &ManifestEntry,
+    compare: CompareBy,
+) -> io::Result<bool> {
+    if left.kind != right.kind {
+        return Ok(true);
+    }
+    if left.kind == EntryKind::Symlink {
+        return Ok(left.target != right.target);
+    }
+    if left.size != right.size {
+        return Ok(true);
+    }
+    match compare {
+        CompareBy::Metadata => Ok(left.modified != right.modified),
+        CompareBy::Content => {
+            let hash = |root: &str| {
+                let path = Path::new(root).join(&left.path);
+                hash_file(&path.to_string_lossy())
+            };
+            Ok(hash(a)? != hash(b)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_diff_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        for root in [&a, &b] {
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("same"), "same").unwrap();
+            fs::write(root.join("sub/touched"), "data").unwrap();
+        }
+        fs::write(a.join("gone"), "x").unwrap();
+        fs::write(b.join("sub/new"), "x").unwrap();
+        fs::write(a.join("edited"), "abcd").unwrap();
+        fs::write(b.join("edited"), "abce").unwrap();
+        // Same content, different modification time.
+        let earlier = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .append(true)
+            .open(b.join("sub/touched"))
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        for root in [&a, &b] {
+            let same = fs::File::options()
+                .append(true)
+                .open(root.join("same"))
+                .unwrap();
+            same.set_modified(earlier).unwrap();
+            let edited = fs::File::options()
+                .append(true)
+                .open(root.join("edited"))
+                .unwrap();
+            edited.set_modified(earlier).unwrap();
+        }
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+        let diff = diff_dirs(a, b).unwrap();
+        assert_eq!(diff.added, ["sub/new"]);
+        assert_eq!(diff.removed, ["gone"]);
+        assert_eq!(diff.modified, ["sub/touched"]);
+
+        let diff = diff_dirs_with(a, b, CompareBy::Content).unwrap();
+        assert_eq!(diff.modified, ["edited"]);
+        assert!(diff_dirs(a, a).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// The size and free space of the filesystem a path lives on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSpace {
+    /// The size of the filesystem, in bytes.
+    pub total: u64,
+    /// The bytes available to unprivileged users, which excludes space reserved for root.
+    pub available: u64,
+}
+
+impl DiskSpace {
+    /// The available space as a percentage of the total.
+    pub fn available_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.available as f64 * 100.0 / self.total as f64
+    }
+}
+
+/// A minimum amount of free space to keep on a filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FreeSpace {
+    Bytes(u64),
+    /// A percentage (0-100) of the size of the filesystem.
+    Percent(u8),
+}
+
+impl FreeSpace {
+    /// The number of bytes this target asks for on a filesystem of `total` bytes.
+    pub fn bytes(&self, total: u64) -> u64 {
+        match *self {
+            FreeSpace::Bytes(bytes) => bytes,
+            FreeSpace::Percent(percent) => (total as u128 * percent.min(100) as u128 / 100) as u64,
+        }
+    }
+}
+
+/// Queries the size and free space of the filesystem containing `path`.
+///
+/// # Arguments
+///
+/// * `path` - A string slice that holds the name of a file or directory on the filesystem
+///
+/// # Example
+///
+/// ```no_run
+/// let space = bbq::disk_space("/var/log").unwrap();
+/// println!("{:.1}% free", space.available_percent());
+/// ```
+pub fn disk_space(path: &str) -> io::Result<DiskSpace> {
+    let stats = fs2::statvfs(path)?;
+    Ok(DiskSpace {
+        total: stats.total_space(),
+        available: stats.available_space(),
+    })
+}
+
+/// Returns true if the filesystem containing `path` is mounted read-only.
+///
+/// On platforms without mount flags, this reports whether `path` itself is marked
+/// read-only.
+///
+/// # Example
+///
+/// ```no_run
+/// if bbq::is_read_only("/var/log").unwrap() {
+///     println!("/var/log is read-only; skipping cleanup");
+/// }
+/// ```
+#[cfg(unix)]
+pub fn is_read_only(path: &str) -> io::Result<bool> {
+    use std::ffi::CString;
+
+    let c_path = CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stats` is a properly sized out-parameter.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stats.f_flag & libc::ST_RDONLY != 0)
+}
+
+/// Returns true if the filesystem containing `path` is mounted read-only.
+///
+/// On platforms without mount flags, this reports whether `path` itself is marked
+/// read-only.
+#[cfg(not(unix))]
+pub fn is_read_only(path: &str) -> io::Result<bool> {
+    Ok(std::fs::metadata(path)?.permissions().readonly())
+}
+
+/// Fails with `ErrorKind::ReadOnlyFilesystem` if `dir` is on a read-only filesystem.
+///
+/// Destructive operations call this before touching anything, so a volume remounted
+/// read-only gives one clear error instead of a failure for every file.
+pub(crate) fn ensure_writable(dir: &str) -> io::Result<()> {
+    let read_only = match is_read_only(dir) {
+        Ok(read_only) => read_only,
+        // Missing directories are left to the operation, which may treat them as empty.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(e),
+    };
+    if read_only {
+        return Err(io::Error::new(
+            io::ErrorKind::ReadOnlyFilesystem,
+            format!("{} is on a read-only filesystem", dir),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let space = disk_space(dir.path().to_str().unwrap()).unwrap();
+        assert!(space.total > 0);
+        assert!(space.available <= space.total);
+        assert_eq!(FreeSpace::Percent(50).bytes(1000), 500);
+        assert_eq!(FreeSpace::Bytes(7).bytes(1000), 7);
+        assert!(!is_read_only(dir.path().to_str().unwrap()).unwrap());
+        ensure_writable(dir.path().to_str().unwrap()).unwrap();
+    }
+}
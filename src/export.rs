@@ -0,0 +1,155 @@
+use crate::info::FileInfo;
+use crate::walk::{Walk, WalkOptions};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+/// The formats [`export_dir_info`] can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// One JSON array of [`FileInfo`] objects.
+    Json,
+    /// A header row, then one row per entry, with RFC 3339 timestamps in UTC.
+    Csv,
+    /// One [`FileInfo`] JSON object per line.
+    Ndjson,
+}
+
+const CSV_HEADER: &str = "file_path,file_name,file_type,size,modified_time,created_time,accessed_time,extension,permissions,owner,group";
+
+/// Writes a listing of every entry under `dir` to `writer`, as it is walked.
+///
+/// Entries are streamed, so an inventory of millions of files never sits in memory; wrap
+/// `writer` in a `BufWriter` when it is a file or socket. Unreadable entries are skipped.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to list.
+/// * `format` - How to write the entries.
+/// * `writer` - Where to write them.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of entries written.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::ExportFormat;
+/// use std::io::BufWriter;
+///
+/// let out = BufWriter::new(std::fs::File::create("inventory.csv").unwrap());
+/// let count = bbq::export_dir_info("/srv/data", ExportFormat::Csv, out).unwrap();
+/// ```
+pub fn export_dir_info<W: Write>(dir: &str, format: ExportFormat, writer: W) -> io::Result<u64> {
+    export_dir_info_with(dir, &WalkOptions::default(), format, writer)
+}
+
+/// Like [`export_dir_info`], walking as `options` says, e.g. only one level deep.
+pub fn export_dir_info_with<W: Write>(
+    dir: &str,
+    options: &WalkOptions,
+    format: ExportFormat,
+    mut writer: W,
+) -> io::Result<u64> {
+    fs::read_dir(dir)?;
+    let mut count = 0u64;
+    match format {
+        ExportFormat::Json => writer.write_all(b"[")?,
+        ExportFormat::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+        ExportFormat::Ndjson => {}
+    }
+    for entry in Walk::with_options(dir, options) {
+        let info = match entry {
+            Ok(info) => info,
+            Err(e) if options.stop_on_error => return Err(e),
+            Err(_) => continue,
+        };
+        match format {
+            ExportFormat::Json => {
+                if count > 0 {
+                    writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut writer, &info)?;
+            }
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(&mut writer, &info)?;
+                writer.write_all(b"\n")?;
+            }
+            ExportFormat::Csv => write_csv_row(&mut writer, &info)?,
+        }
+        count += 1;
+    }
+    if format == ExportFormat::Json {
+        writer.write_all(b"]")?;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn write_csv_row<W: Write>(writer: &mut W, info: &FileInfo) -> io::Result<()> {
+    let fields = [
+        csv_field(&info.file_path),
+        csv_field(&info.file_name),
+        csv_field(&info.file_type),
+        info.size.to_string(),
+        rfc3339(info.modified_time),
+        rfc3339(info.created_time),
+        rfc3339(info.accessed_time),
+        csv_field(info.extension.as_deref().unwrap_or_default()),
+        csv_field(&info.permissions),
+        csv_field(info.owner.as_deref().unwrap_or_default()),
+        csv_field(info.group.as_deref().unwrap_or_default()),
+    ];
+    writeln!(writer, "{}", fields.join(","))
+}
+
+// Quotes a field if it contains a separator, quote or line break, doubling the quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn rfc3339(time: Option<SystemTime>) -> String {
+    time.map(|time| DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_dir_info() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/a,b.txt"), "abc").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let mut json = Vec::new();
+        assert_eq!(
+            export_dir_info(root, ExportFormat::Json, &mut json).unwrap(),
+            2
+        );
+        let infos: Vec<FileInfo> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(infos.len(), 2);
+
+        let mut ndjson = Vec::new();
+        export_dir_info(root, ExportFormat::Ndjson, &mut ndjson).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&ndjson).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        serde_json::from_str::<FileInfo>(lines[1]).unwrap();
+
+        let mut csv = Vec::new();
+        export_dir_info(root, ExportFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], CSV_HEADER);
+        assert!(rows[2].contains("\"a,b.txt\",File,3,"));
+    }
+}
@@ -0,0 +1,780 @@
+use crate::archive::ArchiveFormat;
+use crate::info::get_files;
+use crate::telemetry;
+use crate::verify::{verify_checksums, VerifyOutcome};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+/// An on-demand extraction cache for large archives.
+///
+/// Instead of extracting a whole archive to read a few files, [`ExtractCache::get`]
+/// extracts just the requested entry into a managed cache directory and returns its path.
+/// Later requests for the same entry are served from the cache. When the cache grows
+/// beyond `max_size` bytes, the least recently used entries are evicted.
+///
+/// # Example
+///
+/// ```no_run
+/// let cache = bbq::ExtractCache::new("/var/cache/bbq", 1024 * 1024 * 1024).unwrap();
+/// let path = cache.get("/backups/2024-01-01.tar.gz", "etc/hosts").unwrap();
+/// println!("{}", std::fs::read_to_string(path).unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExtractCache {
+    root: PathBuf,
+    max_size: u64,
+}
+
+impl ExtractCache {
+    /// Creates a cache rooted at `root`, creating the directory if needed.
+    pub fn new(root: &str, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(ExtractCache {
+            root: PathBuf::from(root),
+            max_size,
+        })
+    }
+
+    /// The directory holding the cached entries.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the path of `entry` from `archive`, extracting it first if it is not cached.
+    ///
+    /// Entries are cached per archive and invalidated when the archive's size or
+    /// modification time changes.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<PathBuf>` - A Result containing the path of the extracted file. If the entry is not in the archive, an `ErrorKind::NotFound` error is returned.
+    pub fn get(&self, archive: &str, entry: &str) -> io::Result<PathBuf> {
+        let relative = safe_entry_path(Path::new(entry))?;
+        let target = self.archive_dir(archive)?.join(relative);
+        if target.is_file() {
+            fs::File::options()
+                .append(true)
+                .open(&target)?
+                .set_modified(SystemTime::now())?;
+            return Ok(target);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut partial = target.clone().into_os_string();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        let extracted = extract_entry(archive, entry, &partial);
+        match extracted {
+            Ok(true) => fs::rename(&partial, &target)?,
+            Ok(false) => {
+                let _ = fs::remove_file(&partial);
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} not found in {}", entry, archive),
+                ));
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+        }
+        self.evict_except(Some(&target))?;
+        Ok(target)
+    }
+
+    /// Evicts least recently used entries until the cache is within its size limit.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<Vec<String>>` - A Result containing the paths of the evicted files.
+    pub fn evict(&self) -> io::Result<Vec<String>> {
+        self.evict_except(None)
+    }
+
+    /// Removes every cached entry.
+    pub fn clear(&self) -> io::Result<()> {
+        fs::remove_dir_all(&self.root)?;
+        fs::create_dir_all(&self.root)
+    }
+
+    fn archive_dir(&self, archive: &str) -> io::Result<PathBuf> {
+        let metadata = fs::metadata(archive)?;
+        let canonical = fs::canonicalize(archive)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let key = format!(
+            "{}:{}:{}",
+            canonical.to_string_lossy(),
+            metadata.len(),
+            modified
+        );
+        Ok(self.root.join(format!("{:016x}", fnv1a(key.as_bytes()))))
+    }
+
+    fn evict_except(&self, keep: Option<&Path>) -> io::Result<Vec<String>> {
+        let mut files: Vec<(PathBuf, u64, SystemTime)> = get_files(&self.root)?
+            .into_iter()
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let modified = metadata.modified().ok()?;
+                Some((path, metadata.len(), modified))
+            })
+            .collect();
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        files.sort_by_key(|(_, _, modified)| *modified);
+        let mut evicted = Vec::new();
+        for (path, size, _) in files {
+            if total <= self.max_size {
+                break;
+            }
+            if Some(path.as_path()) == keep {
+                continue;
+            }
+            fs::remove_file(&path)?;
+            total -= size;
+            evicted.push(path.to_string_lossy().into_owned());
+        }
+        Ok(evicted)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn safe_entry_path(entry: &Path) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in entry.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsafe archive entry name: {}", entry.display()),
+                ))
+            }
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty archive entry name",
+        ));
+    }
+    Ok(path)
+}
+
+fn same_entry(name: &Path, entry: &str) -> bool {
+    name.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .eq(Path::new(entry)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir)))
+}
+
+// Streams a single entry out of an archive into `dest`. Returns false if the entry is missing.
+fn extract_entry(archive: &str, entry: &str, dest: &Path) -> io::Result<bool> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported archive format: {}", archive),
+        )
+    })?;
+    let file = fs::File::open(archive)?;
+    match format {
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(io::BufReader::new(file)));
+            for item in tar.entries()? {
+                let mut item = item?;
+                if item.header().entry_type().is_file() && same_entry(&item.path()?, entry) {
+                    write_entry(&mut item, dest)?;
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+            let result = zip.by_name(entry);
+            match result {
+                Ok(mut item) if item.is_file() => {
+                    write_entry(&mut item, dest)?;
+                    Ok(true)
+                }
+                Ok(_) | Err(zip::result::ZipError::FileNotFound) => Ok(false),
+                Err(e) => Err(io::Error::other(e)),
+            }
+        }
+    }
+}
+
+fn write_entry<R: Read>(reader: &mut R, dest: &Path) -> io::Result<()> {
+    let mut output = io::BufWriter::new(fs::File::create(dest)?);
+    io::copy(reader, &mut output)?;
+    output.flush()
+}
+
+/// How [`extract_archive`] treats entries that would land outside the sandbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnsafeEntryMode {
+    /// Abort the extraction with an `ErrorKind::InvalidData` error.
+    #[default]
+    Strict,
+    /// Skip the entry and list it in [`ExtractReport::skipped`].
+    Lenient,
+}
+
+/// Options for [`extract_archive`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Directory nothing may be written outside of. Defaults to the extraction destination.
+    pub sandbox_root: Option<PathBuf>,
+    /// What to do with entries that try to escape the sandbox.
+    pub mode: UnsafeEntryMode,
+}
+
+/// The outcome of [`extract_archive`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractReport {
+    /// Paths of the files, directories and links that were written.
+    pub extracted: Vec<String>,
+    /// Names of the entries that were skipped, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Extracts a whole tar.gz or zip archive into `dest`, guarding against path traversal.
+///
+/// Entries with absolute paths or `..` components, symlinks pointing outside the
+/// sandbox, and entries that would be written through such a symlink are rejected
+/// ("zip-slip"). In [`UnsafeEntryMode::Strict`] the first offending entry aborts the
+/// extraction; in [`UnsafeEntryMode::Lenient`] it is skipped and reported. Hard links
+/// are recreated when they point at a file extracted earlier; entry types that can't be
+/// extracted, such as devices, and links to files that weren't are skipped and reported
+/// in either mode.
+///
+/// # Arguments
+///
+/// * `archive` - A string slice that holds the path of the archive. Its format is detected from the extension.
+/// * `dest` - A string slice that holds the directory to extract into. It is created if needed.
+/// * `options` - The sandbox and strictness options.
+///
+/// # Returns
+///
+/// * `std::io::Result<ExtractReport>` - A Result containing what was extracted and skipped, or an error.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{ExtractOptions, UnsafeEntryMode};
+///
+/// let options = ExtractOptions { mode: UnsafeEntryMode::Lenient, ..Default::default() };
+/// let report = bbq::extract_archive("/uploads/bundle.zip", "/srv/bundle", &options).unwrap();
+/// for (name, reason) in report.skipped {
+///     eprintln!("skipped {}: {}", name, reason);
+/// }
+/// ```
+pub fn extract_archive(
+    archive: &str,
+    dest: &str,
+    options: &ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported archive format: {}", archive),
+        )
+    })?;
+    fs::create_dir_all(dest)?;
+    let dest = fs::canonicalize(dest)?;
+    let sandbox = match &options.sandbox_root {
+        Some(root) => fs::canonicalize(root)?,
+        None => dest.clone(),
+    };
+    let mut extractor = Extractor {
+        dest,
+        sandbox,
+        mode: options.mode,
+        report: ExtractReport::default(),
+    };
+    let file = fs::File::open(archive)?;
+    match format {
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(io::BufReader::new(file)));
+            for item in tar.entries()? {
+                let mut item = item?;
+                let name = item.path()?.into_owned();
+                let entry_type = item.header().entry_type();
+                let kind = if entry_type.is_dir() {
+                    EntryKind::Dir
+                } else if entry_type.is_symlink() {
+                    EntryKind::Symlink(
+                        item.link_name()?
+                            .map(|l| l.into_owned())
+                            .unwrap_or_default(),
+                    )
+                } else if entry_type.is_hard_link() {
+                    EntryKind::HardLink(
+                        item.link_name()?
+                            .map(|l| l.into_owned())
+                            .unwrap_or_default(),
+                    )
+                } else if entry_type.is_file() {
+                    EntryKind::File
+                } else {
+                    extractor.pass_over(&name, "unsupported entry type");
+                    continue;
+                };
+                extractor.extract(&name, kind, &mut item)?;
+            }
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+            for i in 0..zip.len() {
+                let mut item = zip.by_index(i).map_err(io::Error::other)?;
+                let name = PathBuf::from(item.name());
+                let kind = if item.is_dir() {
+                    EntryKind::Dir
+                } else if item.is_symlink() {
+                    let mut target = String::new();
+                    item.read_to_string(&mut target)?;
+                    EntryKind::Symlink(PathBuf::from(target))
+                } else {
+                    EntryKind::File
+                };
+                extractor.extract(&name, kind, &mut item)?;
+            }
+        }
+    }
+    Ok(extractor.report)
+}
+
+/// Restores an archive into `dest` without ever exposing a half-written directory.
+///
+/// The archive is extracted into a hidden sibling of `dest`, every file listed in
+/// `checksums` is verified against its SHA-256, and only then is the new tree swapped
+/// into place. If extraction or verification fails, the temporary directory is removed
+/// and `dest` is left untouched. The swap is two renames on the same filesystem; if the
+/// second one fails, the previous contents are moved back.
+///
+/// # Arguments
+///
+/// * `archive` - A string slice that holds the path of the archive.
+/// * `dest` - A string slice that holds the directory to restore. It doesn't need to exist.
+/// * `checksums` - Expected hex SHA-256 digests, keyed by path relative to the extraction root.
+/// * `options` - The sandbox and strictness options for the extraction.
+///
+/// # Returns
+///
+/// * `std::io::Result<ExtractReport>` - A Result containing what was restored, with paths as extracted into the temporary directory. A missing or mismatching file returns an `ErrorKind::InvalidData` error.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::collections::BTreeMap;
+///
+/// let mut checksums = BTreeMap::new();
+/// checksums.insert("data/db.sqlite".to_string(), "9f86d0...".to_string());
+/// bbq::restore_archive_verified("/backups/data.tar.gz", "/srv/app", &checksums, &Default::default()).unwrap();
+/// ```
+pub fn restore_archive_verified(
+    archive: &str,
+    dest: &str,
+    checksums: &BTreeMap<String, String>,
+    options: &ExtractOptions,
+) -> io::Result<ExtractReport> {
+    let dest = Path::new(dest);
+    let staging = restore_sibling(dest, "restore-tmp")?;
+    let previous = restore_sibling(dest, "restore-old")?;
+    for stale in [&staging, &previous] {
+        if stale.exists() {
+            fs::remove_dir_all(stale)?;
+        }
+    }
+
+    let staged = extract_archive(archive, &staging.to_string_lossy(), options).and_then(|report| {
+        verify_staged(&staging, checksums)?;
+        Ok(report)
+    });
+    let report = match staged {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let had_previous = fs::symlink_metadata(dest).is_ok();
+    if had_previous {
+        fs::rename(dest, &previous)?;
+    }
+    if let Err(e) = fs::rename(&staging, dest) {
+        if had_previous {
+            fs::rename(&previous, dest)?;
+        }
+        return Err(e);
+    }
+    // The restore is in place; a leftover copy of the old tree is cleared by the next run.
+    if had_previous {
+        if let Err(e) = fs::remove_dir_all(&previous) {
+            telemetry::file_failed(&previous, &e.to_string());
+        }
+    }
+    Ok(report)
+}
+
+fn restore_sibling(dest: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let name = dest.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot restore into {}", dest.display()),
+        )
+    })?;
+    let mut sibling = std::ffi::OsString::from(".");
+    sibling.push(name);
+    sibling.push(".");
+    sibling.push(suffix);
+    Ok(dest.with_file_name(sibling))
+}
+
+fn verify_staged(root: &Path, checksums: &BTreeMap<String, String>) -> io::Result<()> {
+    for name in checksums.keys() {
+        safe_entry_path(Path::new(name))?;
+    }
+    let concurrency = thread::available_parallelism().map_or(1, |n| n.get());
+    for check in verify_checksums(&root.to_string_lossy(), checksums, concurrency) {
+        match check.outcome {
+            VerifyOutcome::Match => {}
+            VerifyOutcome::Mismatch { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("checksum mismatch for {}", check.path),
+                ))
+            }
+            VerifyOutcome::Error(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("cannot verify {}: {}", check.path, e),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+enum EntryKind {
+    File,
+    Dir,
+    Symlink(PathBuf),
+    // The name of an earlier entry in the archive.
+    HardLink(PathBuf),
+}
+
+struct Extractor {
+    dest: PathBuf,
+    sandbox: PathBuf,
+    mode: UnsafeEntryMode,
+    report: ExtractReport,
+}
+
+impl Extractor {
+    fn skip(&mut self, name: &Path, reason: &str) -> io::Result<()> {
+        match self.mode {
+            UnsafeEntryMode::Strict => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing to extract {}: {}", name.display(), reason),
+            )),
+            UnsafeEntryMode::Lenient => {
+                self.pass_over(name, reason);
+                Ok(())
+            }
+        }
+    }
+
+    // Records an entry left out for a reason that isn't a threat, whatever the mode.
+    fn pass_over(&mut self, name: &Path, reason: &str) {
+        self.report
+            .skipped
+            .push((name.to_string_lossy().into_owned(), reason.to_string()));
+    }
+
+    fn extract<R: Read>(&mut self, name: &Path, kind: EntryKind, reader: &mut R) -> io::Result<()> {
+        // `./`, as written by `tar -C dir .`, is the destination itself.
+        if name.components().all(|c| c == Component::CurDir) {
+            return Ok(());
+        }
+        let relative = match safe_entry_path(name) {
+            Ok(relative) => relative,
+            Err(_) => return self.skip(name, "absolute path or parent directory component"),
+        };
+        let target = self.dest.join(&relative);
+        if !lexically_inside(&target, &self.sandbox) {
+            return self.skip(name, "outside of the sandbox root");
+        }
+        let parent = target.parent().unwrap_or(&self.dest);
+        fs::create_dir_all(parent)?;
+        // A previously extracted symlink could redirect this entry somewhere else.
+        if !fs::canonicalize(parent)?.starts_with(&self.sandbox)
+            || fs::symlink_metadata(&target)
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(false)
+        {
+            return self.skip(name, "path passes through a symlink");
+        }
+        match kind {
+            EntryKind::Dir => fs::create_dir_all(&target)?,
+            EntryKind::File => write_entry(reader, &target)?,
+            EntryKind::Symlink(link) => {
+                let resolved = if link.is_absolute() {
+                    link.clone()
+                } else {
+                    parent.join(&link)
+                };
+                if !lexically_inside(&resolved, &self.sandbox) {
+                    return self.skip(name, "symlink target outside of the sandbox root");
+                }
+                create_symlink(&link, &target)?;
+            }
+            EntryKind::HardLink(link) => {
+                let source = match safe_entry_path(&link) {
+                    Ok(relative) => self.dest.join(relative),
+                    Err(_) => {
+                        return self.skip(name, "hard link target outside of the sandbox root")
+                    }
+                };
+                if !lexically_inside(&source, &self.sandbox) {
+                    return self.skip(name, "hard link target outside of the sandbox root");
+                }
+                let is_file = fs::symlink_metadata(&source).is_ok_and(|m| m.is_file());
+                if !is_file || !fs::canonicalize(&source)?.starts_with(&self.sandbox) {
+                    self.pass_over(name, "hard link to an entry that wasn't extracted");
+                    return Ok(());
+                }
+                if fs::symlink_metadata(&target).is_ok() {
+                    fs::remove_file(&target)?;
+                }
+                fs::hard_link(&source, &target)?;
+            }
+        }
+        self.report
+            .extracted
+            .push(target.to_string_lossy().into_owned());
+        Ok(())
+    }
+}
+
+// Resolves `.` and `..` without touching the filesystem and checks the result stays under `root`.
+fn lexically_inside(path: &Path, root: &Path) -> bool {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return false;
+                }
+            }
+            Component::CurDir => {}
+            other => resolved.push(other),
+        }
+    }
+    resolved.starts_with(root)
+}
+
+#[cfg(unix)]
+fn create_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link, target)
+}
+
+#[cfg(windows)]
+fn create_symlink(link: &Path, target: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(link, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn build_tar_gz(path: &Path, entries: &[(&str, usize)]) {
+        let file = fs::File::create(path).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        for (name, size) in entries {
+            let data = vec![b'x'; *size];
+            let mut header = tar::Header::new_gnu();
+            header.set_size(*size as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, &data[..]).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_cache_get_and_evict() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("data.tar.gz");
+        build_tar_gz(&archive, &[("a.bin", 600), ("nested/b.bin", 600)]);
+        let archive = archive.to_str().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let cache = ExtractCache::new(cache_dir.to_str().unwrap(), 1000).unwrap();
+
+        let a = cache.get(archive, "a.bin").unwrap();
+        assert_eq!(fs::metadata(&a).unwrap().len(), 600);
+        assert_eq!(cache.get(archive, "a.bin").unwrap(), a);
+
+        let b = cache.get(archive, "nested/b.bin").unwrap();
+        assert!(b.is_file());
+        assert!(!a.exists(), "least recently used entry should be evicted");
+
+        let missing = cache.get(archive, "missing.bin").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        assert!(cache.get(archive, "../escape").is_err());
+    }
+
+    fn build_hostile_tar_gz(path: &Path) {
+        let file = fs::File::create(path).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "ok.txt", &b"ok"[..])
+            .unwrap();
+        // `append_data` refuses `..`, so write the raw name into the header.
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..13].copy_from_slice(b"../escape.txt");
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"no"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_archive_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("hostile.tar.gz");
+        build_hostile_tar_gz(&archive);
+        let dest = dir.path().join("out");
+        let archive = archive.to_str().unwrap();
+        let dest_str = dest.to_str().unwrap();
+
+        let err = extract_archive(archive, dest_str, &ExtractOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let options = ExtractOptions {
+            mode: UnsafeEntryMode::Lenient,
+            ..Default::default()
+        };
+        let report = extract_archive(archive, dest_str, &options).unwrap();
+        assert_eq!(report.extracted.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(dest.join("ok.txt").exists());
+        assert!(!dir.path().join("escape.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_archive_of_current_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("dot.tar.gz");
+        let file = fs::File::create(&archive).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, "./", io::empty()).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "./a.txt", &b"aa"[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        let dest = dir.path().join("out");
+
+        let options = ExtractOptions::default();
+        let report =
+            extract_archive(archive.to_str().unwrap(), dest.to_str().unwrap(), &options).unwrap();
+        assert_eq!(report.extracted.len(), 1);
+        assert!(report.skipped.is_empty());
+        assert_eq!(fs::read(dest.join("a.txt")).unwrap(), b"aa");
+    }
+
+    #[test]
+    fn test_extract_archive_with_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("links.tar.gz");
+        let file = fs::File::create(&archive).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "a.txt", &b"aa"[..])
+            .unwrap();
+        for (name, target) in [("b.txt", "a.txt"), ("c.txt", "missing.txt")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            header.set_mode(0o644);
+            builder.append_link(&mut header, name, target).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        let dest = dir.path().join("out");
+
+        let options = ExtractOptions::default();
+        let report =
+            extract_archive(archive.to_str().unwrap(), dest.to_str().unwrap(), &options).unwrap();
+        assert_eq!(fs::read(dest.join("b.txt")).unwrap(), b"aa");
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, "c.txt");
+    }
+
+    #[test]
+    fn test_restore_archive_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+        build_tar_gz(&archive, &[("a.bin", 3)]);
+        let live = dir.path().join("live");
+        fs::create_dir(&live).unwrap();
+        fs::write(live.join("old.bin"), "old").unwrap();
+        let archive = archive.to_str().unwrap();
+
+        let mut checksums = BTreeMap::new();
+        checksums.insert("a.bin".to_string(), "0".repeat(64));
+        let err = restore_archive_verified(
+            archive,
+            live.to_str().unwrap(),
+            &checksums,
+            &Default::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(live.join("old.bin").exists());
+
+        checksums.insert("a.bin".to_string(), crate::hash::sha256_hex(b"xxx"));
+        restore_archive_verified(
+            archive,
+            live.to_str().unwrap(),
+            &checksums,
+            &Default::default(),
+        )
+        .unwrap();
+        assert!(live.join("a.bin").exists());
+        assert!(!live.join("old.bin").exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::disk::ensure_writable;
+use crate::info::{get_files, select_old_files_matching};
+use crate::retention::DeletionOrder;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::cell::RefCell;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Matches files by owning user and/or group.
+///
+/// On platforms without Unix ownership, a filter with any constraint matches nothing,
+/// so cleanups restricted to an owner never touch files they cannot attribute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnerFilter {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl OwnerFilter {
+    /// Matches files owned by the user `uid`.
+    pub fn uid(uid: u32) -> Self {
+        OwnerFilter {
+            uid: Some(uid),
+            gid: None,
+        }
+    }
+
+    /// Matches files owned by the group `gid`.
+    pub fn gid(gid: u32) -> Self {
+        OwnerFilter {
+            uid: None,
+            gid: Some(gid),
+        }
+    }
+
+    /// Returns true if the given ids belong to the configured owner.
+    ///
+    /// Unknown ids (`None`) never match a constrained filter.
+    pub fn matches_ids(&self, uid: Option<u32>, gid: Option<u32>) -> bool {
+        self.uid.is_none_or(|want| uid == Some(want))
+            && self.gid.is_none_or(|want| gid == Some(want))
+    }
+
+    /// Returns true if `metadata` belongs to the configured owner.
+    #[cfg(unix)]
+    pub fn matches(&self, metadata: &fs::Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        self.uid.is_none_or(|uid| metadata.uid() == uid)
+            && self.gid.is_none_or(|gid| metadata.gid() == gid)
+    }
+
+    /// Returns true if `metadata` belongs to the configured owner.
+    #[cfg(not(unix))]
+    pub fn matches(&self, _metadata: &fs::Metadata) -> bool {
+        self.uid.is_none() && self.gid.is_none()
+    }
+}
+
+/// Returns the name of the user `uid`, or `None` if it has no entry in the user database.
+/// Names are cached, since listings look up the same few ids for every file.
+#[cfg(unix)]
+pub fn user_name(uid: u32) -> Option<String> {
+    thread_local! {
+        static USERS: RefCell<HashMap<u32, Option<String>>> = RefCell::new(HashMap::new());
+    }
+    USERS.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(uid)
+            .or_insert_with(|| {
+                lookup_name(
+                    |pwd: &mut libc::passwd, buf, len, result| {
+                        // SAFETY: every pointer refers to a live, properly sized buffer.
+                        unsafe { libc::getpwuid_r(uid, pwd, buf, len, result) }
+                    },
+                    |pwd| pwd.pw_name,
+                )
+            })
+            .clone()
+    })
+}
+
+/// Returns the name of the group `gid`, or `None` if it has no entry in the group database.
+#[cfg(unix)]
+pub fn group_name(gid: u32) -> Option<String> {
+    thread_local! {
+        static GROUPS: RefCell<HashMap<u32, Option<String>>> = RefCell::new(HashMap::new());
+    }
+    GROUPS.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(gid)
+            .or_insert_with(|| {
+                lookup_name(
+                    |grp: &mut libc::group, buf, len, result| {
+                        // SAFETY: every pointer refers to a live, properly sized buffer.
+                        unsafe { libc::getgrgid_r(gid, grp, buf, len, result) }
+                    },
+                    |grp| grp.gr_name,
+                )
+            })
+            .clone()
+    })
+}
+
+// Calls a reentrant `get*id_r` lookup, growing the buffer while it reports ERANGE.
+#[cfg(unix)]
+fn lookup_name<T>(
+    lookup: impl Fn(&mut T, *mut libc::c_char, usize, *mut *mut T) -> libc::c_int,
+    name: impl Fn(&T) -> *const libc::c_char,
+) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        // SAFETY: passwd and group are plain C structs, for which all zeroes is valid.
+        let mut entry: T = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let rc = lookup(&mut entry, buf.as_mut_ptr(), buf.len(), &mut result);
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+        // SAFETY: on success the name points to a NUL-terminated string inside `buf`.
+        return Some(
+            unsafe { std::ffi::CStr::from_ptr(name(&entry)) }
+                .to_string_lossy()
+                .into_owned(),
+        );
+    }
+}
+
+/// Returns the name of the user `uid`. Always `None` without Unix ownership.
+#[cfg(not(unix))]
+pub fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+/// Returns the name of the group `gid`. Always `None` without Unix ownership.
+#[cfg(not(unix))]
+pub fn group_name(_gid: u32) -> Option<String> {
+    None
+}
+
+/// Compiles glob patterns, reporting the first invalid one as `ErrorKind::InvalidInput`.
+pub(crate) fn compile_globs(patterns: &[String]) -> io::Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|p| {
+            glob::Pattern::new(p).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid glob pattern {:?}: {}", p, e),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Matches a path relative to the scanned root against a glob.
+///
+/// Patterns containing a `/` are matched against the whole relative path (with `**`
+/// crossing directories); other patterns are matched against the file name only, so
+/// `*.log` matches logs at any depth.
+pub(crate) fn glob_matches(pattern: &glob::Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        pattern.matches_path_with(relative, options)
+    } else {
+        relative
+            .file_name()
+            .map(|name| pattern.matches(&name.to_string_lossy()))
+            .unwrap_or(false)
+    }
+}
+
+/// Retrieves all files under `dir` (including subdirectories) owned by the given user/group.
+///
+/// # Arguments
+///
+/// * `dir` - A reference to a Path that holds the directory from which files should be retrieved.
+/// * `owner` - The owner the files must match.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PathBuf>>` - A Result containing the matching files.
+pub fn get_files_owned_by(dir: &Path, owner: &OwnerFilter) -> io::Result<Vec<PathBuf>> {
+    let mut files = get_files(dir)?;
+    files.retain(|path| {
+        fs::symlink_metadata(path)
+            .map(|metadata| owner.matches(&metadata))
+            .unwrap_or(false)
+    });
+    Ok(files)
+}
+
+/// Removes the oldest files owned by the given user/group until the directory fits in `keep` bytes.
+///
+/// The whole directory counts towards its size, but only matching files are ever deleted,
+/// so files belonging to other tenants of a shared volume are left alone even if that
+/// means the target cannot be reached.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `keep` - The maximum size (in bytes) that the directory should be.
+/// * `owner` - The owner files must match to be eligible for deletion.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were removed.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::OwnerFilter;
+///
+/// let removed = bbq::remove_old_files_owned_by("/scratch", 1024 * 1024 * 1024, &OwnerFilter::uid(1001)).unwrap();
+/// ```
+pub fn remove_old_files_owned_by(
+    dir: &str,
+    keep: u64,
+    owner: &OwnerFilter,
+) -> io::Result<Vec<String>> {
+    ensure_writable(dir)?;
+    let mut removed_files = Vec::new();
+    for (file, _) in
+        select_old_files_matching(dir, keep, DeletionOrder::OldestModified, &|_, metadata| {
+            owner.matches(metadata)
+        })?
+    {
+        fs::remove_file(&file)?;
+        removed_files.push(file.to_string_lossy().into_owned());
+    }
+    Ok(removed_files)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_owner_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("mine.txt");
+        fs::write(&file, "x").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+
+        let mine = OwnerFilter::uid(metadata.uid());
+        let theirs = OwnerFilter::uid(metadata.uid().wrapping_add(1));
+        assert!(mine.matches(&metadata));
+        assert!(!theirs.matches(&metadata));
+        assert_eq!(get_files_owned_by(dir.path(), &theirs).unwrap().len(), 0);
+
+        let removed = remove_old_files_owned_by(dir.path().to_str().unwrap(), 0, &theirs).unwrap();
+        assert!(removed.is_empty());
+        assert!(file.exists());
+        let removed = remove_old_files_owned_by(dir.path().to_str().unwrap(), 0, &mine).unwrap();
+        assert_eq!(removed.len(), 1);
+    }
+}
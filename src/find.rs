@@ -0,0 +1,260 @@
+use crate::filter::{compile_globs, glob_matches};
+use crate::info::FileInfo;
+use crate::walk::{include_hidden_by_default, walk_with, Visit, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+/// Options shared by the finders: [`find_with`] and [`find_where`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FindOptions {
+    /// Stop searching once this many matches have been found.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// How many levels to descend; see [`WalkOptions::max_depth`].
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Consider directories as well as files.
+    #[serde(default)]
+    pub include_dirs: bool,
+    /// Search [hidden](crate::is_hidden) files and directories too. Defaults to `true`.
+    #[serde(default = "include_hidden_by_default")]
+    pub include_hidden: bool,
+}
+
+impl Default for FindOptions {
+    fn default() -> Self {
+        FindOptions {
+            limit: None,
+            max_depth: None,
+            include_dirs: false,
+            include_hidden: include_hidden_by_default(),
+        }
+    }
+}
+
+impl FindOptions {
+    /// Returns options that stop after `limit` matches.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns options that go no deeper than `max_depth` levels.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Returns options that search hidden entries, or leave them out.
+    pub fn with_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Returns options that also match directories.
+    pub fn with_dirs(mut self) -> Self {
+        self.include_dirs = true;
+        self
+    }
+}
+
+/// What `find_regex_with` matches its regex against (with the `regex` feature).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MatchOn {
+    /// The file name alone.
+    #[default]
+    Name,
+    /// The full path, starting with the searched directory.
+    Path,
+}
+
+/// Finds the files under `dir` matching a glob, at any depth.
+///
+/// Patterns containing a `/` are matched against the path relative to `dir`, with `**`
+/// matching any number of directories (`**/*.log`, `app/*/current.log`). Other patterns
+/// are matched against the file name only, so `*.log` also finds logs at any depth.
+/// Symlinked directories are not descended into, and unreadable entries are skipped.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to search.
+/// * `pattern` - The glob the files must match.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PathBuf>>` - A Result containing the matching files. An invalid pattern returns `ErrorKind::InvalidInput`.
+///
+/// # Example
+///
+/// ```no_run
+/// let logs = bbq::find("/var/log/app", "**/*.log").unwrap();
+/// ```
+pub fn find(dir: &str, pattern: &str) -> io::Result<Vec<PathBuf>> {
+    find_with(dir, pattern, &FindOptions::default())
+}
+
+/// Like [`find`], but stops as soon as `options.limit` files have matched.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{find_with, FindOptions};
+///
+/// let any_dump = !find_with("/srv", "**/core.*", &FindOptions::default().with_limit(1))
+///     .unwrap()
+///     .is_empty();
+/// ```
+pub fn find_with(dir: &str, pattern: &str, options: &FindOptions) -> io::Result<Vec<PathBuf>> {
+    let pattern = compile_globs(&[pattern.to_string()])?.remove(0);
+    let root = Path::new(dir);
+    find_where(dir, options, |info| {
+        let path = Path::new(&info.file_path);
+        glob_matches(&pattern, path.strip_prefix(root).unwrap_or(path))
+    })
+}
+
+/// Finds the files under `dir` for which `predicate` returns true, stopping as soon as
+/// `options.limit` have matched. Directories are passed to `predicate` too if
+/// `options.include_dirs` is set.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to search.
+/// * `options` - The limit and depth of the search.
+/// * `predicate` - Called with every file found.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PathBuf>>` - A Result containing the matching files, in the order they were found.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{find_where, FindOptions};
+///
+/// // Is there at least one file larger than 10 GB?
+/// let huge = find_where("/srv", &FindOptions::default().with_limit(1), |info| {
+///     info.size > 10 * 1024 * 1024 * 1024
+/// })
+/// .unwrap();
+/// println!("{:?}", huge.first());
+/// ```
+pub fn find_where<F>(dir: &str, options: &FindOptions, mut predicate: F) -> io::Result<Vec<PathBuf>>
+where
+    F: FnMut(&FileInfo) -> bool,
+{
+    let mut found = Vec::new();
+    if options.limit == Some(0) {
+        return Ok(found);
+    }
+    let walk_options = WalkOptions {
+        max_depth: options.max_depth,
+        include_hidden: options.include_hidden,
+        ..Default::default()
+    };
+    walk_with(dir, &walk_options, |info| {
+        let eligible =
+            info.file_type == "File" || (options.include_dirs && info.file_type == "Directory");
+        if eligible && predicate(info) {
+            found.push(PathBuf::from(&info.file_path));
+            if options.limit.is_some_and(|limit| found.len() >= limit) {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    Ok(found)
+}
+
+/// Finds the files under `dir` whose name matches `re`, at any depth.
+///
+/// # Example
+///
+/// ```no_run
+/// let re = regex::Regex::new(r"^app-\d{4}-\d{2}-\d{2}\.log$").unwrap();
+/// let logs = bbq::find_regex("/var/log/app", &re).unwrap();
+/// ```
+#[cfg(feature = "regex")]
+pub fn find_regex(dir: &str, re: &regex::Regex) -> io::Result<Vec<PathBuf>> {
+    find_regex_with(dir, re, MatchOn::Name, &FindOptions::default())
+}
+
+/// Like [`find_regex`], but matches `re` against the file name or the full path, and
+/// takes the usual [`FindOptions`]; set `include_dirs` to match directories too.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{find_regex_with, FindOptions, MatchOn};
+///
+/// let re = regex::Regex::new(r"/(tmp|cache)$").unwrap();
+/// let scratch = find_regex_with("/srv", &re, MatchOn::Path, &FindOptions::default().with_dirs())
+///     .unwrap();
+/// ```
+#[cfg(feature = "regex")]
+pub fn find_regex_with(
+    dir: &str,
+    re: &regex::Regex,
+    on: MatchOn,
+    options: &FindOptions,
+) -> io::Result<Vec<PathBuf>> {
+    find_where(dir, options, |info| match on {
+        MatchOn::Name => re.is_match(&info.file_name),
+        MatchOn::Path => re.is_match(&info.file_path),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_find() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("app/old")).unwrap();
+        fs::write(dir.path().join("top.log"), "x").unwrap();
+        fs::write(dir.path().join("app/old/a.log"), "x").unwrap();
+        fs::write(dir.path().join("app/old/a.txt"), "x").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(find(root, "**/*.log").unwrap().len(), 2);
+        assert_eq!(find(root, "*.txt").unwrap().len(), 1);
+        let nested = find(root, "app/*/*.log").unwrap();
+        assert_eq!(nested, vec![dir.path().join("app/old/a.log")]);
+        let options = FindOptions::default().with_limit(1);
+        assert_eq!(find_with(root, "**/*", &options).unwrap().len(), 1);
+        let mut visited = 0;
+        let first = find_where(root, &options, |_| {
+            visited += 1;
+            true
+        })
+        .unwrap();
+        assert_eq!((first.len(), visited), (1, 1));
+        assert_eq!(
+            find(root, "[").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_find_regex() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("cache")).unwrap();
+        fs::write(dir.path().join("cache/app-1.log"), "x").unwrap();
+        fs::write(dir.path().join("app-x.log"), "x").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let re = regex::Regex::new(r"^app-\d+\.log$").unwrap();
+        let found = find_regex(root, &re).unwrap();
+        assert_eq!(found, vec![dir.path().join("cache/app-1.log")]);
+
+        let re = regex::Regex::new(r"/cache$").unwrap();
+        let options = FindOptions::default().with_dirs();
+        let dirs = find_regex_with(root, &re, MatchOn::Path, &options).unwrap();
+        assert_eq!(dirs, vec![dir.path().join("cache")]);
+    }
+}
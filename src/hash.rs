@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use sha2::digest::generic_array::GenericArray;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+const BLOCK_SIZE: usize = 64;
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The progress of a resumable SHA-256 hash of one file.
+///
+/// The state is plain data and serializes to JSON, so hashing a very large file can be
+/// paused, saved, and picked up again by another process. It remembers the size and
+/// modification time of the file, and refuses to resume if the file has changed since.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::HashState;
+///
+/// let mut state = match HashState::load("/tmp/disk.img.hash.json") {
+///     Ok(state) => state,
+///     Err(_) => HashState::new("/srv/vm/disk.img").unwrap(),
+/// };
+/// // Hash 1 GiB per run, saving progress in between.
+/// match state.update(1024 * 1024 * 1024).unwrap() {
+///     Some(digest) => println!("sha256 {}", digest),
+///     None => state.save("/tmp/disk.img.hash.json").unwrap(),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashState {
+    /// The file being hashed.
+    pub path: String,
+    /// The size of the file when hashing started.
+    pub size: u64,
+    /// The modification time of the file when hashing started.
+    pub modified: Option<SystemTime>,
+    /// The number of bytes hashed so far; always a whole number of blocks.
+    pub offset: u64,
+    state: [u32; 8],
+}
+
+impl HashState {
+    /// Starts hashing `file` from the beginning.
+    pub fn new(file: &str) -> io::Result<HashState> {
+        let metadata = fs::metadata(file)?;
+        Ok(HashState {
+            path: file.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            offset: 0,
+            state: SHA256_INIT,
+        })
+    }
+
+    /// Writes the state to `file` as JSON.
+    pub fn save(&self, file: &str) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        fs::write(file, json)
+    }
+
+    /// Reads a state previously written with [`HashState::save`].
+    ///
+    /// A state whose `offset` is past the end of the file or not a whole number of
+    /// blocks is rejected with an `ErrorKind::InvalidData` error.
+    pub fn load(file: &str) -> io::Result<HashState> {
+        let json = fs::read(file)?;
+        let state: HashState = serde_json::from_slice(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        state.check_offset()?;
+        Ok(state)
+    }
+
+    // Resuming from any other offset would underflow or hash the wrong bytes.
+    fn check_offset(&self) -> io::Result<()> {
+        if self.offset > self.size || !self.offset.is_multiple_of(BLOCK_SIZE as u64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "invalid hash state for {}: offset {} of {} bytes",
+                    self.path, self.offset, self.size
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Hashes at most `max_bytes` more of the file.
+    ///
+    /// `max_bytes` is rounded down to whole 64-byte blocks (at least one block).
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<Option<String>>` - A Result containing the hex SHA-256 digest once the whole file has been hashed, or `None` if there is more to do. If the file changed since hashing started, an `ErrorKind::InvalidData` error is returned.
+    pub fn update(&mut self, max_bytes: u64) -> io::Result<Option<String>> {
+        self.check_offset()?;
+        let metadata = fs::metadata(&self.path)?;
+        if metadata.len() != self.size || metadata.modified().ok() != self.modified {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} changed while it was being hashed", self.path),
+            ));
+        }
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+
+        let budget = (max_bytes / BLOCK_SIZE as u64).max(1) * BLOCK_SIZE as u64;
+        let whole_blocks = (self.size - self.offset) / BLOCK_SIZE as u64 * BLOCK_SIZE as u64;
+        let mut remaining = budget.min(whole_blocks);
+        let mut buffer = vec![0u8; 1024 * BLOCK_SIZE];
+        while remaining > 0 {
+            let len = remaining.min(buffer.len() as u64) as usize;
+            file.read_exact(&mut buffer[..len])?;
+            compress_blocks(&mut self.state, &buffer[..len]);
+            self.offset += len as u64;
+            remaining -= len as u64;
+        }
+
+        if self.size - self.offset >= BLOCK_SIZE as u64 {
+            return Ok(None);
+        }
+        let mut tail = Vec::with_capacity(2 * BLOCK_SIZE);
+        file.read_to_end(&mut tail)?;
+        tail.push(0x80);
+        while tail.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&(self.size * 8).to_be_bytes());
+        let mut state = self.state;
+        compress_blocks(&mut state, &tail);
+        Ok(Some(
+            state.iter().map(|word| format!("{:08x}", word)).collect(),
+        ))
+    }
+}
+
+fn compress_blocks(state: &mut [u32; 8], data: &[u8]) {
+    let blocks: Vec<_> = data
+        .chunks_exact(BLOCK_SIZE)
+        .map(GenericArray::clone_from_slice)
+        .collect();
+    sha2::compress256(state, &blocks);
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    use sha2::Digest;
+    sha2::Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Computes the hex SHA-256 digest of a file in one go.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file.
+///
+/// # Returns
+///
+/// * `std::io::Result<String>` - A Result containing the lowercase hex digest.
+pub fn hash_file(file: &str) -> io::Result<String> {
+    let mut state = HashState::new(file)?;
+    loop {
+        if let Some(digest) = state.update(u64::MAX)? {
+            return Ok(digest);
+        }
+    }
+}
+
+/// Computes a Merkle-style hex SHA-256 digest of everything under `dir`.
+///
+/// Each directory is hashed over the sorted names, kinds and hashes of its entries, so the
+/// result changes when any file's content, or any entry's name, changes anywhere in the
+/// tree, and stays the same across runs and machines otherwise. Modification times and
+/// permissions are ignored. Symlinks are hashed by their target and not followed.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+///
+/// # Returns
+///
+/// * `std::io::Result<String>` - A Result containing the lowercase hex digest.
+///
+/// # Example
+///
+/// ```no_run
+/// let digest = bbq::hash_dir("/srv/site").unwrap();
+/// let last = std::fs::read_to_string("/var/lib/deploy/site.sha256").unwrap_or_default();
+/// if digest != last {
+///     println!("site changed since the last deploy");
+/// }
+/// ```
+pub fn hash_dir(dir: &str) -> io::Result<String> {
+    hash_tree(Path::new(dir))
+}
+
+fn hash_tree(dir: &Path) -> io::Result<String> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut listing = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        let (kind, digest) = if file_type.is_symlink() {
+            let target = fs::read_link(&path)?;
+            ('l', sha256_hex(target.to_string_lossy().as_bytes()))
+        } else if file_type.is_dir() {
+            ('d', hash_tree(&path)?)
+        } else {
+            ('f', hash_file(&path.to_string_lossy())?)
+        };
+        // Length-prefixed, so no name can be mistaken for another entry.
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        listing
+            .extend_from_slice(format!("{} {}:{} {}\n", kind, name.len(), name, digest).as_bytes());
+    }
+    Ok(sha256_hex(&listing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resumable_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, b"abc").unwrap();
+        assert_eq!(
+            hash_file(file.to_str().unwrap()).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        fs::write(&file, &data).unwrap();
+        let saved = dir.path().join("state.json");
+        let mut state = HashState::new(file.to_str().unwrap()).unwrap();
+        let digest = loop {
+            if let Some(digest) = state.update(100).unwrap() {
+                break digest;
+            }
+            state.save(saved.to_str().unwrap()).unwrap();
+            state = HashState::load(saved.to_str().unwrap()).unwrap();
+        };
+        assert_eq!(
+            digest,
+            "a8af099bf2e878609558dbf69d8f88f4a31040a8cf84b549a0cfa912f12ffc3f"
+        );
+
+        let mut state = HashState::new(file.to_str().unwrap()).unwrap();
+        fs::write(&file, b"changed").unwrap();
+        assert_eq!(
+            state.update(64).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, vec![0u8; 200]).unwrap();
+        let saved = dir.path().join("state.json");
+        let saved = saved.to_str().unwrap();
+        for offset in [256, 100] {
+            let mut state = HashState::new(file.to_str().unwrap()).unwrap();
+            state.offset = offset;
+            state.save(saved).unwrap();
+            assert_eq!(
+                HashState::load(saved).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+            assert_eq!(
+                state.update(64).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("a/b/f"), "one").unwrap();
+        fs::write(dir.path().join("g"), "two").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let digest = hash_dir(root).unwrap();
+        assert_eq!(digest.len(), 64);
+        assert_eq!(hash_dir(root).unwrap(), digest);
+
+        fs::write(dir.path().join("a/b/f"), "uno").unwrap();
+        let changed = hash_dir(root).unwrap();
+        assert_ne!(changed, digest);
+        fs::rename(dir.path().join("g"), dir.path().join("h")).unwrap();
+        assert_ne!(hash_dir(root).unwrap(), changed);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::archive::{write_tar_gz, ArchiveOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn walker(dir: &Path) -> ignore::Walk {
+    ignore::WalkBuilder::new(dir)
+        .hidden(false)
+        .git_ignore(true)
+        .git_exclude(true)
+        .ignore(true)
+        .require_git(false)
+        .build()
+}
+
+/// Retrieves all files from a directory, skipping anything excluded by `.gitignore` or `.ignore` rules.
+///
+/// Ignore files are honored even outside of a git repository, and hidden files are
+/// included unless an ignore rule excludes them. Symlinks are skipped, like [`get_files`](crate::get_files).
+///
+/// # Arguments
+///
+/// * `dir` - A reference to a Path that holds the directory from which files should be retrieved.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PathBuf>>` - A Result containing the files that are not ignored.
+pub fn get_files_respecting_ignore(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in walker(dir) {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Archives a directory into `name.tar.gz`, leaving out files excluded by `.gitignore` or `.ignore` rules.
+///
+/// This is handy for packaging source trees without their build artifacts. Entries are
+/// stored under the directory's own name, as with [`archive_dir_with_options`](crate::archive_dir_with_options).
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::ArchiveOptions;
+///
+/// bbq::archive_dir_respecting_ignore("/src/project", "/tmp/project", &ArchiveOptions::default()).unwrap();
+/// ```
+pub fn archive_dir_respecting_ignore(
+    dir: &str,
+    name: &str,
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let dir = Path::new(dir);
+    let files = get_files_respecting_ignore(dir)?;
+    let base = dir.parent().unwrap_or(dir);
+    write_tar_gz(
+        Path::new(&format!("{}.tar.gz", name)),
+        base,
+        &files,
+        options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_get_files_respecting_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.tmp\n").unwrap();
+        fs::write(dir.path().join("main.rs"), "").unwrap();
+        fs::write(dir.path().join("scratch.tmp"), "").unwrap();
+        fs::write(dir.path().join("target/app"), "").unwrap();
+
+        let mut names: Vec<String> = get_files_respecting_ignore(dir.path())
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![".gitignore", "main.rs"]);
+    }
+}
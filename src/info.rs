@@ -1,6 +1,14 @@
+use crate::copy::move_across_devices;
+use crate::filter::{group_name, user_name};
+use crate::manifest::{entry_from_metadata, Manifest, ManifestEntry};
+use crate::part::is_fresh_part_file;
+use crate::progress::CancelToken;
+use crate::retention::DeletionOrder;
+use crate::walk::{walk_with, SymlinkPolicy, Visit, WalkOptions};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,9 +16,67 @@ pub struct FileInfo {
     pub file_name: String,
     pub file_type: String,
     pub file_path: String,
-    pub created_time: SystemTime,
-    pub modified_time: SystemTime,
+    /// The file name without its extension: `app.log` for `app.log.gz`, `.bashrc` for
+    /// `.bashrc`.
+    #[serde(default)]
+    pub file_stem: String,
+    /// The part of the file name after the last dot, without the dot and as written:
+    /// `gz` for `app.log.gz`, `None` for `Makefile` or `.bashrc`.
+    #[serde(default)]
+    pub extension: Option<String>,
+    /// When the file was created. `None` where the filesystem doesn't record birth times,
+    /// as on many Linux filesystems.
+    pub created_time: Option<SystemTime>,
+    pub modified_time: Option<SystemTime>,
+    /// When the file was last read. `None` where the platform can't report it.
+    #[serde(default)]
+    pub accessed_time: Option<SystemTime>,
     pub size: u64,
+    /// The space the file takes on disk, in bytes: its allocated blocks on Unix, which is
+    /// less than `size` for sparse files and more for small files. See [`allocated_size`].
+    #[serde(default)]
+    pub allocated_size: Option<u64>,
+    /// Unix permission bits (including setuid, setgid and sticky), when available.
+    #[serde(default)]
+    pub mode: Option<u32>,
+    /// The permissions as `ls -l` shows them, such as `rw-r--r--`.
+    #[serde(default)]
+    pub permissions: String,
+    /// Whether the file is read-only: no write bits on Unix, the read-only attribute on Windows.
+    #[serde(default)]
+    pub readonly: bool,
+    /// Owning user and group ids, on Unix.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// The names of the owning user and group, when they resolve.
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    /// The MIME type of a file, from its content or extension. Only detected with the
+    /// `mime` feature, by walks with [`WalkOptions::detect_content_type`] set; see
+    /// `detect_content_type`.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// The number of hard links to the file, on Unix.
+    #[serde(default)]
+    pub nlink: Option<u64>,
+    /// The device and inode numbers, on Unix. Together they identify the file; see
+    /// [`FileInfo::is_same_file`].
+    #[serde(default)]
+    pub device: Option<u64>,
+    #[serde(default)]
+    pub inode: Option<u64>,
+}
+
+impl FileInfo {
+    /// Returns true if both entries are the same file, such as two hard links to it.
+    /// Always false where the platform has no file ids.
+    pub fn is_same_file(&self, other: &FileInfo) -> bool {
+        self.inode.is_some() && (self.device, self.inode) == (other.device, other.inode)
+    }
 }
 
 /// Compresses the specified directory into a tar.gz file.
@@ -42,7 +108,7 @@ pub fn archive_dir(dir: &str, name: &str) -> std::io::Result<()> {
         .arg(dir)
         .output()?;
     if !output.status.success() {
-        return Err(std::io::Error::new(std::io::ErrorKind::Other, "tar failed"));
+        return Err(std::io::Error::other("tar failed"));
     }
     Ok(())
 }
@@ -62,6 +128,7 @@ pub fn archive_dir(dir: &str, name: &str) -> std::io::Result<()> {
 /// remove_dir(dir);
 /// ```
 pub fn remove_dir(dir: &str) -> std::io::Result<()> {
+    crate::disk::ensure_writable(dir)?;
     fs::remove_dir_all(dir)
 }
 
@@ -80,6 +147,7 @@ pub fn remove_dir(dir: &str) -> std::io::Result<()> {
 /// remove_file(file);
 /// ```
 pub fn remove_file(file: &str) -> std::io::Result<()> {
+    crate::disk::ensure_writable(file)?;
     fs::remove_file(file)
 }
 
@@ -137,8 +205,63 @@ pub fn write_text_file(file: &str, data: &str) -> std::io::Result<()> {
     fs::write(file, data)
 }
 
+/// Appends binary data to the end of a file, creating the file if it doesn't exist.
+///
+/// The data is written with one call on a file opened for appending, so small appends
+/// from several processes don't interleave.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to append to.
+/// * `data` - A byte slice that contains the data to append.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. If the operation was successful, it will contain an empty tuple. If it was not successful, it will contain an error.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::append_file("/var/lib/app/events.bin", &[1, 2, 3]).unwrap();
+/// ```
+pub fn append_file(file: &str, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    fs::File::options()
+        .create(true)
+        .append(true)
+        .open(file)?
+        .write_all(data)
+}
+
+/// Appends a text string to the end of a file, creating the file if it doesn't exist.
+///
+/// No line break is added; end `data` with `\n` to append whole lines.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to append to.
+/// * `data` - A string slice that contains the text to append.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. If the operation was successful, it will contain an empty tuple. If it was not successful, it will contain an error.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::append_text_file("/var/log/app/audit.log", "user=alice action=login\n").unwrap();
+/// ```
+pub fn append_text_file(file: &str, data: &str) -> std::io::Result<()> {
+    append_file(file, data.as_bytes())
+}
+
 /// Moves a file from one location to another.
 ///
+/// When `dest` is on another filesystem, where the file can't simply be renamed, it is
+/// copied with its permissions, timestamps, ownership and extended attributes (see
+/// [`copy_metadata`](crate::copy_metadata)), synced to disk, and only then removed from
+/// `src`. While being copied it sits next to `dest` as a `.part` file.
+///
 /// # Arguments
 ///
 /// * `src` - A string slice that holds the name of the source file.
@@ -152,40 +275,191 @@ pub fn write_text_file(file: &str, data: &str) -> std::io::Result<()> {
 /// move_file(src, dest);
 /// ```
 pub fn move_file(src: &str, dest: &str) -> std::io::Result<()> {
-    fs::rename(src, dest)
+    crate::disk::ensure_writable(src)?;
+    let dest_dir = match Path::new(dest).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    crate::disk::ensure_writable(&dest_dir.to_string_lossy())?;
+    match fs::rename(src, dest) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => move_across_devices(src, dest),
+        result => result,
+    }
 }
 
 pub fn get_dir_info(dir: &str) -> std::io::Result<Vec<FileInfo>> {
+    get_dir_info_recursive(dir, Some(1))
+}
+
+/// Like [`get_dir_info`], but also lists the contents of subdirectories.
+///
+/// Directories are listed before their contents. Symlinked directories are listed but not
+/// descended into, so links can't make the walk loop. Subdirectories that can't be read
+/// are skipped.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `max_depth` - How many levels to list: `Some(1)` lists only the entries of `dir`, like [`get_dir_info`], `Some(2)` also lists the entries of its subdirectories, and `None` lists the whole tree.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<FileInfo>>` - A Result containing the information of every entry found.
+///
+/// # Example
+///
+/// ```no_run
+/// let tree = bbq::get_dir_info_recursive("/var/log", None).unwrap();
+/// let shallow = bbq::get_dir_info_recursive("/var/log", Some(2)).unwrap();
+/// ```
+pub fn get_dir_info_recursive(
+    dir: &str,
+    max_depth: Option<usize>,
+) -> std::io::Result<Vec<FileInfo>> {
     let mut files_info = Vec::new();
+    collect_dir_info(Path::new(dir), 1, max_depth, &mut files_info)?;
+    Ok(files_info)
+}
+
+fn collect_dir_info(
+    dir: &Path,
+    depth: usize,
+    max_depth: Option<usize>,
+    files_info: &mut Vec<FileInfo>,
+) -> std::io::Result<()> {
+    if max_depth.is_some_and(|max| depth > max) {
+        return Ok(());
+    }
     if let Ok(entries) = fs::read_dir(dir) {
         for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+            let path = entry?.path();
             let metadata = fs::metadata(&path)?;
-            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
-            let file_type = if metadata.is_file() {
-                "File".to_string()
-            } else if metadata.is_dir() {
-                "Directory".to_string()
-            } else {
-                "Unknown".to_string()
-            };
-            let size = metadata.len();
-            let created_time = metadata.created()?;
-            let modified_time = metadata.modified()?;
-
-            files_info.push(FileInfo {
-                file_name,
-                file_type,
-                file_path: path.to_str().unwrap().to_string(),
-                created_time,
-                modified_time,
-                size,
-            });
+            files_info.push(file_info(&path, &metadata)?);
+            if metadata.is_dir() && !path.is_symlink() {
+                collect_dir_info(&path, depth + 1, max_depth, files_info)?;
+            }
         }
     }
+    Ok(())
+}
 
-    Ok(files_info)
+pub(crate) fn file_info(path: &Path, metadata: &fs::Metadata) -> std::io::Result<FileInfo> {
+    // Names that aren't valid UTF-8 are kept lossily rather than failing the whole walk.
+    let file_name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned();
+    let file_type = if metadata.is_file() {
+        "File".to_string()
+    } else if metadata.is_dir() {
+        "Directory".to_string()
+    } else if metadata.file_type().is_symlink() {
+        "Symlink".to_string()
+    } else {
+        "Unknown".to_string()
+    };
+    let (mode, uid, gid) = unix_permissions(metadata);
+    let (nlink, device, inode) = file_id(metadata);
+    let readonly = metadata.permissions().readonly();
+    Ok(FileInfo {
+        file_name,
+        file_type,
+        file_path: path.to_string_lossy().into_owned(),
+        file_stem: path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned()),
+        extension: path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned()),
+        created_time: metadata.created().ok(),
+        modified_time: metadata.modified().ok(),
+        accessed_time: metadata.accessed().ok(),
+        size: metadata.len(),
+        allocated_size: Some(allocated_size(metadata)),
+        mode,
+        permissions: permissions_string(mode, readonly),
+        readonly,
+        uid,
+        gid,
+        owner: uid.and_then(user_name),
+        group: gid.and_then(group_name),
+        content_type: None,
+        nlink,
+        device,
+        inode,
+    })
+}
+
+#[cfg(feature = "mime")]
+pub(crate) fn content_type(path: &Path, metadata: &fs::Metadata) -> Option<String> {
+    if metadata.is_file() {
+        crate::content_type::detect_content_type(path)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "mime"))]
+pub(crate) fn content_type(_path: &Path, _metadata: &fs::Metadata) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> (Option<u64>, Option<u64>, Option<u64>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.nlink()),
+        Some(metadata.dev()),
+        Some(metadata.ino()),
+    )
+}
+
+// The Windows file index is only available through unstable APIs.
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> (Option<u64>, Option<u64>, Option<u64>) {
+    (None, None, None)
+}
+
+#[cfg(unix)]
+fn unix_permissions(metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.mode() & 0o7777),
+        Some(metadata.uid()),
+        Some(metadata.gid()),
+    )
+}
+
+#[cfg(not(unix))]
+fn unix_permissions(_metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
+
+/// Formats permission bits the way `ls -l` does, such as `rwxr-x---`, including the
+/// setuid, setgid and sticky bits. Without bits (on Windows), everything is readable and
+/// only the read-only flag decides whether it is writable.
+pub fn permissions_string(mode: Option<u32>, readonly: bool) -> String {
+    let Some(mode) = mode else {
+        return if readonly { "r--r--r--" } else { "rw-rw-rw-" }.to_string();
+    };
+    let mut text = String::with_capacity(9);
+    for (shift, special, set, unset) in [
+        (6, 0o4000, 's', 'S'),
+        (3, 0o2000, 's', 'S'),
+        (0, 0o1000, 't', 'T'),
+    ] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(match (bits & 1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    text
 }
 
 /// The `get_size` function returns the total size (in bytes) of the specified directory.
@@ -202,6 +476,179 @@ pub fn get_size(dir: &str) -> std::io::Result<u64> {
     get_size_by_path(path)
 }
 
+/// Like [`get_size`], but only lists directories that changed since `previous` was taken,
+/// reusing its sizes for the rest. See [`Manifest::rescan`] for
+/// what counts as a change.
+///
+/// # Returns
+///
+/// * `std::io::Result<(u64, Manifest)>` - A Result containing the total size of the directory and a fresh manifest to pass in next time.
+///
+/// # Example
+///
+/// ```no_run
+/// let (size, manifest) = bbq::get_size_incremental("/srv/data", &bbq::Manifest::scan("/srv/data").unwrap()).unwrap();
+/// manifest.save("/var/lib/app/data.manifest.json").unwrap();
+/// ```
+pub fn get_size_incremental(dir: &str, previous: &Manifest) -> std::io::Result<(u64, Manifest)> {
+    let manifest = Manifest::rescan(dir, previous)?;
+    Ok((manifest.total_size(), manifest))
+}
+
+/// Like [`get_size`], with symlinks treated as `symlinks` says.
+///
+/// [`get_size`] skips symlinks entirely. `SymlinkPolicy::Report` counts the size of the
+/// links themselves, the way `du` does, and `SymlinkPolicy::Follow` counts what they point
+/// to, descending into each linked directory once.
+///
+/// # Example
+///
+/// ```no_run
+/// let size = bbq::get_size_with("/srv/releases", bbq::SymlinkPolicy::Follow).unwrap();
+/// ```
+pub fn get_size_with(dir: &str, symlinks: SymlinkPolicy) -> std::io::Result<u64> {
+    let options = WalkOptions {
+        symlinks,
+        stop_on_error: true,
+        ..Default::default()
+    };
+    let mut total_size = 0;
+    walk_with::<(), _>(dir, &options, |info| {
+        if info.file_type != "Directory" {
+            total_size += info.size;
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    Ok(total_size)
+}
+
+/// Which size [`get_size_as`] adds up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SizeKind {
+    /// The length of the files, as `ls -l` and [`get_size`] report it.
+    #[default]
+    Apparent,
+    /// The disk space allocated to the files, as `du` reports it. Sparse files count for
+    /// the blocks actually written, and every file for its whole last block.
+    Allocated,
+}
+
+/// Returns the disk space allocated to a file: `st_blocks * 512` on Unix. Elsewhere the
+/// allocation isn't available, and this is the file's length.
+pub fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.blocks() * 512
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.len()
+    }
+}
+
+/// Like [`get_size`], adding up the size `kind` says.
+///
+/// With `SizeKind::Allocated`, a file with several hard links in the tree is only counted
+/// once, since its blocks are only allocated once; this is the real disk consumption to
+/// check quotas against. Symlinks are skipped, as with [`get_size`].
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::SizeKind;
+///
+/// let used = bbq::get_size_as("/srv/tenants/acme", SizeKind::Allocated).unwrap();
+/// let apparent = bbq::get_size_as("/srv/tenants/acme", SizeKind::Apparent).unwrap();
+/// println!("{} bytes on disk for {} bytes of data", used, apparent);
+/// ```
+pub fn get_size_as(dir: &str, kind: SizeKind) -> std::io::Result<u64> {
+    let options = WalkOptions {
+        symlinks: SymlinkPolicy::Skip,
+        stop_on_error: true,
+        ..Default::default()
+    };
+    let mut seen = std::collections::HashSet::new();
+    let mut total_size = 0;
+    walk_with::<(), _>(dir, &options, |info| {
+        if info.file_type != "File" {
+            return ControlFlow::Continue(Visit::Descend);
+        }
+        match kind {
+            SizeKind::Apparent => total_size += info.size,
+            SizeKind::Allocated => {
+                let linked = info.nlink.is_some_and(|nlink| nlink > 1);
+                if !linked || seen.insert((info.device, info.inode)) {
+                    total_size += info.allocated_size.unwrap_or(info.size);
+                }
+            }
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    Ok(total_size)
+}
+
+/// The running totals of [`get_size_with_progress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeProgress {
+    /// Files and directories looked at so far.
+    pub entries_visited: u64,
+    /// The size of the files counted so far.
+    pub bytes: u64,
+    /// The walk was cancelled, so `bytes` only covers part of the tree.
+    pub cancelled: bool,
+}
+
+/// Like [`get_size`], calling `progress` after every entry and stopping early when
+/// `cancel` is cancelled.
+///
+/// # Returns
+///
+/// * `std::io::Result<SizeProgress>` - A Result containing the total. If cancelled, it holds the partial total with `cancelled` set, rather than an error.
+///
+/// # Example
+///
+/// ```no_run
+/// let cancel = bbq::CancelToken::new();
+/// let size = bbq::get_size_with_progress("/srv/data", &cancel, |p| {
+///     if p.entries_visited % 10_000 == 0 {
+///         eprintln!("{} entries, {} bytes so far", p.entries_visited, p.bytes);
+///     }
+/// })
+/// .unwrap();
+/// if size.cancelled {
+///     println!("at least {} bytes", size.bytes);
+/// }
+/// ```
+pub fn get_size_with_progress<F>(
+    dir: &str,
+    cancel: &CancelToken,
+    mut progress: F,
+) -> std::io::Result<SizeProgress>
+where
+    F: FnMut(&SizeProgress),
+{
+    let options = WalkOptions {
+        symlinks: SymlinkPolicy::Skip,
+        stop_on_error: true,
+        ..Default::default()
+    };
+    let mut totals = SizeProgress::default();
+    let cancelled = walk_with(dir, &options, |info| {
+        if cancel.is_cancelled() {
+            return ControlFlow::Break(());
+        }
+        totals.entries_visited += 1;
+        if info.file_type == "File" {
+            totals.bytes += info.size;
+        }
+        progress(&totals);
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    totals.cancelled = cancelled.is_some();
+    Ok(totals)
+}
+
 fn get_size_by_path(path: &Path) -> std::io::Result<u64> {
     let metadata = fs::metadata(path)?;
     if metadata.is_file() {
@@ -224,6 +671,11 @@ fn get_size_by_path(path: &Path) -> std::io::Result<u64> {
 
 /// Removes old files from a directory until the total size of the directory is less than a specified size.
 ///
+/// Files in subdirectories are considered too. The directory is locked with a
+/// [`DirLock`](crate::DirLock) while files are removed, so a second concurrent call fails
+/// with an [`AlreadyRunning`](crate::AlreadyRunning) error. To limit a cleanup to the top level, or to
+/// also remove subdirectories it empties, use [`remove_old_files_with_options`].
+///
 /// # Arguments
 ///
 /// * `dir` - A string slice that holds the name of the directory.
@@ -239,40 +691,141 @@ fn get_size_by_path(path: &Path) -> std::io::Result<u64> {
 /// let removed_files = remove_old_files("/path/to/directory", 10000);
 /// ```
 pub fn remove_old_files(dir: &str, keep: u64) -> std::io::Result<Vec<String>> {
-    let mut dir_size = get_size(dir).unwrap();
-    if dir_size < keep {
-        return Ok(vec![]);
+    remove_old_files_by(dir, keep, DeletionOrder::OldestModified)
+}
+
+/// Like [`remove_old_files`], but evicts files in `order` instead of oldest modified first.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::DeletionOrder;
+///
+/// // Evict the cache entries that were used least recently.
+/// let removed =
+///     bbq::remove_old_files_by("/var/cache/app", 1 << 30, DeletionOrder::LeastRecentlyAccessed).unwrap();
+/// ```
+pub fn remove_old_files_by(
+    dir: &str,
+    keep: u64,
+    order: DeletionOrder,
+) -> std::io::Result<Vec<String>> {
+    let options = RemoveOldFilesOptions {
+        order,
+        ..Default::default()
+    };
+    remove_old_files_with_options(dir, keep, &options)
+}
+
+/// Options for [`remove_old_files_with_options`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveOldFilesOptions {
+    /// The order in which files are removed. Defaults to oldest modified first.
+    #[serde(default)]
+    pub order: DeletionOrder,
+    /// Whether files in subdirectories may be removed too, or only files directly in the
+    /// directory. Defaults to `true`. The whole tree counts towards the size either way.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+    /// Remove the subdirectories that a recursive run leaves empty.
+    #[serde(default)]
+    pub remove_empty_dirs: bool,
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+impl Default for RemoveOldFilesOptions {
+    fn default() -> Self {
+        RemoveOldFilesOptions {
+            order: DeletionOrder::default(),
+            recursive: default_recursive(),
+            remove_empty_dirs: false,
+        }
     }
-    let path = Path::new(dir);
-    let mut files = get_files(path)?;
-    files.retain(|path| {
-        fs::metadata(path)
-            .ok()
-            .map(|metadata| !metadata.file_type().is_symlink())
-            .unwrap_or(false)
-    });
-    files.sort_by_key(|path| {
-        fs::metadata(path)
-            .ok()
-            .and_then(|metadata| metadata.modified().ok())
-            .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
-    });
+}
+
+/// Like [`remove_old_files`], with the order, recursion and removal of emptied
+/// subdirectories set by `options`.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{remove_old_files_with_options, RemoveOldFilesOptions};
+///
+/// // Shrink the nested per-job log directories, dropping those left empty.
+/// let options = RemoveOldFilesOptions { remove_empty_dirs: true, ..Default::default() };
+/// let removed = remove_old_files_with_options("/var/log/jobs", 1 << 30, &options).unwrap();
+/// ```
+pub fn remove_old_files_with_options(
+    dir: &str,
+    keep: u64,
+    options: &RemoveOldFilesOptions,
+) -> std::io::Result<Vec<String>> {
+    let _span = crate::telemetry::enter("remove_old_files", Path::new(dir));
+    crate::disk::ensure_writable(dir)?;
+    let _lock = crate::lock::DirLock::acquire(dir)?;
+    let root = Path::new(dir);
+    let eligible = |path: &Path, _: &fs::Metadata| options.recursive || path.parent() == Some(root);
     let mut removed_files = Vec::new();
-    while dir_size > keep {
-        if let Some(file) = files.pop() {
-            if file.is_symlink() {
-                continue;
+    for (file, size) in select_old_files_matching(dir, keep, options.order, &eligible)? {
+        removed_files.push(file.to_string_lossy().into_owned());
+        match fs::remove_file(&file) {
+            Ok(()) => crate::telemetry::file_removed(&file, size),
+            Err(e) => crate::telemetry::file_failed(&file, &e.to_string()),
+        }
+        if options.recursive && options.remove_empty_dirs {
+            crate::cleanup::remove_emptied_parents(root, &file);
+        }
+    }
+    Ok(removed_files)
+}
+
+/// Selects the oldest files under `dir` that have to go for the directory to fit in `keep` bytes.
+///
+/// Files are returned oldest first together with their sizes. Symlinks are never selected.
+pub(crate) fn select_old_files(dir: &str, keep: u64) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    select_old_files_matching(dir, keep, DeletionOrder::OldestModified, &|_, _| true)
+}
+
+/// Like [`select_old_files`], but only files accepted by `eligible` may be selected.
+///
+/// The whole directory still counts towards its size. Files are selected in `order`.
+pub(crate) fn select_old_files_matching(
+    dir: &str,
+    keep: u64,
+    order: DeletionOrder,
+    eligible: &dyn Fn(&Path, &fs::Metadata) -> bool,
+) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    let mut dir_size = get_size(dir)?;
+    if dir_size <= keep {
+        return Ok(vec![]);
+    }
+    let mut files: Vec<(PathBuf, ManifestEntry)> = get_files(Path::new(dir))?
+        .into_iter()
+        // The locks of subdirectories being cleaned on their own are never selected.
+        .filter(|path| !crate::lock::is_lock_file(path))
+        .filter_map(|path| {
+            let metadata = fs::symlink_metadata(&path).ok()?;
+            if !metadata.is_file() || !eligible(&path, &metadata) {
+                return None;
             }
-            let metadata = fs::metadata(&file)?;
-            let size = metadata.len();
-            dir_size -= size;
-            removed_files.push(file.to_str().unwrap().to_string());
-            let _ = fs::remove_file(file.clone());
-        } else {
+            let entry = entry_from_metadata(&path, &metadata, None);
+            Some((path, entry))
+        })
+        .collect();
+    files.sort_by_key(|(_, entry)| entry.modified);
+    order.sort(&mut files, |(_, entry)| entry);
+    let mut selected = Vec::new();
+    for (path, entry) in files {
+        if dir_size <= keep {
             break;
         }
+        dir_size = dir_size.saturating_sub(entry.size);
+        selected.push((path, entry.size));
     }
-    Ok(removed_files)
+    Ok(selected)
 }
 
 /// Removes specified files from the system.
@@ -325,6 +878,9 @@ pub fn read_files(files: Vec<String>) -> std::io::Result<Vec<Vec<u8>>> {
 
 /// Retrieves all files from a specified directory, including subdirectories.
 ///
+/// Files still being written, `.part` files modified within
+/// [`PART_FILE_GRACE`](crate::PART_FILE_GRACE), are left out.
+///
 /// # Arguments
 ///
 /// * `dir` - A reference to a Path that holds the directory from which files should be retrieved.
@@ -348,6 +904,10 @@ pub fn get_files(dir: &Path) -> std::io::Result<Vec<std::path::PathBuf>> {
                 if path.is_symlink() {
                     continue;
                 }
+                let modified = path.metadata().and_then(|m| m.modified()).ok();
+                if is_fresh_part_file(&path, modified) {
+                    continue;
+                }
                 files.push(path);
             } else if path.is_dir() {
                 match get_files(&path) {
@@ -359,6 +919,27 @@ pub fn get_files(dir: &Path) -> std::io::Result<Vec<std::path::PathBuf>> {
     }
     Ok(files)
 }
+/// Like [`get_files`], with symlinks treated as `symlinks` says.
+///
+/// `SymlinkPolicy::Skip` leaves out links to files and doesn't descend into linked
+/// directories, `SymlinkPolicy::Report` also returns the links themselves, and
+/// `SymlinkPolicy::Follow` returns the files reached through links, visiting each linked
+/// directory once so a link back up the tree can't loop. Unreadable entries are skipped.
+pub fn get_files_with(dir: &Path, symlinks: SymlinkPolicy) -> std::io::Result<Vec<PathBuf>> {
+    let options = WalkOptions {
+        symlinks,
+        ..Default::default()
+    };
+    let mut files = Vec::new();
+    walk_with::<(), _>(&dir.to_string_lossy(), &options, |info| {
+        if info.file_type == "File" || info.file_type == "Symlink" {
+            files.push(PathBuf::from(&info.file_path));
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    Ok(files)
+}
+
 pub fn get_files_info_by_dir(dir: &str) -> std::io::Result<Vec<FileInfo>> {
     let path = Path::new(dir);
     let mut files_info = Vec::new();
@@ -367,32 +948,29 @@ pub fn get_files_info_by_dir(dir: &str) -> std::io::Result<Vec<FileInfo>> {
             let entry = entry?;
             let path = entry.path();
             let metadata = fs::metadata(&path)?;
-            let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
-            let file_type = if metadata.is_file() {
-                "File".to_string()
-            } else if metadata.is_dir() {
-                "Directory".to_string()
-            } else {
-                "Unknown".to_string()
-            };
-            let size = metadata.len();
-            let created_time = metadata.created()?;
-            let modified_time = metadata.modified()?;
-
-            files_info.push(FileInfo {
-                file_name,
-                file_type,
-                file_path: path.to_str().unwrap().to_string(),
-                created_time,
-                modified_time,
-                size,
-            });
+            files_info.push(file_info(&path, &metadata)?);
         }
     }
 
     Ok(files_info)
 }
 
+#[cfg(test)]
+mod tests_read_write {
+    use super::*;
+
+    #[test]
+    fn test_append_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log");
+        let file = file.to_str().unwrap();
+        append_text_file(file, "one\n").unwrap();
+        append_text_file(file, "two\n").unwrap();
+        append_file(file, b"three\n").unwrap();
+        assert_eq!(read_text_file(file).unwrap(), "one\ntwo\nthree\n");
+    }
+}
+
 #[cfg(test)]
 mod tests_dir_info {
     use super::*;
@@ -408,6 +986,121 @@ mod tests_dir_info {
         // print MB
         println!("Total size of {} is {} MB", dir, size / 1024 / 1024);
     }
+    #[test]
+    fn test_get_dir_info_recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("top"), "x").unwrap();
+        fs::write(dir.path().join("a/b/deep"), "x").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(get_dir_info(root).unwrap().len(), 2);
+        assert_eq!(get_dir_info_recursive(root, Some(2)).unwrap().len(), 3);
+        let all = get_dir_info_recursive(root, None).unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(all.iter().any(|info| info.file_name == "deep"));
+        assert!(all
+            .iter()
+            .all(|info| info.modified_time.is_some() && info.accessed_time.is_some()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_size_as() {
+        let dir = tempfile::tempdir().unwrap();
+        let sparse = fs::File::create(dir.path().join("sparse")).unwrap();
+        sparse.set_len(64 * 1024 * 1024).unwrap();
+        fs::write(dir.path().join("small"), "x").unwrap();
+        fs::hard_link(dir.path().join("small"), dir.path().join("link")).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            get_size_as(root, SizeKind::Apparent).unwrap(),
+            64 * 1024 * 1024 + 2
+        );
+        let allocated = get_size_as(root, SizeKind::Allocated).unwrap();
+        assert!(allocated < 1024 * 1024);
+        let small = fs::metadata(dir.path().join("small")).unwrap();
+        let sparse = fs::metadata(dir.path().join("sparse")).unwrap();
+        assert_eq!(allocated, allocated_size(&small) + allocated_size(&sparse));
+    }
+
+    #[test]
+    fn test_get_size_with_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a"), "12345").unwrap();
+        fs::write(dir.path().join("sub/b"), "123").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let cancel = CancelToken::new();
+        let mut calls = 0;
+        let size = get_size_with_progress(root, &cancel, |_| calls += 1).unwrap();
+        assert_eq!(
+            (size.bytes, size.entries_visited, size.cancelled),
+            (8, 3, false)
+        );
+        assert_eq!(calls, 3);
+
+        let stopper = cancel.clone();
+        let partial = get_size_with_progress(root, &cancel, |_| stopper.cancel()).unwrap();
+        assert!(partial.cancelled);
+        assert_eq!(partial.entries_visited, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_info_permissions() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, "x").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        let info = file_info(&path, &metadata).unwrap();
+        assert_eq!(info.mode, Some(0o640));
+        assert_eq!(info.permissions, "rw-r-----");
+        assert_eq!(info.uid, Some(metadata.uid()));
+        assert_eq!(
+            permissions_string(Some(0o4755), false),
+            "rwsr-xr-x".to_string()
+        );
+        assert_eq!(permissions_string(None, true), "r--r--r--");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_info_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second) = (dir.path().join("a"), dir.path().join("b"));
+        fs::write(&first, "x").unwrap();
+        fs::hard_link(&first, &second).unwrap();
+        fs::write(dir.path().join("c"), "x").unwrap();
+        let infos = get_dir_info_recursive(dir.path().to_str().unwrap(), None).unwrap();
+        let by_name = |name: &str| infos.iter().find(|info| info.file_name == name).unwrap();
+
+        assert_eq!(by_name("a").nlink, Some(2));
+        assert!(by_name("a").is_same_file(by_name("b")));
+        assert!(!by_name("a").is_same_file(by_name("c")));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_file_info_non_utf8_name() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(OsStr::from_bytes(b"bad\xffname")), "abc").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let infos = get_dir_info(root).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].file_name, "bad\u{fffd}name");
+        assert_eq!(get_size(root).unwrap(), 3);
+    }
+
     #[test]
     fn test_get_dir_info() {
         let dir = "/Users/mojih/Downloads";
@@ -422,6 +1115,79 @@ mod tests_dir_info {
 mod tests_remove_old_files {
     use super::*;
 
+    #[test]
+    fn test_remove_old_files_by_access() {
+        use std::fs::FileTimes;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let set_times = |name: &str, modified: u64, accessed: u64| {
+            let path = dir.path().join(name);
+            fs::write(&path, "x").unwrap();
+            let times = FileTimes::new()
+                .set_modified(now - Duration::from_secs(modified))
+                .set_accessed(now - Duration::from_secs(accessed));
+            fs::File::options()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .set_times(times)
+                .unwrap();
+        };
+        set_times("hot", 1000, 0);
+        set_times("cold", 500, 500);
+
+        let removed = remove_old_files_by(
+            dir.path().to_str().unwrap(),
+            1,
+            DeletionOrder::LeastRecentlyAccessed,
+        )
+        .unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].ends_with("cold"));
+        assert!(dir.path().join("hot").exists());
+    }
+
+    #[test]
+    fn test_remove_old_files_largest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small"), "x").unwrap();
+        fs::write(dir.path().join("large"), "x".repeat(100)).unwrap();
+        let root = dir.path().to_str().unwrap();
+        let removed = remove_old_files_by(root, 50, DeletionOrder::Largest).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].ends_with("large"));
+    }
+
+    #[test]
+    fn test_remove_old_files_with_options() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("job/logs")).unwrap();
+        fs::write(dir.path().join("job/logs/a.log"), "x".repeat(100)).unwrap();
+        fs::write(dir.path().join("top.log"), "x".repeat(10)).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let top_level = RemoveOldFilesOptions {
+            order: DeletionOrder::Largest,
+            recursive: false,
+            ..Default::default()
+        };
+        let removed = remove_old_files_with_options(root, 50, &top_level).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(removed[0].ends_with("top.log"));
+        assert!(dir.path().join("job/logs/a.log").exists());
+
+        let recursive = RemoveOldFilesOptions {
+            remove_empty_dirs: true,
+            ..Default::default()
+        };
+        let removed = remove_old_files_with_options(root, 50, &recursive).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!dir.path().join("job").exists());
+        assert!(dir.path().exists());
+    }
+
     #[test]
     fn test_remove_old_files() {
         let dir = "/Users/mojih/Downloads/test";
@@ -441,6 +1207,7 @@ mod tests_remove_old_files {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_unwrap)]
     fn test_get_size_by_path() {
         let path = "/Users/mojih/Downloads/test";
         let size = get_size(path);
@@ -0,0 +1,348 @@
+use crate::cleanup::CleanupReport;
+use crate::copy::move_across_devices;
+use crate::disk::ensure_writable;
+use crate::lock::DirLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// One line of a cleanup journal.
+///
+/// A journal is a file of JSON lines. A run first writes a `planned` record for every
+/// file it is about to remove, then a `done` or `skipped` record as it deals with each
+/// one, so after a crash the journal tells exactly what was and wasn't done.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalRecord {
+    /// The directory the run cleans. Written first, so a resumed run can lock it.
+    Started { dir: String },
+    /// A file the run is going to remove, or move to `dest`.
+    Planned {
+        path: String,
+        size: u64,
+        dest: Option<String>,
+    },
+    /// The file was removed, or moved to `dest`.
+    Done { path: String, dest: Option<String> },
+    /// The file was left in place.
+    Skipped { path: String },
+    /// A moved file was put back by [`rollback_cleanup`].
+    RolledBack { path: String },
+}
+
+pub(crate) struct JournalWriter {
+    file: fs::File,
+}
+
+impl JournalWriter {
+    pub(crate) fn create(journal: &str) -> io::Result<JournalWriter> {
+        Ok(JournalWriter {
+            file: fs::File::create(journal)?,
+        })
+    }
+
+    fn append(journal: &str) -> io::Result<JournalWriter> {
+        Ok(JournalWriter {
+            file: fs::File::options().append(true).open(journal)?,
+        })
+    }
+
+    // Each record is synced before the operation it describes goes ahead.
+    pub(crate) fn write(&mut self, record: &JournalRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record).map_err(io::Error::other)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+}
+
+/// Reads the records of a cleanup journal, in the order they were written.
+///
+/// A truncated last line, left by a crash while it was written, is ignored.
+pub fn read_journal(journal: &str) -> io::Result<Vec<JournalRecord>> {
+    let reader = io::BufReader::new(fs::File::open(journal)?);
+    let lines: Vec<String> = reader.lines().collect::<io::Result<_>>()?;
+    let mut records = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(_) if i + 1 == lines.len() => break,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+    Ok(records)
+}
+
+/// Finishes an interrupted cleanup from its journal.
+///
+/// Every planned file without a `done` or `skipped` record is removed, or moved to its
+/// quarantine path, exactly as the original run would have. Files that are gone already
+/// are reported as skipped. Like the original run, it holds the directory's [`DirLock`]
+/// while it works, and fails on a read-only filesystem.
+///
+/// # Arguments
+///
+/// * `journal` - A string slice that holds the name of the journal written by [`CleanupConfig::journal`](crate::CleanupConfig::journal)
+///
+/// # Returns
+///
+/// * `std::io::Result<CleanupReport>` - A Result containing what this call did.
+///
+/// # Example
+///
+/// ```no_run
+/// let report = bbq::resume_cleanup("/var/lib/app/cleanup.journal").unwrap();
+/// println!("finished {} removals", report.removed.len());
+/// ```
+pub fn resume_cleanup(journal: &str) -> io::Result<CleanupReport> {
+    let started = Instant::now();
+    let records = read_journal(journal)?;
+    let _lock = lock_cleaned_dir(&records)?;
+    let finished: HashSet<&str> = records
+        .iter()
+        .filter_map(|record| match record {
+            JournalRecord::Done { path, .. } | JournalRecord::Skipped { path } => {
+                Some(path.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    let mut writer = JournalWriter::append(journal)?;
+    let mut report = CleanupReport::default();
+    for record in &records {
+        let JournalRecord::Planned { path, size, dest } = record else {
+            continue;
+        };
+        if finished.contains(path.as_str()) {
+            continue;
+        }
+        report.stats.files_visited += 1;
+        let outcome = match dest {
+            Some(dest) => move_creating_parents(Path::new(path), Path::new(dest)),
+            None => fs::remove_file(path),
+        };
+        match outcome {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                writer.write(&JournalRecord::Skipped { path: path.clone() })?;
+                report.skipped.push((path.clone(), e.to_string()));
+                continue;
+            }
+            Err(e) => {
+                report.errors.push((path.clone(), e.to_string()));
+                continue;
+            }
+        }
+        writer.write(&JournalRecord::Done {
+            path: path.clone(),
+            dest: dest.clone(),
+        })?;
+        match dest {
+            Some(dest) => {
+                report.redirected.push((path.clone(), dest.clone()));
+                report.bytes_moved += size;
+            }
+            None => {
+                report.removed.push(path.clone());
+                report.bytes_freed += size;
+            }
+        }
+    }
+    report.stats.duration = started.elapsed();
+    Ok(report)
+}
+
+/// Undoes a cleanup that moved its files instead of deleting them, e.g. with
+/// [`CleanupConfig::quarantine`](crate::CleanupConfig::quarantine), by moving every file
+/// back to where it was.
+///
+/// If the journal records any file as deleted permanently, nothing is rolled back and an
+/// `ErrorKind::InvalidInput` error is returned. If a new file has appeared where a moved
+/// one would go back, nothing is rolled back either and an `ErrorKind::AlreadyExists`
+/// error is returned, so the newer file is never overwritten.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the paths that were restored, most recently moved first.
+pub fn rollback_cleanup(journal: &str) -> io::Result<Vec<String>> {
+    let records = read_journal(journal)?;
+    let rolled_back: HashSet<&str> = records
+        .iter()
+        .filter_map(|record| match record {
+            JournalRecord::RolledBack { path } => Some(path.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut moves = Vec::new();
+    for record in &records {
+        match record {
+            JournalRecord::Done { path, .. } if rolled_back.contains(path.as_str()) => {}
+            JournalRecord::Done {
+                path,
+                dest: Some(dest),
+            } => moves.push((path, dest)),
+            JournalRecord::Done { path, dest: None } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} was deleted permanently and cannot be restored", path),
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let _lock = lock_cleaned_dir(&records)?;
+    let occupied = |path: &str| fs::symlink_metadata(path).is_ok();
+    if let Some((path, _)) = moves.iter().find(|(path, _)| occupied(path)) {
+        return Err(already_exists(path));
+    }
+    let mut writer = JournalWriter::append(journal)?;
+    let mut restored = Vec::new();
+    for (path, dest) in moves.into_iter().rev() {
+        if occupied(path) {
+            return Err(already_exists(path));
+        }
+        move_creating_parents(Path::new(dest), Path::new(path))?;
+        writer.write(&JournalRecord::RolledBack { path: path.clone() })?;
+        restored.push(path.clone());
+    }
+    Ok(restored)
+}
+
+fn already_exists(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} exists again and would be overwritten", path),
+    )
+}
+
+// Takes the lock on the directory a journal's run cleaned, as the run itself did.
+// Journals written before runs recorded their directory can't be locked.
+fn lock_cleaned_dir(records: &[JournalRecord]) -> io::Result<Option<DirLock>> {
+    let dir = records.iter().find_map(|record| match record {
+        JournalRecord::Started { dir } => Some(dir),
+        _ => None,
+    });
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    ensure_writable(dir)?;
+    DirLock::acquire(dir).map(Some)
+}
+
+// Moves `from` to `to`, creating the parents of `to` and copying across filesystems
+// when the file can't simply be renamed.
+pub(crate) fn move_creating_parents(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            move_across_devices(&from.to_string_lossy(), &to.to_string_lossy())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cleanup::CleanupConfig;
+    use crate::retention::RetentionPolicy;
+
+    #[test]
+    fn test_rollback_quarantined_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir_all(data.join("sub")).unwrap();
+        fs::write(data.join("a.log"), "a").unwrap();
+        fs::write(data.join("sub/b.log"), "b").unwrap();
+        let journal = dir.path().join("cleanup.journal");
+        let journal = journal.to_str().unwrap();
+        let quarantine = dir.path().join("quarantine");
+
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0))
+            .quarantine(quarantine.to_str().unwrap())
+            .journal(journal);
+        let report = config.run(data.to_str().unwrap()).unwrap();
+        assert_eq!(report.redirected.len(), 2);
+        let records = read_journal(journal).unwrap();
+        assert_eq!(records.len(), 5);
+        assert!(resume_cleanup(journal).unwrap().redirected.is_empty());
+
+        let restored = rollback_cleanup(journal).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(fs::read_to_string(data.join("a.log")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(data.join("sub/b.log")).unwrap(), "b");
+        assert!(rollback_cleanup(journal).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rollback_keeps_newer_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("a.log"), "old").unwrap();
+        let journal = dir.path().join("cleanup.journal");
+        let journal = journal.to_str().unwrap();
+        let config = CleanupConfig::new(RetentionPolicy::new().max_files(0))
+            .quarantine(dir.path().join("quarantine").to_str().unwrap())
+            .journal(journal);
+        config.run(data.to_str().unwrap()).unwrap();
+        fs::write(data.join("a.log"), "new").unwrap();
+
+        let lock = DirLock::acquire(data.to_str().unwrap()).unwrap();
+        assert!(crate::is_already_running(
+            &resume_cleanup(journal).unwrap_err()
+        ));
+        drop(lock);
+        let err = rollback_cleanup(journal).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(data.join("a.log")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_resume_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.log");
+        fs::write(&file, "a").unwrap();
+        let journal = dir.path().join("cleanup.journal");
+        let journal = journal.to_str().unwrap();
+        // A run that crashed right after writing its plan.
+        let mut writer = JournalWriter::create(journal).unwrap();
+        for path in [&file, &dir.path().join("vanished.log")] {
+            writer
+                .write(&JournalRecord::Planned {
+                    path: path.to_string_lossy().into_owned(),
+                    size: 1,
+                    dest: None,
+                })
+                .unwrap();
+        }
+
+        let report = resume_cleanup(journal).unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(!file.exists());
+        assert!(resume_cleanup(journal).unwrap().removed.is_empty());
+    }
+
+    #[test]
+    fn test_rollback_refuses_permanent_deletions() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = dir.path().join("cleanup.journal");
+        let journal = journal.to_str().unwrap();
+        let mut writer = JournalWriter::create(journal).unwrap();
+        writer
+            .write(&JournalRecord::Done {
+                path: "/tmp/gone".to_string(),
+                dest: None,
+            })
+            .unwrap();
+        let err = rollback_cleanup(journal).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
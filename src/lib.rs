@@ -1,3 +1,105 @@
+pub mod archive;
+pub mod blob;
+pub mod catalog;
+pub mod channel;
+pub mod chunk;
+pub mod cleanup;
+#[cfg(feature = "mime")]
+pub mod content_type;
+pub mod copy;
+pub mod daemon;
+pub mod diff;
+pub mod disk;
+pub mod export;
+pub mod extract;
+pub mod filter;
+pub mod find;
+pub mod hash;
+#[cfg(feature = "ignore")]
+pub mod ignore_files;
 pub mod info;
+pub mod journal;
+pub mod lock;
+pub mod managed;
+pub mod manifest;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod part;
+pub mod partition;
+pub mod pipeline;
+pub mod progress;
+pub mod query;
+pub mod read;
+pub mod recycle;
+pub mod remote;
+pub mod remove;
+pub mod report;
+pub mod retention;
+pub mod rotate;
+pub mod schedule;
+pub mod sequence;
+pub mod shred;
+pub mod size_cache;
+pub mod spool;
+pub mod stats;
+pub mod sync;
+mod telemetry;
+pub mod tenant;
+pub mod throttle;
+pub mod tree;
+pub mod verify;
+pub mod wait;
+pub mod walk;
 
+pub use archive::*;
+pub use blob::*;
+pub use catalog::*;
+pub use channel::*;
+pub use chunk::*;
+pub use cleanup::*;
+#[cfg(feature = "mime")]
+pub use content_type::*;
+pub use copy::*;
+pub use daemon::*;
+pub use diff::*;
+pub use disk::*;
+pub use export::*;
+pub use extract::*;
+pub use filter::*;
+pub use find::*;
+pub use hash::*;
+#[cfg(feature = "ignore")]
+pub use ignore_files::*;
 pub use info::*;
+pub use journal::*;
+pub use lock::*;
+pub use managed::*;
+pub use manifest::*;
+#[cfg(feature = "rayon")]
+pub use parallel::*;
+pub use part::*;
+pub use partition::*;
+pub use pipeline::*;
+pub use progress::*;
+pub use query::*;
+pub use read::*;
+#[cfg(feature = "trash")]
+pub use recycle::*;
+pub use remote::*;
+pub use remove::*;
+pub use report::*;
+pub use retention::*;
+pub use rotate::*;
+pub use schedule::*;
+pub use sequence::*;
+pub use shred::*;
+pub use size_cache::*;
+pub use spool::*;
+pub use stats::*;
+pub use sync::*;
+pub use tenant::*;
+pub use throttle::*;
+pub use tree::*;
+pub use verify::*;
+pub use wait::*;
+pub use walk::*;
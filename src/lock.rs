@@ -0,0 +1,158 @@
+use fs2::FileExt;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// The suffix of the lock files [`DirLock`] keeps. Cleanups never select files ending in it.
+pub const LOCK_FILE_NAME: &str = ".bbq.lock";
+
+// Returns true if `path` names a lock file kept by a [`DirLock`].
+pub(crate) fn is_lock_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(LOCK_FILE_NAME))
+}
+
+// Where the lock on `dir` lives: `.<name>.bbq.lock` next to it, so it never shows up
+// among the files being managed.
+fn lock_path(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?;
+    let mut lock = std::ffi::OsString::from(".");
+    lock.push(name);
+    lock.push(LOCK_FILE_NAME);
+    Some(dir.parent()?.join(lock))
+}
+
+/// The error inside the `io::Error` returned when another process holds a [`DirLock`].
+///
+/// The `io::Error` has kind `ErrorKind::WouldBlock`; use [`is_already_running`] to tell it
+/// apart from other failures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyRunning {
+    pub dir: PathBuf,
+    /// The process holding the lock, if it recorded itself.
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "another cleanup of {} is running", self.dir.display())?;
+        if let Some(pid) = self.pid {
+            write!(f, " (pid {})", pid)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
+
+/// Returns true if `error` says another process holds the directory's [`DirLock`].
+pub fn is_already_running(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<AlreadyRunning>())
+}
+
+/// An advisory lock on a directory, so only one process cleans it at a time.
+///
+/// The lock is an OS file lock (`flock` / `LockFileEx`) on a `.<name>.bbq.lock` file next
+/// to the directory, so it is released when the holder exits or crashes and a lock file
+/// left behind is never mistaken for a live one. The file itself stays in place. Only
+/// when the parent directory can't hold it, such as for `/` or a mount point in a
+/// read-only parent, is the lock file kept inside the directory as `.bbq.lock`. The lock
+/// is released when the `DirLock` is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// match bbq::DirLock::acquire("/var/log/app") {
+///     Ok(_lock) => { /* clean up */ }
+///     Err(e) if bbq::is_already_running(&e) => println!("{}", e),
+///     Err(e) => panic!("{}", e),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DirLock {
+    file: fs::File,
+}
+
+impl DirLock {
+    /// Takes the lock on `dir`, failing straight away if another process holds it.
+    pub fn acquire(dir: &str) -> io::Result<DirLock> {
+        let canonical = fs::canonicalize(dir)?;
+        let open = |path: &Path| {
+            fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        };
+        let beside = lock_path(&canonical).map(|path| open(&path));
+        let mut file = match beside {
+            Some(Ok(file)) => file,
+            Some(Err(e))
+                if !matches!(
+                    e.kind(),
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+                ) =>
+            {
+                return Err(e)
+            }
+            _ => open(&canonical.join(LOCK_FILE_NAME))?,
+        };
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.raw_os_error() != fs2::lock_contended_error().raw_os_error() {
+                return Err(e);
+            }
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                AlreadyRunning {
+                    dir: PathBuf::from(dir),
+                    pid: holder.trim().parse().ok(),
+                },
+            ));
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(DirLock { file })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dir_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let lock = DirLock::acquire(root).unwrap();
+        let err = DirLock::acquire(root).unwrap_err();
+        assert!(is_already_running(&err));
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+        drop(lock);
+        // A lock file left behind doesn't block the next run.
+        DirLock::acquire(root).unwrap();
+    }
+
+    #[test]
+    fn test_dir_lock_stays_out_of_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        let _lock = DirLock::acquire(data.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read_dir(&data).unwrap().count(), 0);
+        let beside = dir.path().join(".data.bbq.lock");
+        assert!(is_lock_file(&beside) && beside.exists());
+    }
+}
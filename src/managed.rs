@@ -0,0 +1,202 @@
+use crate::cleanup::{keep_latest_n, remove_files_older_than, CleanupConfig, CleanupReport};
+use crate::info::{
+    remove_old_files, remove_old_files_by, remove_old_files_with_options, RemoveOldFilesOptions,
+};
+use crate::retention::DeletionOrder;
+use crate::retention::RetentionPolicy;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Directories no cleanup should ever run on directly. Their subdirectories are fine.
+#[cfg(unix)]
+const PROTECTED_DIRS: &[&str] = &[
+    "/",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/proc",
+    "/root",
+    "/sbin",
+    "/srv",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/var",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Users",
+];
+#[cfg(windows)]
+const PROTECTED_DIRS: &[&str] = &[
+    r"C:\",
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\Users",
+];
+#[cfg(not(any(unix, windows)))]
+const PROTECTED_DIRS: &[&str] = &[];
+
+/// Returns true if `path` is a directory destructive operations must not run on: a
+/// filesystem root, a top-level system directory such as `/etc` or `/var`, or the current
+/// user's home directory.
+///
+/// Only the directories themselves are protected; `/var/log/app` is fine. Paths are
+/// compared after resolving symlinks, where possible.
+pub fn is_protected_path(path: &Path) -> bool {
+    let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if resolved.parent().is_none() {
+        return true;
+    }
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    PROTECTED_DIRS.iter().any(|dir| resolved == Path::new(dir))
+        || home.is_some_and(|home| fs::canonicalize(&home).unwrap_or(home) == resolved)
+}
+
+/// A directory that has been checked to be safe for destructive operations.
+///
+/// A `ManagedDir` can only be made by [`ManagedDir::new`], which makes sure the path
+/// exists, is a directory, and is not [protected](is_protected_path). Code that takes a
+/// `ManagedDir` instead of a `&str` states in its signature that the path was validated,
+/// and the cleanup methods on it are the same as the free functions.
+///
+/// # Example
+///
+/// ```no_run
+/// let logs = bbq::ManagedDir::new("/var/log/app").unwrap();
+/// let removed = logs.remove_old_files(100 * 1024 * 1024).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ManagedDir {
+    path: String,
+}
+
+impl ManagedDir {
+    /// Validates `dir` and resolves it to an absolute path.
+    ///
+    /// A missing path returns `ErrorKind::NotFound`, anything other than a directory
+    /// `ErrorKind::InvalidInput`, and a protected path `ErrorKind::PermissionDenied`.
+    pub fn new(dir: &str) -> io::Result<ManagedDir> {
+        let resolved = fs::canonicalize(dir)?;
+        if !resolved.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", dir),
+            ));
+        }
+        if is_protected_path(&resolved) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "refusing to manage protected directory {}",
+                    resolved.display()
+                ),
+            ));
+        }
+        let path = resolved.into_os_string().into_string().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not valid UTF-8", dir),
+            )
+        })?;
+        Ok(ManagedDir { path })
+    }
+
+    /// The absolute path of the directory.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    pub fn path(&self) -> &Path {
+        Path::new(&self.path)
+    }
+
+    /// See [`remove_old_files`].
+    pub fn remove_old_files(&self, keep: u64) -> io::Result<Vec<String>> {
+        remove_old_files(&self.path, keep)
+    }
+
+    /// See [`remove_old_files_by`].
+    pub fn remove_old_files_by(&self, keep: u64, order: DeletionOrder) -> io::Result<Vec<String>> {
+        remove_old_files_by(&self.path, keep, order)
+    }
+
+    /// See [`remove_old_files_with_options`].
+    pub fn remove_old_files_with_options(
+        &self,
+        keep: u64,
+        options: &RemoveOldFilesOptions,
+    ) -> io::Result<Vec<String>> {
+        remove_old_files_with_options(&self.path, keep, options)
+    }
+
+    /// See [`remove_files_older_than`].
+    pub fn remove_files_older_than(&self, max_age: Duration) -> io::Result<Vec<String>> {
+        remove_files_older_than(&self.path, max_age)
+    }
+
+    /// See [`keep_latest_n`].
+    pub fn keep_latest_n(&self, n: usize) -> io::Result<Vec<String>> {
+        keep_latest_n(&self.path, n)
+    }
+
+    /// Applies `policy`; see [`RetentionPolicy::apply`].
+    pub fn apply(&self, policy: &RetentionPolicy) -> io::Result<Vec<String>> {
+        policy.apply(&self.path)
+    }
+
+    /// Runs `config`; see [`CleanupConfig::run`].
+    pub fn clean(&self, config: &CleanupConfig) -> io::Result<CleanupReport> {
+        config.run(&self.path)
+    }
+}
+
+impl AsRef<Path> for ManagedDir {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+impl fmt::Display for ManagedDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_managed_dir_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "x").unwrap();
+
+        let err = ManagedDir::new(file.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let managed = ManagedDir::new(dir.path().to_str().unwrap()).unwrap();
+        assert!(managed.path().is_absolute());
+        assert_eq!(managed.keep_latest_n(0).unwrap().len(), 1);
+        let err = ManagedDir::new(dir.path().join("missing").to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        #[cfg(unix)]
+        assert_eq!(
+            ManagedDir::new("/").unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        #[cfg(unix)]
+        assert!(is_protected_path(Path::new("/etc/")));
+    }
+}
@@ -0,0 +1,523 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// The type of a [`ManifestEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// One file, directory or symlink recorded in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest root, with `/` separators.
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    #[serde(default)]
+    pub accessed: Option<SystemTime>,
+    /// The last status change time (ctime), where the platform has one.
+    #[serde(default)]
+    pub changed: Option<SystemTime>,
+    /// The creation (birth) time, where the filesystem records one.
+    #[serde(default)]
+    pub created: Option<SystemTime>,
+    /// Unix permission bits, when available.
+    pub mode: Option<u32>,
+    /// Owning user and group ids, when available.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// The link target, for symlinks.
+    pub target: Option<String>,
+}
+
+/// A snapshot of a directory tree's structure and metadata.
+///
+/// Manifests are serialized as JSON, so they can be kept next to backups and used
+/// later to restore structure or detect changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The directory the manifest was taken of.
+    pub root: String,
+    pub created: SystemTime,
+    /// Entries sorted by path; parents always come before their children.
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Records every file, directory and symlink under `dir`. Symlinks are not followed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// let manifest = bbq::Manifest::scan("/srv/data").unwrap();
+    /// manifest.save("/backups/data.manifest.json").unwrap();
+    /// ```
+    pub fn scan(dir: &str) -> io::Result<Manifest> {
+        let mut entries = Vec::new();
+        scan_into(Path::new(dir), Path::new(""), &mut entries)?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest {
+            root: dir.to_string(),
+            created: SystemTime::now(),
+            entries,
+        })
+    }
+
+    /// Records `dir` like [`Manifest::scan`], but carries on past entries it can't read.
+    ///
+    /// Entries that vanish while the tree is scanned, removed by a writer or a log
+    /// rotation, are left out. Other entries that can't be read, such as subdirectories
+    /// without read permission, are left out too and returned with the error as
+    /// `(path, error)` pairs. Only a `dir` that can't be listed is an error.
+    pub fn scan_lenient(dir: &str) -> io::Result<(Manifest, Vec<(String, String)>)> {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        let listing = fs::read_dir(dir)?;
+        scan_listing_lenient(
+            listing,
+            Path::new(dir),
+            Path::new(""),
+            &mut entries,
+            &mut errors,
+        );
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let manifest = Manifest {
+            root: dir.to_string(),
+            created: SystemTime::now(),
+            entries,
+        };
+        Ok((manifest, errors))
+    }
+
+    /// Records `dir` like [`Manifest::scan`], but reuses what `previous` recorded for every
+    /// directory whose modification time hasn't changed, without listing it or looking
+    /// at its files.
+    ///
+    /// A directory's modification time changes when entries are added, removed or
+    /// renamed in it, but not when a file in it is rewritten in place, so files that grew
+    /// or shrank in an otherwise unchanged directory keep their previous size. Take a
+    /// full [`Manifest::scan`] from time to time to catch those.
+    pub fn rescan(dir: &str, previous: &Manifest) -> io::Result<Manifest> {
+        if previous.root != dir {
+            return Manifest::scan(dir);
+        }
+        let mut entries = Vec::new();
+        rescan_into(Path::new(dir), Path::new(""), previous, &mut entries)?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest {
+            root: dir.to_string(),
+            created: SystemTime::now(),
+            entries,
+        })
+    }
+
+    /// Writes the manifest to `file` as JSON.
+    pub fn save(&self, file: &str) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        fs::write(file, json)
+    }
+
+    /// Reads a manifest previously written with [`Manifest::save`].
+    pub fn load(file: &str) -> io::Result<Manifest> {
+        let json = fs::read(file)?;
+        serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The total size of the files in the manifest, in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::File)
+            .map(|e| e.size)
+            .sum()
+    }
+
+    /// Looks up the entry for a relative path.
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|e| e.path.as_str().cmp(path))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+}
+
+pub(crate) fn relative_key(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn unix_attributes(metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        Some(metadata.mode() & 0o7777),
+        Some(metadata.uid()),
+        Some(metadata.gid()),
+    )
+}
+
+#[cfg(not(unix))]
+fn unix_attributes(_metadata: &fs::Metadata) -> (Option<u32>, Option<u32>, Option<u32>) {
+    (None, None, None)
+}
+
+fn scan_into(dir: &Path, relative: &Path, entries: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        let entry = read_entry(&path, &relative)?;
+        let is_dir = entry.kind == EntryKind::Dir;
+        entries.push(entry);
+        if is_dir {
+            scan_into(&path, &relative, entries)?;
+        }
+    }
+    Ok(())
+}
+
+// Like `scan_into`, for an already opened `listing` of `dir`. Vanished entries are
+// dropped and the other failures pushed to `errors`.
+fn scan_listing_lenient(
+    listing: fs::ReadDir,
+    dir: &Path,
+    relative: &Path,
+    entries: &mut Vec<ManifestEntry>,
+    errors: &mut Vec<(String, String)>,
+) {
+    for entry in listing {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                record_scan_error(errors, dir, e);
+                continue;
+            }
+        };
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        let entry = match read_entry(&path, &relative) {
+            Ok(entry) => entry,
+            Err(e) => {
+                record_scan_error(errors, &path, e);
+                continue;
+            }
+        };
+        let is_dir = entry.kind == EntryKind::Dir;
+        entries.push(entry);
+        if is_dir {
+            match fs::read_dir(&path) {
+                Ok(listing) => scan_listing_lenient(listing, &path, &relative, entries, errors),
+                Err(e) => record_scan_error(errors, &path, e),
+            }
+        }
+    }
+}
+
+fn record_scan_error(errors: &mut Vec<(String, String)>, path: &Path, e: io::Error) {
+    if e.kind() != io::ErrorKind::NotFound {
+        errors.push((path.to_string_lossy().into_owned(), e.to_string()));
+    }
+}
+
+fn read_entry(path: &Path, relative: &Path) -> io::Result<ManifestEntry> {
+    let metadata = fs::symlink_metadata(path)?;
+    let target = if metadata.file_type().is_symlink() {
+        Some(fs::read_link(path)?.to_string_lossy().into_owned())
+    } else {
+        None
+    };
+    Ok(entry_from_metadata(relative, &metadata, target))
+}
+
+// The entry for `metadata`, taken with `symlink_metadata`; `target` is the link target
+// of a symlink.
+pub(crate) fn entry_from_metadata(
+    relative: &Path,
+    metadata: &fs::Metadata,
+    target: Option<String>,
+) -> ManifestEntry {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_symlink() {
+        EntryKind::Symlink
+    } else if file_type.is_dir() {
+        EntryKind::Dir
+    } else {
+        EntryKind::File
+    };
+    let (mode, uid, gid) = unix_attributes(metadata);
+    ManifestEntry {
+        path: relative_key(relative),
+        kind,
+        size: if kind == EntryKind::File {
+            metadata.len()
+        } else {
+            0
+        },
+        modified: metadata.modified().ok(),
+        accessed: metadata.accessed().ok(),
+        changed: changed_time(metadata),
+        created: metadata.created().ok(),
+        mode,
+        uid,
+        gid,
+        target,
+    }
+}
+
+#[cfg(unix)]
+fn changed_time(metadata: &fs::Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    let secs = u64::try_from(metadata.ctime()).ok()?;
+    let nanos = u32::try_from(metadata.ctime_nsec()).ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::new(secs, nanos))
+}
+
+#[cfg(not(unix))]
+fn changed_time(_: &fs::Metadata) -> Option<SystemTime> {
+    None
+}
+
+// Like `scan_into`, but takes the contents of directories whose modification time
+// matches `previous` from there instead of listing them again.
+fn rescan_into(
+    dir: &Path,
+    relative: &Path,
+    previous: &Manifest,
+    entries: &mut Vec<ManifestEntry>,
+) -> io::Result<()> {
+    let children: Vec<ManifestEntry> = match previous_children(previous, relative, dir)? {
+        Some(children) => children,
+        None => {
+            let mut children = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                children.push(read_entry(
+                    &entry.path(),
+                    &relative.join(entry.file_name()),
+                )?);
+            }
+            children
+        }
+    };
+    for child in children {
+        let is_dir = child.kind == EntryKind::Dir;
+        let name = Path::new(&child.path)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_default();
+        entries.push(child);
+        if is_dir {
+            rescan_into(&dir.join(&name), &relative.join(&name), previous, entries)?;
+        }
+    }
+    Ok(())
+}
+
+// The direct children of `relative` recorded in `previous`, if the directory hasn't
+// changed since. Child directories are stat'ed again, since their own contents are
+// checked separately.
+fn previous_children(
+    previous: &Manifest,
+    relative: &Path,
+    dir: &Path,
+) -> io::Result<Option<Vec<ManifestEntry>>> {
+    let key = relative_key(relative);
+    if key.is_empty() {
+        return Ok(None);
+    }
+    let unchanged = previous.get(&key).is_some_and(|entry| {
+        entry.kind == EntryKind::Dir
+            && entry.modified.is_some()
+            && fs::symlink_metadata(dir)
+                .ok()
+                .and_then(|m| m.modified().ok())
+                == entry.modified
+    });
+    if !unchanged {
+        return Ok(None);
+    }
+    let prefix = format!("{}/", key);
+    let start = previous
+        .entries
+        .partition_point(|e| e.path.as_str() < prefix.as_str());
+    let mut children = Vec::new();
+    for entry in previous.entries[start..]
+        .iter()
+        .take_while(|e| e.path.starts_with(&prefix))
+        .filter(|e| !e.path[prefix.len()..].contains('/'))
+    {
+        if entry.kind == EntryKind::Dir {
+            let name = &entry.path[prefix.len()..];
+            children.push(read_entry(&dir.join(name), &relative.join(name))?);
+        } else {
+            children.push(entry.clone());
+        }
+    }
+    Ok(Some(children))
+}
+
+fn checked_join(dir: &Path, relative: &str) -> io::Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsafe manifest path: {}", relative),
+                ))
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Recreates the directories and symlinks recorded in a manifest that are missing from `dir`.
+///
+/// This repairs the skeleton of a tree after an overly aggressive cleanup, even when the
+/// file contents themselves have to come from backups. Existing entries are left alone,
+/// directory permissions are restored where recorded, and file entries are skipped.
+///
+/// # Arguments
+///
+/// * `manifest` - The snapshot describing the expected structure.
+/// * `dir` - A string slice that holds the directory to restore into. It is created if needed.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the paths of the directories and symlinks that were created.
+///
+/// # Example
+///
+/// ```no_run
+/// let manifest = bbq::Manifest::load("/backups/data.manifest.json").unwrap();
+/// let created = bbq::restore_structure(&manifest, "/srv/data").unwrap();
+/// ```
+pub fn restore_structure(manifest: &Manifest, dir: &str) -> io::Result<Vec<String>> {
+    let root = Path::new(dir);
+    fs::create_dir_all(root)?;
+    let mut created = Vec::new();
+    for entry in &manifest.entries {
+        let path = checked_join(root, &entry.path)?;
+        if fs::symlink_metadata(&path).is_ok() {
+            continue;
+        }
+        match entry.kind {
+            EntryKind::Dir => {
+                fs::create_dir_all(&path)?;
+                #[cfg(unix)]
+                if let Some(mode) = entry.mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+                }
+            }
+            EntryKind::Symlink => {
+                let target = match &entry.target {
+                    Some(target) => target,
+                    None => continue,
+                };
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(target, &path)?;
+                #[cfg(windows)]
+                std::os::windows::fs::symlink_file(target, &path)?;
+            }
+            EntryKind::File => continue,
+        }
+        created.push(path.to_string_lossy().into_owned());
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_structure() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        fs::create_dir_all(data.join("logs/2024")).unwrap();
+        fs::write(data.join("logs/2024/a.log"), "a").unwrap();
+        std::os::unix::fs::symlink("logs/2024", data.join("current")).unwrap();
+
+        let manifest = Manifest::scan(data.to_str().unwrap()).unwrap();
+        let saved = dir.path().join("manifest.json");
+        manifest.save(saved.to_str().unwrap()).unwrap();
+        let manifest = Manifest::load(saved.to_str().unwrap()).unwrap();
+        assert_eq!(manifest.get("logs/2024/a.log").unwrap().size, 1);
+
+        fs::remove_dir_all(&data).unwrap();
+        let created = restore_structure(&manifest, data.to_str().unwrap()).unwrap();
+        assert_eq!(created.len(), 3);
+        assert!(data.join("logs/2024").is_dir());
+        assert_eq!(
+            fs::read_link(data.join("current")).unwrap(),
+            Path::new("logs/2024")
+        );
+        assert!(!data.join("logs/2024/a.log").exists());
+    }
+
+    #[test]
+    fn test_scan_lenient_leaves_out_vanished_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("rotated")).unwrap();
+        fs::write(dir.path().join("rotated/app.log.1"), "a").unwrap();
+        fs::write(dir.path().join("app.log"), "b").unwrap();
+
+        // The directory disappears between being listed and being read.
+        let listing = fs::read_dir(dir.path()).unwrap();
+        fs::remove_dir_all(dir.path().join("rotated")).unwrap();
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        scan_listing_lenient(
+            listing,
+            dir.path(),
+            Path::new(""),
+            &mut entries,
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "app.log");
+
+        let (manifest, errors) = Manifest::scan_lenient(dir.path().to_str().unwrap()).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(manifest.total_size(), 1);
+        assert!(Manifest::scan_lenient(dir.path().join("gone").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rescan_reuses_unchanged_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("static/deep")).unwrap();
+        fs::create_dir_all(dir.path().join("busy")).unwrap();
+        fs::write(dir.path().join("static/deep/a"), "aaaa").unwrap();
+        fs::write(dir.path().join("busy/b"), "bb").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let previous = Manifest::scan(root).unwrap();
+
+        // Rewritten in place: the directory's mtime stays, so the old size is reused.
+        fs::write(dir.path().join("static/deep/a"), "a").unwrap();
+        fs::write(dir.path().join("busy/c"), "ccc").unwrap();
+        let manifest = Manifest::rescan(root, &previous).unwrap();
+        assert_eq!(manifest.get("static/deep/a").unwrap().size, 4);
+        assert_eq!(manifest.get("busy/c").unwrap().size, 3);
+        assert_eq!(manifest.entries.len(), previous.entries.len() + 1);
+        assert_eq!(Manifest::scan(root).unwrap().total_size(), 6);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::info::{file_info, FileInfo};
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Like [`get_files`](crate::get_files), but lists subdirectories in parallel on the rayon
+/// thread pool.
+///
+/// Each subdirectory becomes its own task, so on network filesystems, where every
+/// directory listing is a round trip, the scan runs as many listings at once as the pool
+/// has threads. The files come back in the same order as `get_files` returns them.
+/// Symlinks are skipped and unreadable subdirectories left out, as with `get_files`.
+///
+/// # Example
+///
+/// ```no_run
+/// let files = bbq::par_get_files(std::path::Path::new("/mnt/nfs/projects")).unwrap();
+/// ```
+pub fn par_get_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match read_entries(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let nested: Vec<Vec<PathBuf>> = entries
+        .into_par_iter()
+        .map(|path| {
+            if path.is_symlink() {
+                Ok(Vec::new())
+            } else if path.is_file() {
+                Ok(vec![path])
+            } else if path.is_dir() {
+                par_get_files(&path)
+            } else {
+                Ok(Vec::new())
+            }
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(nested.into_iter().flatten().collect())
+}
+
+/// Like [`get_size`](crate::get_size), but sizes subdirectories in parallel on the rayon
+/// thread pool. Symlinks are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// let size = bbq::par_get_size("/mnt/nfs/projects").unwrap();
+/// ```
+pub fn par_get_size(dir: &str) -> io::Result<u64> {
+    par_size(Path::new(dir))
+}
+
+fn par_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    read_entries(path)?
+        .into_par_iter()
+        .filter(|path| !path.is_symlink())
+        .map(|path| par_size(&path))
+        .try_reduce(|| 0, |a, b| Ok(a + b))
+}
+
+/// Like [`get_dir_info_recursive`](crate::get_dir_info_recursive), but reads the entries
+/// and lists subdirectories in parallel on the rayon thread pool. The entries come back in
+/// the same order.
+///
+/// # Example
+///
+/// ```no_run
+/// let tree = bbq::par_get_dir_info("/mnt/nfs/projects", None).unwrap();
+/// ```
+pub fn par_get_dir_info(dir: &str, max_depth: Option<usize>) -> io::Result<Vec<FileInfo>> {
+    par_dir_info(Path::new(dir), 1, max_depth)
+}
+
+fn par_dir_info(dir: &Path, depth: usize, max_depth: Option<usize>) -> io::Result<Vec<FileInfo>> {
+    if max_depth.is_some_and(|max| depth > max) {
+        return Ok(Vec::new());
+    }
+    let entries = match read_entries(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let nested: Vec<Vec<FileInfo>> = entries
+        .into_par_iter()
+        .map(|path| {
+            let metadata = fs::metadata(&path)?;
+            let mut infos = vec![file_info(&path, &metadata)?];
+            if metadata.is_dir() && !path.is_symlink() {
+                infos.extend(par_dir_info(&path, depth + 1, max_depth)?);
+            }
+            Ok(infos)
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(nested.into_iter().flatten().collect())
+}
+
+fn read_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info::{get_dir_info_recursive, get_files, get_size};
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["a/x", "a/y", "b"] {
+            fs::create_dir_all(dir.path().join(sub)).unwrap();
+            for i in 0..5 {
+                fs::write(dir.path().join(sub).join(i.to_string()), "abc").unwrap();
+            }
+        }
+        let root = dir.path().to_str().unwrap();
+
+        assert_eq!(
+            par_get_files(dir.path()).unwrap(),
+            get_files(dir.path()).unwrap()
+        );
+        assert_eq!(par_get_size(root).unwrap(), get_size(root).unwrap());
+        let names = |infos: Vec<FileInfo>| -> Vec<String> {
+            infos.into_iter().map(|info| info.file_path).collect()
+        };
+        assert_eq!(
+            names(par_get_dir_info(root, Some(2)).unwrap()),
+            names(get_dir_info_recursive(root, Some(2)).unwrap())
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use crate::disk::ensure_writable;
+use crate::walk::walk;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The suffix of files still being written. Readers should ignore files ending in it.
+pub const PART_SUFFIX: &str = ".part";
+
+/// How long a `.part` file may go unmodified before it is considered abandoned. Cleanups
+/// never touch younger ones, since a writer may still be filling them.
+pub const PART_FILE_GRACE: Duration = Duration::from_secs(3600);
+
+/// Writes `data` to `path` so that readers never see a partial file.
+///
+/// The data goes to `path` + [`PART_SUFFIX`] first, is synced to disk, and the part file
+/// is then renamed over `path`. If the process dies half-way, only the `.part` file is
+/// left behind, for [`purge_stale_part_files`] to remove.
+///
+/// # Arguments
+///
+/// * `path` - A string slice that holds the name of the file to write.
+/// * `data` - The bytes to write.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::write_via_part_file("/srv/outbox/report.json", b"{}").unwrap();
+/// ```
+pub fn write_via_part_file(path: &str, data: &[u8]) -> io::Result<()> {
+    let part = format!("{}{}", path, PART_SUFFIX);
+    let mut file = fs::File::create(&part)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&part, path)
+}
+
+// The part file `path` is written to before being renamed into place.
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(PART_SUFFIX);
+    PathBuf::from(part)
+}
+
+/// Returns true if `path` names a file still being written, by [`PART_SUFFIX`].
+pub fn is_part_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(PART_SUFFIX))
+}
+
+/// Returns true if `path` is a `.part` file modified within [`PART_FILE_GRACE`], which
+/// cleanups must leave to its writer.
+pub(crate) fn is_fresh_part_file(path: &Path, modified: Option<SystemTime>) -> bool {
+    is_part_file(path)
+        && modified
+            .is_some_and(|modified| modified.elapsed().map_or(true, |age| age < PART_FILE_GRACE))
+}
+
+/// Removes the `.part` files under `dir` (including subdirectories) that haven't been
+/// modified for `older_than`, left behind by writers that died half-way.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were removed.
+///
+/// # Example
+///
+/// ```no_run
+/// let removed = bbq::purge_stale_part_files("/srv/outbox", bbq::PART_FILE_GRACE).unwrap();
+/// ```
+pub fn purge_stale_part_files(dir: &str, older_than: Duration) -> io::Result<Vec<String>> {
+    ensure_writable(dir)?;
+    let mut removed = Vec::new();
+    for info in walk(dir).including_part_files().filter_map(Result::ok) {
+        let path = Path::new(&info.file_path);
+        let stale = info
+            .modified_time
+            .and_then(|time| time.elapsed().ok())
+            .is_some_and(|age| age >= older_than);
+        if info.file_type == "File" && is_part_file(path) && stale {
+            fs::remove_file(path)?;
+            removed.push(info.file_path);
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_via_part_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.json");
+        write_via_part_file(path.to_str().unwrap(), b"{}").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"{}");
+        assert!(!dir.path().join("out.json.part").exists());
+
+        let abandoned = dir.path().join("other.json.part");
+        fs::write(&abandoned, "half").unwrap();
+        let root = dir.path().to_str().unwrap();
+        assert!(purge_stale_part_files(root, PART_FILE_GRACE)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            purge_stale_part_files(root, Duration::ZERO).unwrap().len(),
+            1
+        );
+        assert!(path.exists());
+    }
+}
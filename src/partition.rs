@@ -0,0 +1,283 @@
+use crate::disk::ensure_writable;
+use chrono::{DateTime, LocalResult, Months, NaiveDate, NaiveDateTime, TimeDelta, TimeZone};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A dated directory found by [`iter_partitions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition<Tz: TimeZone> {
+    pub path: PathBuf,
+    /// The start of the period the directory holds, in the layout's time zone.
+    pub start: DateTime<Tz>,
+    /// The end of the period: the start of the next hour, day, month or year, depending on
+    /// the finest field in the layout.
+    pub end: DateTime<Tz>,
+}
+
+/// Returns the directory under `base` that holds data from `time`, for a dated layout such
+/// as `"%Y/%m/%d"` or `"%Y-%m-%d/%H"`.
+///
+/// The path is formatted in `time`'s own time zone, so pass a `DateTime<Utc>` for a UTC
+/// layout and a `DateTime<Local>` (or a `chrono-tz` zone) for a local one. Nothing is
+/// created on disk.
+///
+/// # Arguments
+///
+/// * `base` - The root of the layout.
+/// * `time` - The time to find the partition of.
+/// * `format` - A `chrono` format string, with `/` separating directory levels.
+///
+/// # Example
+///
+/// ```no_run
+/// let dir = bbq::partition_path("/data/events", &chrono::Utc::now(), "%Y/%m/%d");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// ```
+pub fn partition_path<Tz>(base: &str, time: &DateTime<Tz>, format: &str) -> PathBuf
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    Path::new(base).join(time.format(format).to_string())
+}
+
+/// Lists the partition directories under `base` whose period starts within `range`,
+/// oldest first.
+///
+/// Directories are matched against `format` level by level, so `"%Y/%m/%d"` looks three
+/// levels deep; anything that doesn't parse as a date is ignored. Each directory's start
+/// is read as a local time in `tz`. Across a daylight-saving change, an ambiguous start
+/// (clocks going back) resolves to the earlier instant, and a start that doesn't exist
+/// (clocks going forward) to the first instant after the gap.
+///
+/// # Arguments
+///
+/// * `base` - The root of the layout.
+/// * `format` - The format the partitions were created with by [`partition_path`].
+/// * `tz` - The time zone the layout is in.
+/// * `range` - The starts to include, for example `from..to` or `..cutoff`.
+///
+/// # Returns
+///
+/// * `std::io::Result<std::vec::IntoIter<Partition<Tz>>>` - A Result containing the matching partitions. A missing `base` yields none.
+///
+/// # Example
+///
+/// ```no_run
+/// use chrono::{Duration, Utc};
+///
+/// let week_ago = Utc::now() - Duration::days(7);
+/// for partition in bbq::iter_partitions("/data/events", "%Y/%m/%d", &Utc, ..week_ago).unwrap() {
+///     println!("{} is older than a week", partition.path.display());
+/// }
+/// ```
+pub fn iter_partitions<Tz, R>(
+    base: &str,
+    format: &str,
+    tz: &Tz,
+    range: R,
+) -> io::Result<std::vec::IntoIter<Partition<Tz>>>
+where
+    Tz: TimeZone,
+    R: RangeBounds<DateTime<Tz>>,
+{
+    let depth = format.split('/').count();
+    let mut dirs = Vec::new();
+    collect_dirs(Path::new(base), Path::new(""), depth, &mut dirs)?;
+    let mut partitions: Vec<Partition<Tz>> = dirs
+        .into_iter()
+        .filter_map(|(path, relative)| {
+            let naive = parse_partition(&relative, format)?;
+            let start = resolve_local(tz, naive)?;
+            let end = resolve_local(tz, period_end(naive, format)?)?;
+            Some(Partition { path, start, end })
+        })
+        .filter(|partition| range.contains(&partition.start))
+        .collect();
+    partitions.sort_by(|a, b| a.start.cmp(&b.start));
+    Ok(partitions.into_iter())
+}
+
+/// Removes the partition directories under `base` whose whole period ended more than
+/// `older_than` ago, then any parent directories of the layout left empty.
+///
+/// A partition is only removed once its last moment is outside the retention window, so
+/// `"%Y/%m"` with 30 days keeps last month's directory until 30 days after the month
+/// ended. Each partition goes in one `remove_dir_all`, without looking at its files.
+///
+/// # Arguments
+///
+/// * `base` - The root of the layout.
+/// * `format` - The format the partitions were created with by [`partition_path`].
+/// * `tz` - The time zone the layout is in.
+/// * `older_than` - The retention window.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the partition directories removed, oldest first.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let removed = bbq::prune_partitions("/data/events", "%Y/%m/%d", &chrono::Utc, Duration::from_secs(90 * 86_400)).unwrap();
+/// ```
+pub fn prune_partitions<Tz: TimeZone>(
+    base: &str,
+    format: &str,
+    tz: &Tz,
+    older_than: Duration,
+) -> io::Result<Vec<String>> {
+    ensure_writable(base)?;
+    let window = TimeDelta::from_std(older_than).map_err(io::Error::other)?;
+    let cutoff = tz.from_utc_datetime(&chrono::Utc::now().naive_utc()) - window;
+    let mut removed = Vec::new();
+    for partition in iter_partitions(base, format, tz, ..)? {
+        if partition.end > cutoff {
+            continue;
+        }
+        fs::remove_dir_all(&partition.path)?;
+        remove_empty_parents(Path::new(base), &partition.path);
+        removed.push(partition.path.to_string_lossy().into_owned());
+    }
+    Ok(removed)
+}
+
+fn remove_empty_parents(base: &Path, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        if parent == base || !parent.starts_with(base) || fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+}
+
+// Collects the directories exactly `depth` levels below `dir`, with their relative paths
+// joined by `/`.
+fn collect_dirs(
+    dir: &Path,
+    relative: &Path,
+    depth: usize,
+    dirs: &mut Vec<(PathBuf, String)>,
+) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let relative = relative.join(entry.file_name());
+        if depth == 1 {
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            dirs.push((entry.path(), name));
+        } else {
+            collect_dirs(&entry.path(), &relative, depth - 1, dirs)?;
+        }
+    }
+    Ok(())
+}
+
+// Parses a partition name back into the start of its period. Layouts coarser than a day
+// (such as `%Y/%m`) don't parse as a date on their own, so the first of the month is
+// filled in.
+fn parse_partition(name: &str, format: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(name, format)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(name, format)
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(&format!("{}|01", name), &format!("{}|%d", format))
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+// The start of the period after the one starting at `start`, judged by the finest field
+// the layout formats.
+fn period_end(start: NaiveDateTime, format: &str) -> Option<NaiveDateTime> {
+    let has = |fields: &[&str]| fields.iter().any(|field| format.contains(field));
+    if has(&["%M", "%R", "%T"]) {
+        Some(start + TimeDelta::minutes(1))
+    } else if has(&["%H", "%k", "%I", "%l"]) {
+        Some(start + TimeDelta::hours(1))
+    } else if has(&["%d", "%e", "%j", "%F", "%D"]) {
+        Some(start + TimeDelta::days(1))
+    } else if has(&["%m", "%b", "%B", "%h"]) {
+        start.checked_add_months(Months::new(1))
+    } else {
+        start.checked_add_months(Months::new(12))
+    }
+}
+
+fn resolve_local<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(time) => Some(time),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        // In a gap, the wall-clock time is skipped; move forward until it exists.
+        LocalResult::None => (1..=24)
+            .map(|minutes| naive + TimeDelta::minutes(minutes * 15))
+            .find_map(|later| tz.from_local_datetime(&later).earliest()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        // 23:30 UTC is already the next day two hours east.
+        let time = Utc.with_ymd_and_hms(2024, 3, 9, 23, 30, 0).unwrap();
+        let path = partition_path(base, &time.with_timezone(&tz), "%Y/%m/%d");
+        assert_eq!(path, dir.path().join("2024/03/10"));
+
+        for day in ["2024/03/08", "2024/03/09", "2024/03/10", "2024/03/notes"] {
+            fs::create_dir_all(dir.path().join(day)).unwrap();
+        }
+        let cutoff = tz.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+        let old: Vec<PathBuf> = iter_partitions(base, "%Y/%m/%d", &tz, ..cutoff)
+            .unwrap()
+            .map(|partition| partition.path)
+            .collect();
+        assert_eq!(
+            old,
+            vec![dir.path().join("2024/03/08"), dir.path().join("2024/03/09")]
+        );
+        assert_eq!(iter_partitions(base, "%Y/%m", &Utc, ..).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_prune_partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        let today = partition_path(base, &Utc::now(), "%Y/%m/%d");
+        fs::create_dir_all(&today).unwrap();
+        fs::create_dir_all(dir.path().join("2001/02/03")).unwrap();
+        fs::write(dir.path().join("2001/02/03/data"), "x").unwrap();
+
+        let removed =
+            prune_partitions(base, "%Y/%m/%d", &Utc, Duration::from_secs(86_400)).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!dir.path().join("2001").exists());
+        assert!(today.exists());
+    }
+}
@@ -0,0 +1,417 @@
+use crate::archive::{write_tar_gz, ArchiveOptions};
+use crate::lock::{is_lock_file, DirLock};
+use crate::manifest::{EntryKind, Manifest};
+use crate::progress::CancelToken;
+use crate::remote::{file_key, ship_file, Remote, Verify};
+use crate::retention::RetentionPolicy;
+use crate::stats::Stats;
+use crate::telemetry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// A step of a [`Pipeline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PipelineStep {
+    Scan,
+    Archive,
+    Upload,
+    Delete,
+}
+
+/// How far a [`Pipeline`] has got, passed to its progress callback after every file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineProgress {
+    pub step: PipelineStep,
+    /// The items this step has finished.
+    pub done: u64,
+    /// The items this step has in total.
+    pub total: u64,
+}
+
+/// The outcome of [`Pipeline::run`]. In a dry run, it lists what a real run would do.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineReport {
+    /// The files selected by the scan and filter.
+    pub selected: Vec<String>,
+    pub bytes_selected: u64,
+    /// The archive written, if the pipeline archives.
+    pub archive: Option<String>,
+    /// The files (or the archive) uploaded and verified.
+    pub uploaded: Vec<String>,
+    /// The selected files removed at the end.
+    pub deleted: Vec<String>,
+    /// The selected files left in place because their size or modification time changed
+    /// after the scan, so what was archived or uploaded may not be all they hold.
+    #[serde(default)]
+    pub changed: Vec<String>,
+    pub dry_run: bool,
+    /// The run was cancelled; the steps after the cancellation didn't run.
+    pub cancelled: bool,
+    pub stats: Stats,
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(&PipelineProgress) + 'a>;
+
+/// A maintenance job declared as a chain of steps: scan a directory, filter the files,
+/// archive them, upload the archive (or the files), delete the originals, and report.
+///
+/// Every step is optional except the scan. Each step only runs if the ones before it
+/// succeeded, so files are never deleted unless they were archived and uploaded as
+/// asked. The whole pipeline shares one dry-run switch, progress callback and
+/// [`CancelToken`].
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{DirRemote, Pipeline, RetentionPolicy, Verify};
+/// use std::time::Duration;
+///
+/// let mut remote = DirRemote::open("/mnt/archive").unwrap();
+/// let report = Pipeline::new("/var/log/app")
+///     .filter(RetentionPolicy::new().max_age(Duration::from_secs(7 * 86_400)))
+///     .archive("/var/tmp/app-logs.tar.gz")
+///     .upload(&mut remote, Verify::Checksum)
+///     .delete(true)
+///     .on_progress(|p| println!("{:?} {}/{}", p.step, p.done, p.total))
+///     .run()
+///     .unwrap();
+/// println!("shipped {} bytes", report.bytes_selected);
+/// ```
+pub struct Pipeline<'a> {
+    dir: String,
+    filter: Option<RetentionPolicy>,
+    archive: Option<String>,
+    upload: Option<(&'a mut dyn Remote, Verify)>,
+    delete: bool,
+    dry_run: bool,
+    cancel: CancelToken,
+    progress: Option<ProgressFn<'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Starts a pipeline working on the files under `dir`.
+    pub fn new(dir: &str) -> Self {
+        Pipeline {
+            dir: dir.to_string(),
+            filter: None,
+            archive: None,
+            upload: None,
+            delete: false,
+            dry_run: false,
+            cancel: CancelToken::new(),
+            progress: None,
+        }
+    }
+
+    /// Only selects the files `policy` would remove. Without a filter, every file is selected.
+    pub fn filter(mut self, policy: RetentionPolicy) -> Self {
+        self.filter = Some(policy);
+        self
+    }
+
+    /// Writes the selected files to a tar.gz at `dest`, named relative to the directory.
+    pub fn archive(mut self, dest: &str) -> Self {
+        self.archive = Some(dest.to_string());
+        self
+    }
+
+    /// Uploads the archive, or the selected files if there is no archive step, and
+    /// verifies each upload. See [`ship_files`](crate::ship_files). Files are stored under
+    /// their paths relative to the directory, and the archive under its file name.
+    pub fn upload(mut self, remote: &'a mut dyn Remote, verify: Verify) -> Self {
+        self.upload = Some((remote, verify));
+        self
+    }
+
+    /// Removes the selected files once the other steps have succeeded. Files that changed
+    /// since the scan are kept and reported in [`PipelineReport::changed`]. The directory
+    /// is locked with a [`DirLock`] for the whole run.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Only reports what would be done, without writing, uploading or deleting anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Stops the pipeline when `cancel` is cancelled.
+    pub fn cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Calls `progress` after every file of every step.
+    pub fn on_progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&PipelineProgress) + 'a,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Runs the steps in order.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<PipelineReport>` - A Result containing what was done. A failing step stops the pipeline with its error; cancelling stops it with a report marked `cancelled`.
+    pub fn run(mut self) -> io::Result<PipelineReport> {
+        let _span = telemetry::enter("pipeline", Path::new(&self.dir));
+        let started = Instant::now();
+        let mut report = PipelineReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+
+        let _lock = if self.delete && !self.dry_run {
+            crate::disk::ensure_writable(&self.dir)?;
+            Some(DirLock::acquire(&self.dir)?)
+        } else {
+            None
+        };
+        let snapshot = Manifest::scan(&self.dir)?;
+        let scanned: HashMap<PathBuf, Option<SystemTime>> = snapshot
+            .entries
+            .iter()
+            .map(|entry| (Path::new(&self.dir).join(&entry.path), entry.modified))
+            .collect();
+        let selected: Vec<(PathBuf, u64)> = match &self.filter {
+            Some(policy) => policy
+                .plan_snapshot(&snapshot)?
+                .into_iter()
+                .map(|victim| (victim.path, victim.size))
+                .collect(),
+            None => snapshot
+                .entries
+                .iter()
+                .filter(|entry| entry.kind == EntryKind::File)
+                .filter(|entry| !is_lock_file(Path::new(&entry.path)))
+                .map(|entry| (Path::new(&self.dir).join(&entry.path), entry.size))
+                .collect(),
+        };
+        report.stats.files_visited = snapshot.entries.len() as u64;
+        report.bytes_selected = selected.iter().map(|(_, size)| size).sum();
+        report.selected = selected
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect();
+        let total = selected.len() as u64;
+        self.report_progress(PipelineStep::Scan, total, total);
+
+        if let Some(dest) = self.archive.clone() {
+            if self.cancel.is_cancelled() {
+                return Ok(self.cancelled(report, started));
+            }
+            if !self.dry_run {
+                let files: Vec<PathBuf> = selected.iter().map(|(path, _)| path.clone()).collect();
+                write_tar_gz(
+                    Path::new(&dest),
+                    Path::new(&self.dir),
+                    &files,
+                    &ArchiveOptions::default(),
+                )?;
+                report.stats.bytes_read += report.bytes_selected;
+                report.stats.bytes_written += fs::metadata(&dest)?.len();
+            }
+            self.report_progress(PipelineStep::Archive, 1, 1);
+            report.archive = Some(dest);
+        }
+
+        if let Some((remote, verify)) = self.upload.take() {
+            // Files are stored under their path relative to the directory, so files with
+            // the same name in different subdirectories don't overwrite each other.
+            let items: Vec<(String, String)> = match &report.archive {
+                Some(archive) => vec![(archive.clone(), file_key(archive)?)],
+                None => selected
+                    .iter()
+                    .map(|(path, _)| {
+                        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+                        let key = relative
+                            .components()
+                            .map(|c| c.as_os_str().to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join("/");
+                        (path.to_string_lossy().into_owned(), key)
+                    })
+                    .collect(),
+            };
+            for (i, (item, key)) in items.iter().enumerate() {
+                if self.cancel.is_cancelled() {
+                    return Ok(self.cancelled(report, started));
+                }
+                if !self.dry_run {
+                    let stats = ship_file(item, key, remote, verify)?;
+                    report.stats.bytes_read += stats.bytes_read;
+                    report.stats.retries += stats.retries;
+                }
+                report.uploaded.push(item.clone());
+                self.report_progress(PipelineStep::Upload, i as u64 + 1, items.len() as u64);
+            }
+        }
+
+        if self.delete {
+            for (i, (path, size)) in selected.iter().enumerate() {
+                if self.cancel.is_cancelled() {
+                    return Ok(self.cancelled(report, started));
+                }
+                if !self.dry_run {
+                    // A file written to after the scan holds data that was never archived
+                    // or uploaded, so it stays.
+                    let modified = scanned.get(path).copied().flatten();
+                    if changed_since_scan(path, *size, modified) {
+                        report.changed.push(path.to_string_lossy().into_owned());
+                        self.report_progress(PipelineStep::Delete, i as u64 + 1, total);
+                        continue;
+                    }
+                    fs::remove_file(path)?;
+                    telemetry::file_removed(path, *size);
+                }
+                report.deleted.push(path.to_string_lossy().into_owned());
+                self.report_progress(PipelineStep::Delete, i as u64 + 1, total);
+            }
+        }
+
+        report.stats.duration = started.elapsed();
+        telemetry::finished(&report.stats);
+        Ok(report)
+    }
+
+    fn report_progress(&mut self, step: PipelineStep, done: u64, total: u64) {
+        if let Some(progress) = &mut self.progress {
+            progress(&PipelineProgress { step, done, total });
+        }
+    }
+
+    fn cancelled(&self, mut report: PipelineReport, started: Instant) -> PipelineReport {
+        report.cancelled = true;
+        report.stats.duration = started.elapsed();
+        report
+    }
+}
+
+// Whether the file at `path` no longer has the size and modification time it was scanned
+// with. A file that is gone counts as unchanged; there is nothing left to lose.
+fn changed_since_scan(path: &Path, size: u64, modified: Option<SystemTime>) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.len() != size || metadata.modified().ok() != modified,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::DirRemote;
+
+    #[test]
+    fn test_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(logs.join("a.log"), "aaa").unwrap();
+        fs::write(logs.join("b.log"), "bb").unwrap();
+        let archive = dir.path().join("logs.tar.gz");
+        let mut remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+
+        let dry = Pipeline::new(logs.to_str().unwrap())
+            .archive(archive.to_str().unwrap())
+            .delete(true)
+            .dry_run(true)
+            .run()
+            .unwrap();
+        assert_eq!(dry.deleted.len(), 2);
+        assert!(!archive.exists());
+        assert!(logs.join("a.log").exists());
+
+        let mut steps = Vec::new();
+        let report = Pipeline::new(logs.to_str().unwrap())
+            .archive(archive.to_str().unwrap())
+            .upload(&mut remote, Verify::Checksum)
+            .delete(true)
+            .on_progress(|p| steps.push(p.step))
+            .run()
+            .unwrap();
+        assert_eq!(report.bytes_selected, 5);
+        assert_eq!(report.uploaded.len(), 1);
+        assert!(dir.path().join("remote/logs.tar.gz").exists());
+        assert_eq!(fs::read_dir(&logs).unwrap().count(), 0);
+        assert_eq!(steps.first(), Some(&PipelineStep::Scan));
+        assert_eq!(steps.last(), Some(&PipelineStep::Delete));
+    }
+
+    #[test]
+    fn test_pipeline_keeps_files_changed_after_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(logs.join("app.log"), "first\n").unwrap();
+        fs::write(logs.join("old.log"), "old\n").unwrap();
+        let archive = dir.path().join("logs.tar.gz");
+
+        let report = Pipeline::new(logs.to_str().unwrap())
+            .archive(archive.to_str().unwrap())
+            .delete(true)
+            .on_progress(|p| {
+                if p.step == PipelineStep::Archive {
+                    crate::info::append_text_file(
+                        logs.join("app.log").to_str().unwrap(),
+                        "second\n",
+                    )
+                    .unwrap();
+                }
+            })
+            .run()
+            .unwrap();
+        assert_eq!(report.changed.len(), 1);
+        assert!(report.changed[0].ends_with("app.log"));
+        assert_eq!(report.deleted.len(), 1);
+        assert_eq!(
+            fs::read_to_string(logs.join("app.log")).unwrap(),
+            "first\nsecond\n"
+        );
+        assert!(!logs.join("old.log").exists());
+    }
+
+    #[test]
+    fn test_pipeline_cancel() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), "a").unwrap();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let report = Pipeline::new(dir.path().to_str().unwrap())
+            .delete(true)
+            .cancel_token(cancel)
+            .run()
+            .unwrap();
+        assert!(report.cancelled);
+        assert!(report.deleted.is_empty());
+        assert!(dir.path().join("a").exists());
+    }
+
+    #[test]
+    fn test_pipeline_uploads_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        for sub in ["a", "b"] {
+            fs::create_dir_all(data.join(sub)).unwrap();
+            fs::write(data.join(sub).join("same.log"), sub).unwrap();
+        }
+        let mut remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+
+        Pipeline::new(data.to_str().unwrap())
+            .upload(&mut remote, Verify::Size)
+            .delete(true)
+            .run()
+            .unwrap();
+        for sub in ["a", "b"] {
+            let uploaded = remote.root().join(sub).join("same.log");
+            assert_eq!(fs::read_to_string(uploaded).unwrap(), sub);
+        }
+    }
+}
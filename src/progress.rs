@@ -0,0 +1,51 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag for stopping a long operation from another thread.
+///
+/// Clones share the flag, so one clone can be handed to the operation and another kept
+/// to cancel it. Operations check the flag between files, so cancelling takes effect
+/// after the file in progress.
+///
+/// # Example
+///
+/// ```no_run
+/// let cancel = bbq::CancelToken::new();
+/// let handle = cancel.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     handle.cancel();
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the operations holding this token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an `ErrorKind::Interrupted` error if the token has been cancelled.
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the operation was cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
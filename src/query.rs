@@ -0,0 +1,508 @@
+use crate::info::{file_info, FileInfo};
+use crate::manifest::EntryKind;
+use crate::walk::{include_hidden_by_default, walk_with, SymlinkPolicy, Visit, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The field listings are sorted by; see [`sort_file_info`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    /// Timestamps sort entries without one first, as the oldest.
+    Modified,
+    Created,
+    Accessed,
+    /// The extension, case-insensitively, then the name.
+    Extension,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Sorts a listing in place. Ties are broken by name, so the order is stable across calls.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{sort_file_info, SortBy, SortOrder};
+///
+/// let mut files = bbq::get_dir_info("/var/log").unwrap();
+/// sort_file_info(&mut files, SortBy::Size, SortOrder::Descending);
+/// ```
+pub fn sort_file_info(infos: &mut [FileInfo], by: SortBy, order: SortOrder) {
+    infos.sort_by(|a, b| {
+        let ordering = match by {
+            SortBy::Name => Ordering::Equal,
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Modified => a.modified_time.cmp(&b.modified_time),
+            SortBy::Created => a.created_time.cmp(&b.created_time),
+            SortBy::Accessed => a.accessed_time.cmp(&b.accessed_time),
+            SortBy::Extension => extension_of(a).cmp(&extension_of(b)),
+        }
+        .then_with(|| a.file_name.cmp(&b.file_name));
+        match order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    });
+}
+
+fn extension_of(info: &FileInfo) -> Option<String> {
+    info.extension.as_ref().map(|ext| ext.to_lowercase())
+}
+
+/// Groups `infos` by extension, compared case-insensitively, so `a.LOG` and `b.log` land
+/// together under `"log"`. Entries without an extension are grouped under `""`. Each group
+/// keeps the order of `infos`.
+///
+/// # Example
+///
+/// ```no_run
+/// let files = bbq::get_dir_info("/var/log/app").unwrap();
+/// for (extension, group) in bbq::by_extension(&files) {
+///     let bytes: u64 = group.iter().map(|info| info.size).sum();
+///     println!("{:>8} {} files, {} bytes", extension, group.len(), bytes);
+/// }
+/// ```
+pub fn by_extension(infos: &[FileInfo]) -> BTreeMap<String, Vec<FileInfo>> {
+    let mut groups: BTreeMap<String, Vec<FileInfo>> = BTreeMap::new();
+    for info in infos {
+        groups
+            .entry(extension_of(info).unwrap_or_default())
+            .or_default()
+            .push(info.clone());
+    }
+    groups
+}
+
+/// Like [`get_dir_info`](crate::get_dir_info), with the entries sorted.
+pub fn get_dir_info_sorted(dir: &str, by: SortBy, order: SortOrder) -> io::Result<Vec<FileInfo>> {
+    get_dir_info_matching(dir, &DirQuery::new().sort_by(by, order))
+}
+
+/// Conditions on the entries returned by [`get_dir_info_matching`].
+///
+/// Every condition set must hold; an empty query matches everything, like
+/// [`get_dir_info`](crate::get_dir_info).
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{DirQuery, EntryKind};
+///
+/// let big_logs = DirQuery::new()
+///     .kind(EntryKind::File)
+///     .extension("log")
+///     .min_size(100 * 1024 * 1024)
+///     .recursive(true)
+///     .run("/var/log")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirQuery {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Entries without a modification time never match a time bound.
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    /// Extensions without the dot, compared case-insensitively. Empty means any.
+    pub extensions: Vec<String>,
+    pub name_contains: Option<String>,
+    pub kind: Option<EntryKind>,
+    /// Also list the contents of subdirectories.
+    #[serde(default)]
+    pub recursive: bool,
+    /// How to sort the results. Unsorted results come in directory order.
+    #[serde(default)]
+    pub sort: Option<(SortBy, SortOrder)>,
+    /// Skip this many matches. Pair with a sort, so pages line up between calls.
+    #[serde(default)]
+    pub offset: usize,
+    /// Return at most this many matches.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// What to do with symlinks; links are listed as links by default.
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    /// List [hidden](crate::is_hidden) entries. Defaults to `true`, like `ls -a`.
+    #[serde(default = "include_hidden_by_default")]
+    pub include_hidden: bool,
+}
+
+impl Default for DirQuery {
+    fn default() -> Self {
+        DirQuery {
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            extensions: Vec::new(),
+            name_contains: None,
+            kind: None,
+            recursive: false,
+            sort: None,
+            offset: 0,
+            limit: None,
+            symlinks: SymlinkPolicy::default(),
+            include_hidden: include_hidden_by_default(),
+        }
+    }
+}
+
+impl DirQuery {
+    /// Creates a query matching everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches entries of at least `bytes`.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Only matches entries of at most `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Only matches entries modified at or after `time`.
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Only matches entries modified before `time`.
+    pub fn modified_before(mut self, time: SystemTime) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    /// Matches entries with the extension `ext`. May be called several times.
+    pub fn extension(mut self, ext: &str) -> Self {
+        self.extensions
+            .push(ext.trim_start_matches('.').to_lowercase());
+        self
+    }
+
+    /// Only matches entries whose name contains `text`.
+    pub fn name_contains(mut self, text: &str) -> Self {
+        self.name_contains = Some(text.to_string());
+        self
+    }
+
+    /// Only matches files, or only directories.
+    pub fn kind(mut self, kind: EntryKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Also lists the contents of subdirectories.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Lists hidden entries, or leaves them out, like a file manager's "show hidden" switch.
+    pub fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Treats symlinks as `policy` says.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sorts the results; see [`sort_file_info`].
+    pub fn sort_by(mut self, by: SortBy, order: SortOrder) -> Self {
+        self.sort = Some((by, order));
+        self
+    }
+
+    /// Skips the first `offset` matches and returns at most `limit`, for paging through
+    /// large directories.
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns true if `info` meets every condition.
+    pub fn matches(&self, info: &FileInfo) -> bool {
+        let kind = match info.file_type.as_str() {
+            "File" => Some(EntryKind::File),
+            "Directory" => Some(EntryKind::Dir),
+            _ => None,
+        };
+        let extension = extension_of(info);
+        self.min_size.is_none_or(|min| info.size >= min)
+            && self.max_size.is_none_or(|max| info.size <= max)
+            && self
+                .modified_after
+                .is_none_or(|after| info.modified_time.is_some_and(|time| time >= after))
+            && self
+                .modified_before
+                .is_none_or(|before| info.modified_time.is_some_and(|time| time < before))
+            && (self.extensions.is_empty()
+                || extension.is_some_and(|ext| self.extensions.contains(&ext)))
+            && self
+                .name_contains
+                .as_ref()
+                .is_none_or(|text| info.file_name.contains(text.as_str()))
+            && self.kind.is_none_or(|want| kind == Some(want))
+    }
+
+    /// Runs the query on `dir`; see [`get_dir_info_matching`].
+    pub fn run(&self, dir: &str) -> io::Result<Vec<FileInfo>> {
+        get_dir_info_matching(dir, self)
+    }
+}
+
+/// Like [`get_dir_info`](crate::get_dir_info), but only returns the entries matching
+/// `query`, filtering as the directory is read instead of building the full list first.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `query` - The conditions the entries must meet, and whether to look in subdirectories.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<FileInfo>>` - A Result containing the matching entries.
+pub fn get_dir_info_matching(dir: &str, query: &DirQuery) -> io::Result<Vec<FileInfo>> {
+    let options = WalkOptions {
+        max_depth: if query.recursive { None } else { Some(1) },
+        stop_on_error: true,
+        symlinks: query.symlinks,
+        include_hidden: query.include_hidden,
+        ..Default::default()
+    };
+    // Without a sort, matches come in walk order, so the walk can stop once the page is full.
+    let wanted = match query.sort {
+        Some(_) => None,
+        None => query.limit.map(|limit| query.offset + limit),
+    };
+    let mut found = Vec::new();
+    if wanted != Some(0) {
+        walk_with(dir, &options, |info| {
+            if query.matches(info) {
+                found.push(info.clone());
+                if wanted.is_some_and(|wanted| found.len() >= wanted) {
+                    return ControlFlow::Break(());
+                }
+            }
+            ControlFlow::Continue(Visit::Descend)
+        })?;
+    }
+    if let Some((by, order)) = query.sort {
+        sort_file_info(&mut found, by, order);
+    }
+    let end = query
+        .limit
+        .map_or(found.len(), |limit| found.len().min(query.offset + limit));
+    Ok(found.drain(query.offset.min(end)..end).collect())
+}
+
+/// One page of a directory listing, from [`get_dir_info_page`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirPage {
+    pub entries: Vec<FileInfo>,
+    /// The cursor to pass to get the next page, or `None` on the last page.
+    pub next: Option<String>,
+}
+
+/// Lists one page of the entries of `dir`, in name order, starting after the name `after`.
+///
+/// Only the names of the directory are read in full; metadata is read for the entries on
+/// the page alone, so paging through a directory of 100,000 files costs one `stat` per
+/// entry shown. Entries added or removed between calls are picked up or skipped as their
+/// names fall, without shifting the rest of the pages.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `after` - The `next` cursor of the previous page, or `None` for the first page.
+/// * `limit` - The most entries to return.
+///
+/// # Returns
+///
+/// * `std::io::Result<DirPage>` - A Result containing the entries and the cursor of the next page.
+///
+/// # Example
+///
+/// ```no_run
+/// let mut cursor = None;
+/// loop {
+///     let page = bbq::get_dir_info_page("/srv/uploads", cursor.as_deref(), 100).unwrap();
+///     for info in &page.entries {
+///         println!("{}", info.file_name);
+///     }
+///     match page.next {
+///         Some(next) => cursor = Some(next),
+///         None => break,
+///     }
+/// }
+/// ```
+pub fn get_dir_info_page(dir: &str, after: Option<&str>, limit: usize) -> io::Result<DirPage> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if after.is_none_or(|after| name.as_str() > after) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    let mut entries = Vec::new();
+    for name in names.iter().take(limit) {
+        let path = Path::new(dir).join(name);
+        match fs::metadata(&path) {
+            Ok(metadata) => entries.push(file_info(&path, &metadata)?),
+            // Removed since the names were read.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    let next = if names.len() > limit {
+        limit.checked_sub(1).map(|last| names[last].clone())
+    } else {
+        None
+    };
+    Ok(DirPage { entries, next })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_dir_query() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("app.LOG"), "x".repeat(10)).unwrap();
+        fs::write(dir.path().join("tiny.log"), "x").unwrap();
+        fs::write(dir.path().join("sub/deep.log"), "x".repeat(10)).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let query = DirQuery::new().extension(".log").min_size(5);
+        let names: Vec<String> = query
+            .run(root)
+            .unwrap()
+            .into_iter()
+            .map(|info| info.file_name)
+            .collect();
+        assert_eq!(names, vec!["app.LOG"]);
+        assert_eq!(query.recursive(true).run(root).unwrap().len(), 2);
+        let dirs = DirQuery::new().kind(EntryKind::Dir).run(root).unwrap();
+        assert_eq!(dirs.len(), 1);
+
+        fs::create_dir_all(dir.path().join(".cache")).unwrap();
+        fs::write(dir.path().join(".cache/hidden.log"), "x".repeat(10)).unwrap();
+        let all = DirQuery::new().recursive(true);
+        assert_eq!(all.clone().run(root).unwrap().len(), 6);
+        assert_eq!(all.include_hidden(false).run(root).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_pagination() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a", "b", "c", "d", "e"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        let root = dir.path().to_str().unwrap();
+
+        let query = DirQuery::new().sort_by(SortBy::Name, SortOrder::Ascending);
+        let page: Vec<String> = query
+            .page(1, 2)
+            .run(root)
+            .unwrap()
+            .into_iter()
+            .map(|info| info.file_name)
+            .collect();
+        assert_eq!(page, ["b", "c"]);
+        assert_eq!(DirQuery::new().page(4, 10).run(root).unwrap().len(), 1);
+
+        let mut cursor = None;
+        let mut seen = Vec::new();
+        loop {
+            let page = get_dir_info_page(root, cursor.as_deref(), 2).unwrap();
+            seen.extend(page.entries.into_iter().map(|info| info.file_name));
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn test_sort_file_info() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.txt"), "xxx").unwrap();
+        fs::write(dir.path().join("a.log"), "x").unwrap();
+        fs::write(dir.path().join("c.log"), "xx").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let names = |by, order| -> Vec<String> {
+            get_dir_info_sorted(root, by, order)
+                .unwrap()
+                .into_iter()
+                .map(|info| info.file_name)
+                .collect()
+        };
+
+        assert_eq!(
+            names(SortBy::Name, SortOrder::Ascending),
+            ["a.log", "b.txt", "c.log"]
+        );
+        assert_eq!(
+            names(SortBy::Size, SortOrder::Descending),
+            ["b.txt", "c.log", "a.log"]
+        );
+        assert_eq!(
+            names(SortBy::Extension, SortOrder::Ascending),
+            ["a.log", "c.log", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn test_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.LOG", "b.log", "app.log.gz", "Makefile"] {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+        let infos = get_dir_info_sorted(
+            dir.path().to_str().unwrap(),
+            SortBy::Name,
+            SortOrder::Ascending,
+        )
+        .unwrap();
+        let gz = infos
+            .iter()
+            .find(|info| info.file_name == "app.log.gz")
+            .unwrap();
+        assert_eq!(
+            (gz.file_stem.as_str(), gz.extension.as_deref()),
+            ("app.log", Some("gz"))
+        );
+
+        let groups = by_extension(&infos);
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["", "gz", "log"]);
+        assert_eq!(groups["log"].len(), 2);
+        assert_eq!(groups[""][0].file_name, "Makefile");
+    }
+}
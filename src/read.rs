@@ -0,0 +1,470 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// The chunks of a file, as returned by [`read_file_chunks`].
+#[derive(Debug)]
+pub struct FileChunks {
+    file: Option<fs::File>,
+    // An error opening the file, returned as the only item.
+    error: Option<io::Error>,
+    chunk_size: usize,
+}
+
+impl Iterator for FileChunks {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let file = self.file.as_mut()?;
+        let mut chunk = vec![0u8; self.chunk_size];
+        match fill(file, &mut chunk) {
+            Ok(0) => {
+                self.file = None;
+                None
+            }
+            Ok(read) => {
+                chunk.truncate(read);
+                Some(Ok(chunk))
+            }
+            Err(e) => {
+                self.file = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Reads a file in chunks of `chunk_size` bytes, so files far larger than memory can be
+/// processed.
+///
+/// Every chunk is `chunk_size` bytes long except the last, which holds what is left. If
+/// the file can't be opened, or `chunk_size` is 0, the only item is the error. A read
+/// error ends the iteration after it is returned.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to read.
+/// * `chunk_size` - The number of bytes in each chunk.
+///
+/// # Returns
+///
+/// * `FileChunks` - An iterator over the chunks of the file.
+///
+/// # Example
+///
+/// ```no_run
+/// let mut total = 0;
+/// for chunk in bbq::read_file_chunks("/srv/vm/disk.img", 4 * 1024 * 1024) {
+///     total += chunk.unwrap().len();
+/// }
+/// ```
+pub fn read_file_chunks(file: &str, chunk_size: usize) -> FileChunks {
+    let file = if chunk_size == 0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "chunk size must be greater than 0",
+        ))
+    } else {
+        fs::File::open(file)
+    };
+    match file {
+        Ok(file) => FileChunks {
+            file: Some(file),
+            error: None,
+            chunk_size,
+        },
+        Err(e) => FileChunks {
+            file: None,
+            error: Some(e),
+            chunk_size,
+        },
+    }
+}
+
+/// Like [`read_file_chunks`], but calls `f` with each chunk, reusing one buffer.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of bytes read. An error returned by `f` stops the reading and is returned.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io::Write;
+///
+/// let mut out = std::io::stdout();
+/// bbq::for_each_chunk("/var/log/huge.log", 64 * 1024, |chunk| out.write_all(chunk)).unwrap();
+/// ```
+pub fn for_each_chunk<F>(file: &str, chunk_size: usize, mut f: F) -> io::Result<u64>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    if chunk_size == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "chunk size must be greater than 0",
+        ));
+    }
+    let mut file = fs::File::open(file)?;
+    let mut buffer = vec![0u8; chunk_size];
+    let mut total = 0u64;
+    loop {
+        let read = fill(&mut file, &mut buffer)?;
+        if read == 0 {
+            return Ok(total);
+        }
+        f(&buffer[..read])?;
+        total += read as u64;
+    }
+}
+
+// How much of the file read_last_lines reads at a time, going backwards.
+const TAIL_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Reads the last `n` lines of a text file, like `tail -n`.
+///
+/// The file is read backwards in blocks from its end until enough lines are found, so
+/// the time taken depends on the length of the lines returned, not the size of the file.
+/// A line break at the very end of the file doesn't start another line. Lines are
+/// returned without their `\n` or `\r\n`, oldest first.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to read.
+/// * `n` - The number of lines to read. If the file has fewer, all of them are returned.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the lines. A line that isn't valid UTF-8 returns an `ErrorKind::InvalidData` error.
+///
+/// # Example
+///
+/// ```no_run
+/// for line in bbq::read_last_lines("/var/log/app/app.log", 50).unwrap() {
+///     println!("{}", line);
+/// }
+/// ```
+pub fn read_last_lines(file: &str, n: usize) -> io::Result<Vec<String>> {
+    let mut file = fs::File::open(file)?;
+    let len = file.metadata()?.len();
+    if n == 0 || len == 0 {
+        return Ok(Vec::new());
+    }
+    let mut blocks = Vec::new();
+    let mut newlines = 0;
+    let mut position = len;
+    while position > 0 && newlines < n {
+        let start = position.saturating_sub(TAIL_BLOCK_SIZE);
+        let mut block = vec![0u8; (position - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        newlines += block.iter().filter(|&&byte| byte == b'\n').count();
+        if position == len && block.ends_with(b"\n") {
+            newlines -= 1;
+        }
+        blocks.push(block);
+        position = start;
+    }
+    blocks.reverse();
+    let tail = blocks.concat();
+    let tail = tail.strip_suffix(b"\n").unwrap_or(&tail);
+    // When reading stopped early, the first piece is a partial line, and never among the last n.
+    let pieces: Vec<&[u8]> = tail.split(|&byte| byte == b'\n').collect();
+    pieces[pieces.len().saturating_sub(n)..]
+        .iter()
+        .map(|line| line_to_string(line))
+        .collect()
+}
+
+/// Reads the last `n` bytes of a file, or the whole file if it is shorter.
+///
+/// # Example
+///
+/// ```no_run
+/// let trailer = bbq::read_last_bytes("/srv/data/archive.zip", 22).unwrap();
+/// ```
+pub fn read_last_bytes(file: &str, n: u64) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(file)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(n)))?;
+    let mut data = Vec::new();
+    file.take(n).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Reads the first `n` lines of a text file, like `head -n`, without reading the rest.
+///
+/// Lines are returned without their `\n` or `\r\n`.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to read.
+/// * `n` - The number of lines to read. If the file has fewer, all of them are returned.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the lines. A line that isn't valid UTF-8 returns an `ErrorKind::InvalidData` error.
+///
+/// # Example
+///
+/// ```no_run
+/// let header = bbq::read_first_lines("/srv/data/export.csv", 1).unwrap();
+/// ```
+pub fn read_first_lines(file: &str, n: usize) -> io::Result<Vec<String>> {
+    read_lines_range(file, 0, n)
+}
+
+/// Reads the lines from `start` up to, but not including, `end` of a text file, counting
+/// from 0, and stops reading there.
+///
+/// The lines before `start` are read but not kept, so they don't need to be valid UTF-8.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to read.
+/// * `start` - The index of the first line to return.
+/// * `end` - The index after the last line to return. Lines past the end of the file are left out.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the lines, without their `\n` or `\r\n`.
+///
+/// # Example
+///
+/// ```no_run
+/// // The third page of 100 lines.
+/// let page = bbq::read_lines_range("/var/log/app/app.log", 200, 300).unwrap();
+/// ```
+pub fn read_lines_range(file: &str, start: usize, end: usize) -> io::Result<Vec<String>> {
+    let wanted = start..end;
+    let mut lines = Vec::new();
+    if wanted.is_empty() {
+        return Ok(lines);
+    }
+    let mut reader = BufReader::new(fs::File::open(file)?);
+    let mut buffer = Vec::new();
+    for index in 0..end {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+        if wanted.contains(&index) {
+            let line = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+            lines.push(line_to_string(line)?);
+        }
+    }
+    Ok(lines)
+}
+
+/// The lines of a text file, as returned by [`lines`].
+#[derive(Debug)]
+pub struct FileLines {
+    lines: Option<io::Lines<BufReader<fs::File>>>,
+    // An error opening the file, returned as the only item.
+    error: Option<io::Error>,
+}
+
+impl Iterator for FileLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let line = self.lines.as_mut()?.next();
+        if matches!(line, None | Some(Err(_))) {
+            self.lines = None;
+        }
+        line
+    }
+}
+
+/// Reads a text file line by line through a buffer, so only one line is held in memory
+/// at a time.
+///
+/// Lines are returned without their `\n` or `\r\n`. If the file can't be opened, the
+/// only item is the error. An error, such as a line that isn't valid UTF-8, ends the
+/// iteration after it is returned.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to read.
+///
+/// # Returns
+///
+/// * `FileLines` - An iterator over the lines of the file.
+///
+/// # Example
+///
+/// ```no_run
+/// let errors = bbq::lines("/var/log/app/app.log")
+///     .filter_map(Result::ok)
+///     .filter(|line| line.contains("ERROR"))
+///     .count();
+/// ```
+pub fn lines(file: &str) -> FileLines {
+    match fs::File::open(file) {
+        Ok(file) => FileLines {
+            lines: Some(BufReader::new(file).lines()),
+            error: None,
+        },
+        Err(e) => FileLines {
+            lines: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// Like [`lines`], but calls `f` with each line, reusing one buffer.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of lines read. An error returned by `f` stops the reading and is returned.
+///
+/// # Example
+///
+/// ```no_run
+/// let mut errors = 0;
+/// bbq::for_each_line("/var/log/app/app.log", |line| {
+///     if line.contains("ERROR") {
+///         errors += 1;
+///     }
+///     Ok(())
+/// })
+/// .unwrap();
+/// ```
+pub fn for_each_line<F>(file: &str, mut f: F) -> io::Result<u64>
+where
+    F: FnMut(&str) -> io::Result<()>,
+{
+    let mut reader = BufReader::new(fs::File::open(file)?);
+    let mut line = String::new();
+    let mut count = 0u64;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(count);
+        }
+        let trimmed = line.strip_suffix('\n').unwrap_or(&line);
+        f(trimmed.strip_suffix('\r').unwrap_or(trimmed))?;
+        count += 1;
+    }
+}
+
+fn line_to_string(line: &[u8]) -> io::Result<String> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8(line.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Reads until `buffer` is full or the end of the file, returning the bytes read.
+fn fill(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_file_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+        fs::write(&file, &data).unwrap();
+        let file = file.to_str().unwrap();
+
+        let chunks: Vec<Vec<u8>> = read_file_chunks(file, 1000)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let sizes: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [1000, 1000, 500]);
+        assert_eq!(chunks.concat(), data);
+
+        let mut joined = Vec::new();
+        let total = for_each_chunk(file, 1000, |chunk| {
+            joined.extend_from_slice(chunk);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(total, 2500);
+        assert_eq!(joined, data);
+
+        let missing = dir.path().join("missing");
+        let mut chunks = read_file_chunks(missing.to_str().unwrap(), 1000);
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_read_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log");
+        let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&file, &text).unwrap();
+        let file = file.to_str().unwrap();
+
+        assert_eq!(
+            read_last_lines(file, 2).unwrap(),
+            ["line 19998", "line 19999"]
+        );
+        assert_eq!(read_last_lines(file, 20_000).unwrap().len(), 20_000);
+        assert_eq!(read_last_lines(file, 30_000).unwrap()[0], "line 0");
+        assert_eq!(read_last_bytes(file, 6).unwrap(), b"19999\n");
+
+        fs::write(file, "a\r\nb\r\n\nc").unwrap();
+        assert_eq!(read_last_lines(file, 3).unwrap(), ["b", "", "c"]);
+        fs::write(file, "\n").unwrap();
+        assert_eq!(read_last_lines(file, 3).unwrap(), [""]);
+        fs::write(file, "").unwrap();
+        assert!(read_last_lines(file, 3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_lines_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log");
+        fs::write(&file, "zero\r\none\ntwo\nthree").unwrap();
+        let file = file.to_str().unwrap();
+
+        assert_eq!(read_first_lines(file, 2).unwrap(), ["zero", "one"]);
+        assert_eq!(read_first_lines(file, 10).unwrap().len(), 4);
+        assert_eq!(read_lines_range(file, 2, 4).unwrap(), ["two", "three"]);
+        assert!(read_lines_range(file, 5, 8).unwrap().is_empty());
+        assert!(read_lines_range(file, 3, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("app.log");
+        fs::write(&file, "one\r\ntwo\n\nfour").unwrap();
+        let file = file.to_str().unwrap();
+
+        let all: Vec<String> = lines(file).collect::<io::Result<_>>().unwrap();
+        assert_eq!(all, ["one", "two", "", "four"]);
+
+        let mut seen = Vec::new();
+        let count = for_each_line(file, |line| {
+            seen.push(line.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(seen, all);
+
+        let mut missing = lines(dir.path().join("missing").to_str().unwrap());
+        assert!(missing.next().unwrap().is_err());
+        assert!(missing.next().is_none());
+    }
+}
@@ -0,0 +1,66 @@
+use std::io;
+use std::path::Path;
+
+/// Moves a file or directory to the platform trash (freedesktop Trash, macOS Trash or the
+/// Windows Recycle Bin) instead of unlinking it.
+///
+/// Without the `trash` feature this always fails with `ErrorKind::Unsupported`, so a
+/// cleanup configured to use the trash never falls back to deleting permanently.
+pub(crate) fn move_to_trash(path: &Path) -> io::Result<()> {
+    #[cfg(feature = "trash")]
+    {
+        ::trash::delete(path).map_err(io::Error::other)
+    }
+    #[cfg(not(feature = "trash"))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "cannot move {} to the trash: built without the `trash` feature",
+                path.display()
+            ),
+        ))
+    }
+}
+
+/// Moves the specified file to the system trash, so it can be recovered.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::trash_file("/var/log/app/old.log").unwrap();
+/// ```
+#[cfg(feature = "trash")]
+pub fn trash_file(file: &str) -> io::Result<()> {
+    move_to_trash(Path::new(file))
+}
+
+/// Moves the specified directory, with everything in it, to the system trash.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory
+#[cfg(feature = "trash")]
+pub fn trash_dir(dir: &str) -> io::Result<()> {
+    move_to_trash(Path::new(dir))
+}
+
+/// Like [`remove_old_files`](crate::remove_old_files), but moves the files to the system
+/// trash instead of deleting them.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were moved to the trash.
+#[cfg(feature = "trash")]
+pub fn trash_old_files(dir: &str, keep: u64) -> io::Result<Vec<String>> {
+    let mut trashed = Vec::new();
+    for (file, _) in crate::info::select_old_files(dir, keep)? {
+        move_to_trash(&file)?;
+        trashed.push(file.to_string_lossy().into_owned());
+    }
+    Ok(trashed)
+}
@@ -0,0 +1,620 @@
+use crate::hash::{hash_file, sha256_hex};
+use crate::part::write_via_part_file;
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// A storage backend that takes files in parts, like S3 multipart uploads or an SFTP
+/// server written to at offsets.
+///
+/// Backends only move bytes; [`upload_resumable`] takes care of splitting files and of
+/// picking an interrupted upload up where it stopped.
+pub trait Remote {
+    /// Starts uploading an object under `key`, returning an id for the upload.
+    fn begin_upload(&mut self, key: &str) -> io::Result<String>;
+
+    /// Stores part `number` (counting from 1) of an upload, returning a tag identifying
+    /// the stored part.
+    fn upload_part(&mut self, upload_id: &str, number: u32, data: &[u8]) -> io::Result<String>;
+
+    /// Assembles the uploaded parts, in order, into the object under `key`.
+    fn complete_upload(
+        &mut self,
+        upload_id: &str,
+        key: &str,
+        parts: &[UploadedPart],
+    ) -> io::Result<()>;
+
+    /// Describes the object stored under `key`, failing with `ErrorKind::NotFound` if
+    /// there is none.
+    fn head(&mut self, key: &str) -> io::Result<RemoteObject>;
+}
+
+/// What a [`Remote`] knows about a stored object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteObject {
+    pub size: u64,
+    /// The hex SHA-256 of the object, if the remote can tell.
+    pub sha256: Option<String>,
+}
+
+/// A [`Remote`] backed by a local directory, such as a mounted network share.
+///
+/// Parts are staged under `.uploads/<id>` in the root and joined when the upload
+/// completes, so an interrupted upload leaves nothing under its key. Keys must be
+/// relative paths inside the root, and upload ids must be ones it handed out; anything
+/// else returns `ErrorKind::InvalidInput`.
+#[derive(Debug, Clone)]
+pub struct DirRemote {
+    root: PathBuf,
+}
+
+impl DirRemote {
+    /// Uses `root` as the remote, creating it if needed.
+    pub fn open(root: &str) -> io::Result<DirRemote> {
+        fs::create_dir_all(root)?;
+        Ok(DirRemote {
+            root: PathBuf::from(root),
+        })
+    }
+
+    /// The directory objects are stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn staging(&self, upload_id: &str) -> io::Result<PathBuf> {
+        if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not an upload id", upload_id),
+            ));
+        }
+        Ok(self.root.join(".uploads").join(upload_id))
+    }
+
+    fn object(&self, key: &str) -> io::Result<PathBuf> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a relative key inside the remote", key),
+            )
+        };
+        let mut path = self.root.clone();
+        for component in Path::new(key).components() {
+            match component {
+                Component::Normal(part) if path == self.root && part == ".uploads" => {
+                    return Err(invalid())
+                }
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {}
+                _ => return Err(invalid()),
+            }
+        }
+        if path == self.root {
+            return Err(invalid());
+        }
+        Ok(path)
+    }
+}
+
+impl Remote for DirRemote {
+    fn begin_upload(&mut self, key: &str) -> io::Result<String> {
+        self.object(key)?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let id = sha256_hex(format!("{}\0{}", key, nanos).as_bytes())[..16].to_string();
+        fs::create_dir_all(self.staging(&id)?)?;
+        Ok(id)
+    }
+
+    fn upload_part(&mut self, upload_id: &str, number: u32, data: &[u8]) -> io::Result<String> {
+        let staging = self.staging(upload_id)?;
+        if !staging.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no upload with id {}", upload_id),
+            ));
+        }
+        fs::write(staging.join(format!("part-{}", number)), data)?;
+        Ok(sha256_hex(data))
+    }
+
+    fn complete_upload(
+        &mut self,
+        upload_id: &str,
+        key: &str,
+        parts: &[UploadedPart],
+    ) -> io::Result<()> {
+        let staging = self.staging(upload_id)?;
+        let dest = self.object(key)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = staging.join("object");
+        let mut object = fs::File::create(&tmp)?;
+        for part in parts {
+            let mut data = fs::File::open(staging.join(format!("part-{}", part.number)))?;
+            io::copy(&mut data, &mut object)?;
+        }
+        object.sync_all()?;
+        fs::rename(&tmp, &dest)?;
+        fs::remove_dir_all(staging)
+    }
+
+    fn head(&mut self, key: &str) -> io::Result<RemoteObject> {
+        let path = self.object(key)?;
+        let size = fs::metadata(&path)?.len();
+        let sha256 = hash_file(&path.to_string_lossy())?;
+        Ok(RemoteObject {
+            size,
+            sha256: Some(sha256),
+        })
+    }
+}
+
+/// One part of an upload that the remote has acknowledged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedPart {
+    /// The part number, counting from 1.
+    pub number: u32,
+    /// Where the part starts in the source file.
+    pub offset: u64,
+    pub len: u64,
+    /// The tag the remote returned for the part.
+    pub tag: String,
+}
+
+/// The progress of an upload, saved after every part so an interrupted upload can
+/// continue instead of starting over.
+///
+/// Like [`HashState`](crate::HashState), the journal remembers the size and
+/// modification time of the source, and is only resumed if the file is unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadJournal {
+    pub source: String,
+    pub key: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub part_size: u64,
+    pub upload_id: String,
+    /// The parts uploaded so far, in order.
+    pub parts: Vec<UploadedPart>,
+}
+
+impl UploadJournal {
+    /// Writes the journal to `file` as JSON, replacing it atomically.
+    pub fn save(&self, file: &str) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        write_via_part_file(file, &json)
+    }
+
+    /// Reads a journal previously written with [`UploadJournal::save`].
+    pub fn load(file: &str) -> io::Result<UploadJournal> {
+        let json = fs::read(file)?;
+        serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The number of bytes already uploaded.
+    pub fn uploaded(&self) -> u64 {
+        self.parts.iter().map(|part| part.len).sum()
+    }
+}
+
+/// Uploads `file` to `remote` under `key` in parts of `part_size` bytes, keeping an
+/// [`UploadJournal`] at `journal`.
+///
+/// If the journal exists and matches the file, the upload continues after the last part
+/// it records; otherwise a new upload is started. The journal is removed once the upload
+/// completes.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to upload
+/// * `key` - The name to store the file under on the remote
+/// * `remote` - The remote to upload to
+/// * `part_size` - The size of each part, in bytes
+/// * `journal` - A string slice that holds the name of the journal file
+///
+/// # Returns
+///
+/// * `std::io::Result<Stats>` - A Result containing the cost of this run; `bytes_read` only counts the parts uploaded by this call, and `retries` is 1 when an interrupted upload was resumed.
+///
+/// # Example
+///
+/// ```no_run
+/// let mut remote = bbq::DirRemote::open("/mnt/backup").unwrap();
+/// bbq::upload_resumable(
+///     "/srv/vm/disk.img",
+///     "vm/disk.img",
+///     &mut remote,
+///     64 * 1024 * 1024,
+///     "/var/lib/app/disk.img.upload.json",
+/// )
+/// .unwrap();
+/// ```
+pub fn upload_resumable(
+    file: &str,
+    key: &str,
+    remote: &mut dyn Remote,
+    part_size: u64,
+    journal: &str,
+) -> io::Result<Stats> {
+    if part_size == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "part size must be greater than zero",
+        ));
+    }
+    let started = Instant::now();
+    let metadata = fs::metadata(file)?;
+    let resumed = UploadJournal::load(journal).ok().filter(|state| {
+        state.source == file
+            && state.key == key
+            && state.size == metadata.len()
+            && state.modified == metadata.modified().ok()
+            && state.part_size == part_size
+    });
+    let retries = u64::from(resumed.is_some());
+    let mut state = match resumed {
+        Some(state) => state,
+        None => UploadJournal {
+            source: file.to_string(),
+            key: key.to_string(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            part_size,
+            upload_id: remote.begin_upload(key)?,
+            parts: Vec::new(),
+        },
+    };
+
+    let mut stats = Stats {
+        files_visited: 1,
+        retries,
+        ..Default::default()
+    };
+    let mut source = fs::File::open(file)?;
+    let mut offset = state.uploaded();
+    source.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::new();
+    while offset < state.size {
+        let len = part_size.min(state.size - offset);
+        buffer.clear();
+        (&mut source).take(len).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} changed while it was being uploaded", file),
+            ));
+        }
+        let number = state.parts.len() as u32 + 1;
+        let tag = remote.upload_part(&state.upload_id, number, &buffer)?;
+        state.parts.push(UploadedPart {
+            number,
+            offset,
+            len,
+            tag,
+        });
+        state.save(journal)?;
+        offset += len;
+        stats.bytes_read += len;
+    }
+
+    remote.complete_upload(&state.upload_id, key, &state.parts)?;
+    match fs::remove_file(journal) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    stats.duration = started.elapsed();
+    Ok(stats)
+}
+
+// The part size used by [`ship_files`].
+const SHIP_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How [`ship_files`] checks an upload before deleting the local copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verify {
+    /// Trust the upload.
+    None,
+    /// Compare the size of the remote object with the local file.
+    #[default]
+    Size,
+    /// Compare sizes and SHA-256 checksums. Fails if the remote cannot report a checksum.
+    Checksum,
+}
+
+/// The outcome of [`ship_files`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShipReport {
+    /// The files uploaded and verified.
+    pub shipped: Vec<String>,
+    /// The shipped files whose local copies were deleted.
+    pub deleted: Vec<String>,
+    /// Files that failed to upload or verify, with the error. Their local copies are
+    /// always kept.
+    pub errors: Vec<(String, String)>,
+    pub stats: Stats,
+}
+
+/// Uploads files to `remote`, verifies each upload, and only then deletes the local copy.
+///
+/// Each file is stored under its file name, so files with the same name from different
+/// directories can't be shipped together: if two paths would be stored under the same
+/// name, an `ErrorKind::InvalidInput` error is returned before anything is uploaded.
+/// Uploads go through [`upload_resumable`] with
+/// a hidden `.<name>.bbq-upload.json` journal next to the file, so shipping the same file
+/// again after an interruption continues where it stopped. A file that fails is recorded
+/// in the report and the rest are still shipped.
+///
+/// # Arguments
+///
+/// * `paths` - The files to ship
+/// * `remote` - The remote to upload to
+/// * `verify` - How to check each upload
+/// * `delete_after` - Whether to delete local copies once they are verified
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{DirRemote, Verify};
+///
+/// let mut remote = DirRemote::open("/mnt/archive/spool").unwrap();
+/// let files = bbq::get_files(std::path::Path::new("/var/spool/outbox")).unwrap();
+/// let paths: Vec<&str> = files.iter().filter_map(|f| f.to_str()).collect();
+/// let report = bbq::ship_files(&paths, &mut remote, Verify::Checksum, true).unwrap();
+/// println!("{} shipped, {} failed", report.shipped.len(), report.errors.len());
+/// ```
+pub fn ship_files(
+    paths: &[&str],
+    remote: &mut dyn Remote,
+    verify: Verify,
+    delete_after: bool,
+) -> io::Result<ShipReport> {
+    let started = Instant::now();
+    let mut keys: HashMap<String, &str> = HashMap::new();
+    for &path in paths {
+        // Missing names are reported per file below.
+        let Ok(key) = file_key(path) else {
+            continue;
+        };
+        if let Some(other) = keys.insert(key.clone(), path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} and {} would both be stored as {}", other, path, key),
+            ));
+        }
+    }
+    let mut report = ShipReport::default();
+    for &path in paths {
+        match file_key(path).and_then(|key| ship_file(path, &key, remote, verify)) {
+            Ok(stats) => {
+                report.stats.merge(&stats);
+                report.shipped.push(path.to_string());
+            }
+            Err(e) => {
+                report.errors.push((path.to_string(), e.to_string()));
+                continue;
+            }
+        }
+        if delete_after {
+            match fs::remove_file(path) {
+                Ok(()) => report.deleted.push(path.to_string()),
+                Err(e) => report.errors.push((path.to_string(), e.to_string())),
+            }
+        }
+    }
+    report.stats.duration = started.elapsed();
+    Ok(report)
+}
+
+// The key a file is stored under by ship_files: its file name.
+pub(crate) fn file_key(path: &str) -> io::Result<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file name", path),
+            )
+        })
+}
+
+// Uploads `path` as `key`, then verifies the upload.
+pub(crate) fn ship_file(
+    path: &str,
+    key: &str,
+    remote: &mut dyn Remote,
+    verify: Verify,
+) -> io::Result<Stats> {
+    let file = Path::new(path);
+    let journal = file.with_file_name(format!(".{}.bbq-upload.json", file_key(path)?));
+    let mut stats = upload_resumable(
+        path,
+        key,
+        remote,
+        SHIP_PART_SIZE,
+        &journal.to_string_lossy(),
+    )?;
+    if verify == Verify::None {
+        return Ok(stats);
+    }
+    let object = remote.head(key)?;
+    let size = fs::metadata(path)?.len();
+    if object.size != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is {} bytes on the remote, {} locally",
+                key, object.size, size
+            ),
+        ));
+    }
+    if verify == Verify::Checksum {
+        let local = hash_file(path)?;
+        stats.bytes_read += size;
+        match object.sha256 {
+            Some(remote_sum) if remote_sum == local => {}
+            Some(remote_sum) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "checksum mismatch for {}: {} remote, {} local",
+                        key, remote_sum, local
+                    ),
+                ))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("the remote has no checksum for {}", key),
+                ))
+            }
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails every part after the first `allowed`, like a dropped connection.
+    struct Flaky {
+        inner: DirRemote,
+        allowed: usize,
+        sent: usize,
+    }
+
+    impl Remote for Flaky {
+        fn begin_upload(&mut self, key: &str) -> io::Result<String> {
+            self.inner.begin_upload(key)
+        }
+
+        fn upload_part(&mut self, id: &str, number: u32, data: &[u8]) -> io::Result<String> {
+            if self.sent == self.allowed {
+                return Err(io::Error::other("connection reset"));
+            }
+            self.sent += 1;
+            self.inner.upload_part(id, number, data)
+        }
+
+        fn complete_upload(
+            &mut self,
+            id: &str,
+            key: &str,
+            parts: &[UploadedPart],
+        ) -> io::Result<()> {
+            self.inner.complete_upload(id, key, parts)
+        }
+
+        fn head(&mut self, key: &str) -> io::Result<RemoteObject> {
+            self.inner.head(key)
+        }
+    }
+
+    #[test]
+    fn test_dir_remote_rejects_escaping_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+        for key in ["../escape", "/etc/passwd", ".uploads/x", ""] {
+            let err = remote.begin_upload(key).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", key);
+            assert_eq!(
+                remote.head(key).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+        let id = remote.begin_upload("ok/a.bin").unwrap();
+        let err = remote.complete_upload(&id, "../a.bin", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = remote
+            .complete_upload("../..", "ok/a.bin", &[])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(dir.path().join("remote").exists());
+    }
+
+    #[test]
+    fn test_upload_resumes_from_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&file, &data).unwrap();
+        let file = file.to_str().unwrap();
+        let journal = dir.path().join("data.upload.json");
+        let journal = journal.to_str().unwrap();
+        let remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+
+        let mut flaky = Flaky {
+            inner: remote.clone(),
+            allowed: 3,
+            sent: 0,
+        };
+        assert!(upload_resumable(file, "a/data.bin", &mut flaky, 1024, journal).is_err());
+        assert_eq!(UploadJournal::load(journal).unwrap().parts.len(), 3);
+
+        flaky.allowed = usize::MAX;
+        flaky.sent = 0;
+        let stats = upload_resumable(file, "a/data.bin", &mut flaky, 1024, journal).unwrap();
+        assert_eq!(flaky.sent, 7);
+        assert_eq!(stats.bytes_read, 10_000 - 3 * 1024);
+        assert_eq!(stats.retries, 1);
+        assert_eq!(fs::read(remote.root().join("a/data.bin")).unwrap(), data);
+        assert!(!Path::new(journal).exists());
+    }
+
+    #[test]
+    fn test_ship_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = dir.path().join("spool");
+        fs::create_dir_all(&spool).unwrap();
+        fs::write(spool.join("a.json"), "a").unwrap();
+        fs::write(spool.join("b.json"), "bb").unwrap();
+        let mut remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+
+        let a = spool.join("a.json");
+        let b = spool.join("b.json");
+        let missing = spool.join("missing.json");
+        let paths = [
+            a.to_str().unwrap(),
+            missing.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ];
+        let report = ship_files(&paths, &mut remote, Verify::Checksum, true).unwrap();
+        assert_eq!(report.shipped.len(), 2);
+        assert_eq!(report.deleted.len(), 2);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            fs::read_to_string(remote.root().join("b.json")).unwrap(),
+            "bb"
+        );
+        assert_eq!(fs::read_dir(&spool).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_ship_files_refuses_duplicate_names() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["a", "b"] {
+            fs::create_dir_all(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("data.json"), sub).unwrap();
+        }
+        let mut remote = DirRemote::open(dir.path().join("remote").to_str().unwrap()).unwrap();
+        let a = dir.path().join("a/data.json");
+        let b = dir.path().join("b/data.json");
+        let paths = [a.to_str().unwrap(), b.to_str().unwrap()];
+
+        let err = ship_files(&paths, &mut remote, Verify::Size, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(a.exists() && b.exists());
+        assert!(!remote.root().join("data.json").exists());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::disk::ensure_writable;
+use crate::managed::is_protected_path;
+use crate::progress::CancelToken;
+use crate::telemetry;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How far [`remove_dir_with_progress`] has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoveProgress {
+    pub files_removed: u64,
+    pub dirs_removed: u64,
+    pub bytes_freed: u64,
+}
+
+/// Options for [`remove_dir_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct RemoveDirOptions {
+    /// Renames the directory to a hidden sibling before deleting it, so the path is free
+    /// (and can be recreated) at once, while the contents are deleted.
+    pub rename_first: bool,
+    /// Stops the deletion when cancelled, leaving what is left in place.
+    pub cancel: CancelToken,
+}
+
+/// Removes a directory and everything in it, like [`remove_dir`](crate::remove_dir), calling
+/// `progress` after every entry removed.
+///
+/// Unlike `remove_dir`, the tree is deleted one entry at a time while it is read, so
+/// memory use depends on the depth of the tree rather than the number of entries, and a
+/// directory holding millions of files shows steady progress instead of blocking
+/// silently. Symlinks are removed, never followed. A protected directory (see
+/// [`is_protected_path`]) is refused with `ErrorKind::PermissionDenied`.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to remove.
+/// * `progress` - Called with the running totals after each file or directory removed.
+///
+/// # Returns
+///
+/// * `std::io::Result<RemoveProgress>` - A Result containing the totals removed.
+///
+/// # Example
+///
+/// ```no_run
+/// let done = bbq::remove_dir_with_progress("/srv/cache/old", |p| {
+///     if p.files_removed % 100_000 == 0 {
+///         println!("{} files, {} bytes", p.files_removed, p.bytes_freed);
+///     }
+/// })
+/// .unwrap();
+/// ```
+pub fn remove_dir_with_progress<F>(dir: &str, progress: F) -> io::Result<RemoveProgress>
+where
+    F: FnMut(&RemoveProgress),
+{
+    remove_dir_with_options(dir, &RemoveDirOptions::default(), progress)
+}
+
+/// Like [`remove_dir_with_progress`], with cancellation and rename-then-delete.
+///
+/// With `rename_first`, the directory is first renamed to `.<name>.deleting-<id>` next to
+/// it, and the renamed tree is deleted. If the run is cancelled or fails, that is where
+/// the rest is left.
+///
+/// # Returns
+///
+/// * `std::io::Result<RemoveProgress>` - A Result containing the totals removed. Cancelling returns `ErrorKind::Interrupted`.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{remove_dir_with_options, CancelToken, RemoveDirOptions};
+///
+/// let options = RemoveDirOptions { rename_first: true, cancel: CancelToken::new() };
+/// remove_dir_with_options("/srv/builds/1234", &options, |_| {}).unwrap();
+/// ```
+pub fn remove_dir_with_options<F>(
+    dir: &str,
+    options: &RemoveDirOptions,
+    mut progress: F,
+) -> io::Result<RemoveProgress>
+where
+    F: FnMut(&RemoveProgress),
+{
+    let path = Path::new(dir);
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", dir),
+        ));
+    }
+    if is_protected_path(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("refusing to remove protected directory {}", dir),
+        ));
+    }
+    ensure_writable(dir)?;
+    let _span = telemetry::enter("remove_dir", path);
+    let root = if options.rename_first {
+        let renamed = deleting_name(path);
+        fs::rename(path, &renamed)?;
+        renamed
+    } else {
+        path.to_path_buf()
+    };
+
+    let mut totals = RemoveProgress::default();
+    let mut stack = vec![(root.clone(), fs::read_dir(&root)?)];
+    while let Some((current, entries)) = stack.last_mut() {
+        options.cancel.check()?;
+        let Some(entry) = entries.next() else {
+            let current = current.clone();
+            stack.pop();
+            match fs::remove_dir(&current) {
+                Ok(()) => {
+                    totals.dirs_removed += 1;
+                    progress(&totals);
+                }
+                // Entries created while the directory was being read; go round again.
+                Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                    let entries = fs::read_dir(&current)?;
+                    stack.push((current, entries));
+                }
+                Err(e) => return Err(e),
+            }
+            continue;
+        };
+        let entry = entry?;
+        let entry_path = entry.path();
+        if entry.file_type()?.is_dir() {
+            let entries = fs::read_dir(&entry_path)?;
+            stack.push((entry_path, entries));
+            continue;
+        }
+        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        match fs::remove_file(&entry_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+        telemetry::file_removed(&entry_path, size);
+        totals.files_removed += 1;
+        totals.bytes_freed += size;
+        progress(&totals);
+    }
+    Ok(totals)
+}
+
+fn deleting_name(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    path.with_file_name(format!(
+        ".{}.deleting-{}-{}",
+        name,
+        std::process::id(),
+        nanos
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_dir_with_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("big");
+        fs::create_dir_all(target.join("a/b")).unwrap();
+        for i in 0..50 {
+            fs::write(target.join(format!("a/{}", i)), "xx").unwrap();
+        }
+        fs::write(target.join("a/b/deep"), "x").unwrap();
+
+        let mut calls = 0;
+        let totals = remove_dir_with_progress(target.to_str().unwrap(), |_| calls += 1).unwrap();
+        assert_eq!(totals.files_removed, 51);
+        assert_eq!(totals.dirs_removed, 3);
+        assert_eq!(totals.bytes_freed, 101);
+        assert_eq!(calls, 54);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_remove_dir_cancel_and_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("builds");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a"), "x").unwrap();
+        let options = RemoveDirOptions {
+            rename_first: true,
+            cancel: CancelToken::new(),
+        };
+        options.cancel.cancel();
+
+        let err = remove_dir_with_options(target.to_str().unwrap(), &options, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        // The path is free at once; the contents wait under the hidden name.
+        assert!(!target.exists());
+        let left: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(left.len(), 1);
+    }
+}
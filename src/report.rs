@@ -0,0 +1,550 @@
+use crate::info::FileInfo;
+use crate::walk::{walk_with, SymlinkPolicy, Visit, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Finds leaf subdirectories whose newest file is older than `max_age`.
+///
+/// A leaf directory is one without subdirectories, such as the per-job or per-day
+/// folders at the bottom of a dated layout (`2024/01/15/`). A leaf is stale only when
+/// *every* file in it is older than the threshold; an empty leaf is judged by its own
+/// modification time. The top-level `dir` itself is never reported.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to scan.
+/// * `max_age` - How old the newest file of a leaf directory must be for it to be reported.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the paths of the stale leaf directories.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let stale = bbq::find_stale_dirs("/data/jobs", Duration::from_secs(7 * 24 * 3600)).unwrap();
+/// for dir in stale {
+///     bbq::remove_dir(&dir).unwrap();
+/// }
+/// ```
+pub fn find_stale_dirs(dir: &str, max_age: Duration) -> io::Result<Vec<String>> {
+    let cutoff = SystemTime::now()
+        .checked_sub(max_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut stale = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_stale_dirs(&entry.path(), cutoff, &mut stale)?;
+        }
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+fn collect_stale_dirs(dir: &Path, cutoff: SystemTime, stale: &mut Vec<String>) -> io::Result<()> {
+    let mut newest: Option<SystemTime> = None;
+    let mut is_leaf = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            is_leaf = false;
+            collect_stale_dirs(&entry.path(), cutoff, stale)?;
+        } else if file_type.is_file() {
+            let modified = entry.metadata()?.modified()?;
+            newest = Some(newest.map_or(modified, |n| n.max(modified)));
+        }
+    }
+    if is_leaf {
+        let newest = match newest {
+            Some(newest) => newest,
+            None => fs::metadata(dir)?.modified()?,
+        };
+        if newest < cutoff {
+            stale.push(dir.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// A kind of permission problem reported by [`find_permission_anomalies`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PermissionAnomalyKind {
+    /// Anyone may write to it (directories with the sticky bit are not reported).
+    WorldWritable,
+    /// The setuid bit is set.
+    Setuid,
+    /// The setgid bit is set on a file.
+    Setgid,
+    /// It has permission bits outside the expected mask.
+    UnexpectedMode,
+}
+
+/// A file or directory with suspicious permissions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionAnomaly {
+    pub path: String,
+    /// The permission bits, e.g. `0o4755`.
+    pub mode: u32,
+    pub kinds: Vec<PermissionAnomalyKind>,
+}
+
+/// Scans a tree for world-writable entries, setuid/setgid files and unexpected modes.
+///
+/// Symlinks are not followed or reported.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to scan.
+/// * `expected_mode_mask` - The permission bits entries may have, e.g. `0o755`. Any bit outside the mask is reported as [`PermissionAnomalyKind::UnexpectedMode`]. Pass `0o7777` to only report the built-in checks.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PermissionAnomaly>>` - A Result containing every anomalous entry under `dir`.
+///
+/// # Example
+///
+/// ```no_run
+/// for anomaly in bbq::find_permission_anomalies("/srv/www", 0o755).unwrap() {
+///     println!("{} {:o} {:?}", anomaly.path, anomaly.mode, anomaly.kinds);
+/// }
+/// ```
+#[cfg(unix)]
+pub fn find_permission_anomalies(
+    dir: &str,
+    expected_mode_mask: u32,
+) -> io::Result<Vec<PermissionAnomaly>> {
+    let mut anomalies = Vec::new();
+    collect_permission_anomalies(Path::new(dir), expected_mode_mask, &mut anomalies)?;
+    Ok(anomalies)
+}
+
+#[cfg(unix)]
+fn collect_permission_anomalies(
+    dir: &Path,
+    mask: u32,
+    anomalies: &mut Vec<PermissionAnomaly>,
+) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        let mode = metadata.permissions().mode() & 0o7777;
+        let mut kinds = Vec::new();
+        let sticky_dir = metadata.is_dir() && mode & 0o1000 != 0;
+        if mode & 0o002 != 0 && !sticky_dir {
+            kinds.push(PermissionAnomalyKind::WorldWritable);
+        }
+        if metadata.is_file() && mode & 0o4000 != 0 {
+            kinds.push(PermissionAnomalyKind::Setuid);
+        }
+        if metadata.is_file() && mode & 0o2000 != 0 {
+            kinds.push(PermissionAnomalyKind::Setgid);
+        }
+        if mode & !mask != 0 {
+            kinds.push(PermissionAnomalyKind::UnexpectedMode);
+        }
+        if !kinds.is_empty() {
+            anomalies.push(PermissionAnomaly {
+                path: entry.path().to_string_lossy().into_owned(),
+                mode,
+                kinds,
+            });
+        }
+        if metadata.is_dir() {
+            collect_permission_anomalies(&entry.path(), mask, anomalies)?;
+        }
+    }
+    Ok(())
+}
+
+/// A set of paths that are hard links to the same inode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardlinkGroup {
+    pub device: u64,
+    pub inode: u64,
+    /// The size of the shared data, stored once on disk.
+    pub size: u64,
+    /// The total number of links to the inode, including ones outside the scanned tree.
+    pub nlink: u64,
+    /// The links found in the scanned tree.
+    pub paths: Vec<String>,
+}
+
+/// Finds files under `dir` that share an inode with at least one other link.
+///
+/// Shared extents from reflinks (`cp --reflink`, btrfs/XFS dedupe) are not visible
+/// through portable metadata and are not reported.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to scan.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<HardlinkGroup>>` - A Result containing one group per multiply-linked inode, sorted by path.
+#[cfg(unix)]
+pub fn hardlink_groups(dir: &str) -> io::Result<Vec<HardlinkGroup>> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::MetadataExt;
+    let mut groups: HashMap<(u64, u64), HardlinkGroup> = HashMap::new();
+    for file in crate::info::get_files(Path::new(dir))? {
+        let metadata = fs::symlink_metadata(&file)?;
+        if metadata.nlink() < 2 {
+            continue;
+        }
+        groups
+            .entry((metadata.dev(), metadata.ino()))
+            .or_insert_with(|| HardlinkGroup {
+                device: metadata.dev(),
+                inode: metadata.ino(),
+                size: metadata.len(),
+                nlink: metadata.nlink(),
+                paths: Vec::new(),
+            })
+            .paths
+            .push(file.to_string_lossy().into_owned());
+    }
+    let mut groups: Vec<HardlinkGroup> = groups
+        .into_values()
+        .map(|mut group| {
+            group.paths.sort();
+            group
+        })
+        .collect();
+    groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+    Ok(groups)
+}
+
+/// Computes how many bytes deleting `files` would actually free.
+///
+/// Each inode is counted once, and only if every one of its hard links is in `files`;
+/// data still reachable through another link is not freed. Reflinked extents cannot be
+/// detected and are counted as freed.
+///
+/// # Arguments
+///
+/// * `files` - The files that would be deleted.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the number of bytes that would be freed.
+#[cfg(unix)]
+pub fn freed_size(files: &[std::path::PathBuf]) -> io::Result<u64> {
+    use std::collections::HashMap;
+    use std::os::unix::fs::MetadataExt;
+    let mut inodes: HashMap<(u64, u64), (u64, u64, u64)> = HashMap::new();
+    for file in files {
+        let metadata = fs::symlink_metadata(file)?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let seen = inodes.entry((metadata.dev(), metadata.ino())).or_insert((
+            metadata.len(),
+            metadata.nlink(),
+            0,
+        ));
+        seen.2 += 1;
+    }
+    Ok(inodes
+        .values()
+        .filter(|(_, nlink, seen)| seen >= nlink)
+        .map(|(size, _, _)| size)
+        .sum())
+}
+
+/// The files of one extension in [`DirStats::by_extension`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionStats {
+    pub files: u64,
+    pub size: u64,
+}
+
+/// A summary of a directory tree, from [`dir_stats`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirStats {
+    pub files: u64,
+    /// The subdirectories, not counting the directory itself.
+    pub dirs: u64,
+    /// The total size of the files.
+    pub total_size: u64,
+    pub largest_file: Option<FileInfo>,
+    /// The files with the latest and earliest modification times.
+    pub newest_file: Option<FileInfo>,
+    pub oldest_file: Option<FileInfo>,
+    /// File counts and sizes per lowercase extension; files without one are under `""`.
+    pub by_extension: BTreeMap<String, ExtensionStats>,
+}
+
+/// Summarizes the tree under `dir` in one traversal: how many files and directories it
+/// holds, their total size, the largest, newest and oldest files, and the size taken by
+/// each extension.
+///
+/// Symlinks are not followed or counted. Entries that can't be read are skipped.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to scan.
+///
+/// # Returns
+///
+/// * `std::io::Result<DirStats>` - A Result containing the summary.
+///
+/// # Example
+///
+/// ```no_run
+/// let stats = bbq::dir_stats("/var/log").unwrap();
+/// println!("{} files, {} bytes", stats.files, stats.total_size);
+/// for (extension, ext) in &stats.by_extension {
+///     println!("{:>8}: {} bytes", extension, ext.size);
+/// }
+/// ```
+pub fn dir_stats(dir: &str) -> io::Result<DirStats> {
+    let mut stats = DirStats::default();
+    let options = WalkOptions {
+        symlinks: SymlinkPolicy::Skip,
+        ..Default::default()
+    };
+    walk_with::<(), _>(dir, &options, |info| {
+        if info.file_type == "Directory" {
+            stats.dirs += 1;
+        } else if info.file_type == "File" {
+            stats.files += 1;
+            stats.total_size += info.size;
+            let extension = info.extension.as_deref().unwrap_or_default().to_lowercase();
+            let ext = stats.by_extension.entry(extension).or_default();
+            ext.files += 1;
+            ext.size += info.size;
+            if stats
+                .largest_file
+                .as_ref()
+                .is_none_or(|largest| info.size > largest.size)
+            {
+                stats.largest_file = Some(info.clone());
+            }
+            if stats
+                .newest_file
+                .as_ref()
+                .is_none_or(|newest| info.modified_time > newest.modified_time)
+            {
+                stats.newest_file = Some(info.clone());
+            }
+            if stats
+                .oldest_file
+                .as_ref()
+                .is_none_or(|oldest| info.modified_time < oldest.modified_time)
+            {
+                stats.oldest_file = Some(info.clone());
+            }
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    Ok(stats)
+}
+
+/// One subdirectory in a [`disk_usage`] report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirUsage {
+    pub path: String,
+    /// How far below the scanned directory it is: 1 for its direct subdirectories.
+    pub depth: usize,
+    /// The total size of the files under it, at any depth.
+    pub size: u64,
+    pub files: u64,
+}
+
+/// Reports every subdirectory of `dir` down to `depth` levels with the total size of the
+/// files under it, largest first, like `du -d <depth>`.
+///
+/// Sizes include files at any depth, not just down to `depth`; `depth` only limits which
+/// directories are listed. The tree is walked once. Symlinks are not followed or counted,
+/// and entries that can't be read are skipped.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory to scan.
+/// * `depth` - How many levels of subdirectories to list.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<DirUsage>>` - A Result containing the subdirectories, by size descending, then by path.
+///
+/// # Example
+///
+/// ```no_run
+/// for usage in bbq::disk_usage("/srv", 2).unwrap().iter().take(20) {
+///     println!("{:>12} {}", usage.size, usage.path);
+/// }
+/// ```
+pub fn disk_usage(dir: &str, depth: usize) -> io::Result<Vec<DirUsage>> {
+    let root = Path::new(dir);
+    let mut usage: HashMap<PathBuf, DirUsage> = HashMap::new();
+    let options = WalkOptions {
+        symlinks: SymlinkPolicy::Skip,
+        ..Default::default()
+    };
+    walk_with::<(), _>(dir, &options, |info| {
+        let path = Path::new(&info.file_path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let levels = relative.components().count();
+        if info.file_type == "Directory" && levels <= depth {
+            usage.entry(path.to_path_buf()).or_insert_with(|| DirUsage {
+                path: info.file_path.clone(),
+                depth: levels,
+                size: 0,
+                files: 0,
+            });
+        } else if info.file_type == "File" {
+            // Only the directories down to `depth` are in the map.
+            for ancestor in path.ancestors().skip(1).take(levels - 1) {
+                if let Some(entry) = usage.get_mut(ancestor) {
+                    entry.size += info.size;
+                    entry.files += 1;
+                }
+            }
+        }
+        ControlFlow::Continue(Visit::Descend)
+    })?;
+    let mut usage: Vec<DirUsage> = usage.into_values().collect();
+    usage.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_age(path: &Path, age_secs: u64) {
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_stale_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_job = dir.path().join("2024/01/01");
+        let mixed_job = dir.path().join("2024/01/02");
+        fs::create_dir_all(&old_job).unwrap();
+        fs::create_dir_all(&mixed_job).unwrap();
+        for (path, age) in [
+            (old_job.join("a.out"), 7200),
+            (mixed_job.join("a.out"), 7200),
+            (mixed_job.join("b.out"), 10),
+        ] {
+            fs::write(&path, "x").unwrap();
+            set_age(&path, age);
+        }
+
+        let stale =
+            find_stale_dirs(dir.path().to_str().unwrap(), Duration::from_secs(3600)).unwrap();
+        assert_eq!(stale, vec![old_job.to_string_lossy().into_owned()]);
+    }
+
+    #[test]
+    fn test_dir_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        for (name, size, age) in [("a/x.LOG", 10, 300), ("a/b/y.log", 30, 10), ("z", 5, 100)] {
+            let path = dir.path().join(name);
+            fs::write(&path, vec![b'x'; size]).unwrap();
+            set_age(&path, age);
+        }
+
+        let stats = dir_stats(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!((stats.files, stats.dirs, stats.total_size), (3, 2, 45));
+        assert_eq!(stats.largest_file.unwrap().file_name, "y.log");
+        assert_eq!(stats.newest_file.unwrap().file_name, "y.log");
+        assert_eq!(stats.oldest_file.unwrap().file_name, "x.LOG");
+        assert_eq!(
+            stats.by_extension["log"],
+            ExtensionStats { files: 2, size: 40 }
+        );
+        assert_eq!(stats.by_extension[""].size, 5);
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("big/deeper/deepest")).unwrap();
+        fs::create_dir_all(dir.path().join("small")).unwrap();
+        fs::create_dir_all(dir.path().join("empty")).unwrap();
+        fs::write(dir.path().join("big/deeper/deepest/a"), vec![0u8; 100]).unwrap();
+        fs::write(dir.path().join("big/b"), vec![0u8; 10]).unwrap();
+        fs::write(dir.path().join("small/c"), vec![0u8; 5]).unwrap();
+        fs::write(dir.path().join("top"), vec![0u8; 1000]).unwrap();
+
+        let usage = disk_usage(dir.path().to_str().unwrap(), 2).unwrap();
+        let summary: Vec<(String, usize, u64)> = usage
+            .iter()
+            .map(|u| {
+                let relative = Path::new(&u.path).strip_prefix(dir.path()).unwrap();
+                (relative.to_string_lossy().into_owned(), u.depth, u.size)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("big".to_string(), 1, 110),
+                ("big/deeper".to_string(), 2, 100),
+                ("small".to_string(), 1, 5),
+                ("empty".to_string(), 1, 0),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_permission_anomalies() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let fine = dir.path().join("fine.sh");
+        let open = dir.path().join("open.txt");
+        fs::write(&fine, "").unwrap();
+        fs::write(&open, "").unwrap();
+        fs::set_permissions(&fine, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&open, fs::Permissions::from_mode(0o666)).unwrap();
+
+        let anomalies = find_permission_anomalies(dir.path().to_str().unwrap(), 0o755).unwrap();
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].mode, 0o666);
+        assert_eq!(
+            anomalies[0].kinds,
+            vec![
+                PermissionAnomalyKind::WorldWritable,
+                PermissionAnomalyKind::UnexpectedMode
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.bin");
+        let b = dir.path().join("b.bin");
+        fs::write(&a, vec![0u8; 100]).unwrap();
+        fs::hard_link(&a, &b).unwrap();
+
+        let groups = hardlink_groups(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].paths.len(), 2);
+        assert_eq!(freed_size(std::slice::from_ref(&a)).unwrap(), 0);
+        assert_eq!(freed_size(&[a, b]).unwrap(), 100);
+    }
+}
@@ -0,0 +1,523 @@
+use crate::archive::civil_from_days;
+use crate::disk::{disk_space, ensure_writable, FreeSpace};
+use crate::filter::{compile_globs, glob_matches};
+use crate::manifest::{EntryKind, Manifest, ManifestEntry};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Why a file was selected for removal by a [`RetentionPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RetentionReason {
+    /// It is older than the maximum age.
+    Age,
+    /// It is not among the newest `max_files` files.
+    Count,
+    /// It had to go for the directory to fit in the maximum size.
+    Size,
+    /// It is not the representative of its week or month under a [`GfsRule`].
+    Gfs,
+    /// It had to go for the filesystem to have the minimum free space.
+    FreeSpace,
+}
+
+/// A grandfather-father-son rotation: keep everything recent, then thin out to one file
+/// per week, then one per month.
+///
+/// Weeks start on Monday and months are calendar months, both in UTC. The newest file of
+/// each bucket is the one kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GfsRule {
+    /// Every file younger than this is kept.
+    pub keep_all: Duration,
+    /// The number of weeks, counting the current one, to keep one file from.
+    pub weekly: u32,
+    /// The number of months, counting the current one, to keep one file from.
+    pub monthly: u32,
+}
+
+/// A file selected for removal by a [`RetentionPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionVictim {
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: RetentionReason,
+}
+
+/// The order in which files are evicted to satisfy a size limit, by a
+/// [`RetentionPolicy`] or [`remove_old_files_by`](crate::remove_old_files_by).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeletionOrder {
+    /// Least recently modified first.
+    #[default]
+    OldestModified,
+    /// Least recently used first, for caches. Filesystems mounted `noatime` or `relatime`
+    /// don't record every read, so a file counts as used when it was last accessed or
+    /// modified, whichever is later.
+    LeastRecentlyAccessed,
+    /// Oldest status change time (ctime) first, e.g. a rename or permission change.
+    /// Falls back to the modification time where there is none.
+    OldestChanged,
+    /// Oldest creation (birth) time first. Falls back to the modification time on
+    /// filesystems that don't record it.
+    OldestCreated,
+    /// Largest first, freeing the space with as few deletions as possible.
+    Largest,
+}
+
+impl DeletionOrder {
+    /// Sorts `items`, given oldest modified first, into this order. The sort is stable,
+    /// so ties stay oldest modified first.
+    pub(crate) fn sort<T>(&self, items: &mut [T], entry: impl Fn(&T) -> &ManifestEntry) {
+        let time = |item: &T, other: fn(&ManifestEntry) -> Option<SystemTime>| {
+            let entry = entry(item);
+            let modified = entry.modified.unwrap_or(SystemTime::UNIX_EPOCH);
+            other(entry).unwrap_or(modified)
+        };
+        match self {
+            DeletionOrder::OldestModified => {}
+            DeletionOrder::LeastRecentlyAccessed => items.sort_by_key(|item| {
+                let modified = time(item, |e| e.modified);
+                time(item, |e| e.accessed).max(modified)
+            }),
+            DeletionOrder::OldestChanged => items.sort_by_key(|item| time(item, |e| e.changed)),
+            DeletionOrder::OldestCreated => items.sort_by_key(|item| time(item, |e| e.created)),
+            DeletionOrder::Largest => items.sort_by_key(|item| Reverse(entry(item).size)),
+        }
+    }
+}
+
+struct Candidate<'a> {
+    path: PathBuf,
+    entry: &'a ManifestEntry,
+    modified: SystemTime,
+}
+
+/// A retention policy combining size, age, count and filename rules.
+///
+/// All rules are evaluated together: a file is removed if it breaks *any* limit.
+/// Only files matching the `include` globs (all files, if there are none) and none of
+/// the `exclude` globs are eligible, but every file counts towards the size limit.
+/// Globs without a `/` match the file name at any depth; globs with a `/` match the
+/// path relative to the directory, e.g. `archive/**/*.gz`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let policy = bbq::RetentionPolicy::new()
+///     .max_size(10 * 1024 * 1024 * 1024)
+///     .max_age(Duration::from_secs(30 * 24 * 3600))
+///     .max_files(1000)
+///     .include("*.log")
+///     .exclude("current.log");
+/// let removed = policy.apply("/var/log/app").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub max_files: Option<usize>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub gfs: Option<GfsRule>,
+    /// Which files go first when the size limit is exceeded.
+    #[serde(default)]
+    pub order: DeletionOrder,
+    #[serde(default)]
+    pub min_free: Option<FreeSpace>,
+}
+
+impl RetentionPolicy {
+    /// Creates a policy without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the total size of the directory, in bytes.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Limits the age (time since last modification) of eligible files.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Limits the number of eligible files, keeping the most recently modified ones.
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = Some(count);
+        self
+    }
+
+    /// Restricts the policy to files matching `pattern`. May be called several times.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Never removes files matching `pattern`. May be called several times.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Chooses which files are evicted first to satisfy the size limit.
+    ///
+    /// The age, count and GFS rules are unaffected, but the files they select are
+    /// returned (and removed) in this order too.
+    pub fn order(mut self, order: DeletionOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Removes files, in the deletion order, until the filesystem holding the directory
+    /// has at least `target` free.
+    ///
+    /// Unlike the other rules this depends on the disk rather than the directory alone:
+    /// planning, even against a snapshot, queries the filesystem the snapshot root is on.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bbq::{FreeSpace, RetentionPolicy};
+    ///
+    /// let policy = RetentionPolicy::new().min_free(FreeSpace::Percent(15));
+    /// let removed = policy.apply("/var/cache/app").unwrap();
+    /// ```
+    pub fn min_free(mut self, target: FreeSpace) -> Self {
+        self.min_free = Some(target);
+        self
+    }
+
+    /// Thins out eligible files with a grandfather-father-son rotation.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use bbq::{GfsRule, RetentionPolicy};
+    /// use std::time::Duration;
+    ///
+    /// // All from the last 7 days, one per week for a month, one per month for a year.
+    /// let policy = RetentionPolicy::new().gfs(GfsRule {
+    ///     keep_all: Duration::from_secs(7 * 24 * 3600),
+    ///     weekly: 5,
+    ///     monthly: 12,
+    /// });
+    /// let removed = policy.apply("/var/backups/db").unwrap();
+    /// ```
+    pub fn gfs(mut self, rule: GfsRule) -> Self {
+        self.gfs = Some(rule);
+        self
+    }
+
+    /// Computes which files the policy would remove from `dir`, without removing anything.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<Vec<RetentionVictim>>` - A Result containing the files to remove, oldest first. An invalid glob returns an `ErrorKind::InvalidInput` error.
+    pub fn plan(&self, dir: &str) -> io::Result<Vec<RetentionVictim>> {
+        self.plan_snapshot(&Manifest::scan(dir)?)
+    }
+
+    /// Computes which files the policy would remove from the tree recorded in `snapshot`.
+    ///
+    /// Planning against a snapshot touches no files, so several policies can be
+    /// compared against exactly the same state of a directory.
+    pub fn plan_snapshot(&self, snapshot: &Manifest) -> io::Result<Vec<RetentionVictim>> {
+        self.plan_matching(snapshot, &|_| true)
+    }
+
+    pub(crate) fn plan_matching(
+        &self,
+        snapshot: &Manifest,
+        eligible: &dyn Fn(&ManifestEntry) -> bool,
+    ) -> io::Result<Vec<RetentionVictim>> {
+        let include = compile_globs(&self.include)?;
+        let exclude = compile_globs(&self.exclude)?;
+        let root = Path::new(&snapshot.root);
+        let mut candidates: Vec<Candidate> = Vec::new();
+        for entry in &snapshot.entries {
+            if entry.kind != EntryKind::File || !eligible(entry) {
+                continue;
+            }
+            let relative = Path::new(&entry.path);
+            let included = include.is_empty() || include.iter().any(|p| glob_matches(p, relative));
+            if !included || exclude.iter().any(|p| glob_matches(p, relative)) {
+                continue;
+            }
+            candidates.push(Candidate {
+                path: root.join(relative),
+                entry,
+                modified: entry.modified.unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+        // Newest first, so the files to keep come before the files to drop.
+        candidates.sort_by(|a, b| {
+            b.modified
+                .cmp(&a.modified)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+
+        let cutoff = self.max_age.map(|age| {
+            SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        });
+        let mut reasons: Vec<Option<RetentionReason>> = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| {
+                if cutoff.is_some_and(|cutoff| candidate.modified < cutoff) {
+                    Some(RetentionReason::Age)
+                } else if self.max_files.is_some_and(|n| i >= n) {
+                    Some(RetentionReason::Count)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        if let Some(gfs) = &self.gfs {
+            let now = SystemTime::now();
+            let keep_all = now
+                .checked_sub(gfs.keep_all)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let (this_week, this_month) = gfs_buckets(now);
+            let mut weeks = HashSet::new();
+            let mut months = HashSet::new();
+            for (candidate, reason) in candidates.iter().zip(reasons.iter_mut()) {
+                if reason.is_some() {
+                    continue;
+                }
+                let (week, month) = gfs_buckets(candidate.modified);
+                let recent = candidate.modified >= keep_all;
+                let weekly = this_week - week < gfs.weekly as i64 && weeks.insert(week);
+                let monthly = this_month - month < gfs.monthly as i64 && months.insert(month);
+                if !recent && !weekly && !monthly {
+                    *reason = Some(RetentionReason::Gfs);
+                }
+            }
+        }
+
+        // The order files are evicted in.
+        let mut order: Vec<(usize, &ManifestEntry)> = candidates
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, candidate)| (i, candidate.entry))
+            .collect();
+        self.order.sort(&mut order, |(_, entry)| *entry);
+        let order: Vec<usize> = order.into_iter().map(|(i, _)| i).collect();
+
+        if let Some(max_size) = self.max_size {
+            let mut total = snapshot.total_size();
+            for (candidate, reason) in candidates.iter().zip(&reasons) {
+                if reason.is_some() {
+                    total = total.saturating_sub(candidate.entry.size);
+                }
+            }
+            for &i in &order {
+                if total <= max_size {
+                    break;
+                }
+                if reasons[i].is_none() {
+                    reasons[i] = Some(RetentionReason::Size);
+                    total = total.saturating_sub(candidates[i].entry.size);
+                }
+            }
+        }
+
+        if let Some(target) = self.min_free {
+            let space = disk_space(&snapshot.root)?;
+            let deficit = target.bytes(space.total).saturating_sub(space.available);
+            let mut freed: u64 = candidates
+                .iter()
+                .zip(&reasons)
+                .filter(|(_, reason)| reason.is_some())
+                .map(|(candidate, _)| candidate.entry.size)
+                .sum();
+            for &i in &order {
+                if freed >= deficit {
+                    break;
+                }
+                if reasons[i].is_none() {
+                    reasons[i] = Some(RetentionReason::FreeSpace);
+                    freed += candidates[i].entry.size;
+                }
+            }
+        }
+
+        Ok(order
+            .into_iter()
+            .filter_map(|i| {
+                reasons[i].map(|reason| RetentionVictim {
+                    path: candidates[i].path.clone(),
+                    size: candidates[i].entry.size,
+                    reason,
+                })
+            })
+            .collect())
+    }
+
+    /// Applies the policy to `dir`, removing every file that breaks one of its rules.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were removed, oldest first.
+    pub fn apply(&self, dir: &str) -> io::Result<Vec<String>> {
+        ensure_writable(dir)?;
+        let mut removed_files = Vec::new();
+        for victim in self.plan(dir)? {
+            fs::remove_file(&victim.path)?;
+            removed_files.push(victim.path.to_string_lossy().into_owned());
+        }
+        Ok(removed_files)
+    }
+}
+
+// The week (Monday-based) and month indexes of a point in time, counted from the epoch.
+fn gfs_buckets(time: SystemTime) -> (i64, i64) {
+    let days = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86_400) as i64)
+        .unwrap_or(0);
+    let (year, month, _) = civil_from_days(days);
+    // 1970-01-01 was a Thursday.
+    ((days + 3).div_euclid(7), year * 12 + month as i64 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_aged(path: &Path, size: usize, age_secs: u64) {
+        fs::write(path, vec![b'x'; size]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_policy_combines_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write_aged(&root.join("ancient.log"), 10, 90_000);
+        write_aged(&root.join("a.log"), 100, 400);
+        write_aged(&root.join("b.log"), 100, 300);
+        write_aged(&root.join("c.log"), 100, 200);
+        write_aged(&root.join("d.log"), 100, 100);
+        write_aged(&root.join("keep.txt"), 100, 90_000);
+
+        let policy = RetentionPolicy::new()
+            .max_age(Duration::from_secs(86_400))
+            .max_files(3)
+            .max_size(300)
+            .include("*.log");
+        let plan = policy.plan(root.to_str().unwrap()).unwrap();
+        let summary: Vec<(String, RetentionReason)> = plan
+            .iter()
+            .map(|v| {
+                let name = v.path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, v.reason)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("ancient.log".to_string(), RetentionReason::Age),
+                ("a.log".to_string(), RetentionReason::Count),
+                ("b.log".to_string(), RetentionReason::Size),
+            ]
+        );
+
+        let removed = policy.apply(root.to_str().unwrap()).unwrap();
+        assert_eq!(removed.len(), 3);
+        assert!(root.join("keep.txt").exists());
+    }
+
+    #[test]
+    fn test_invalid_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = RetentionPolicy::new()
+            .include("[")
+            .plan(dir.path().to_str().unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_gfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = 86_400;
+        write_aged(&dir.path().join("recent"), 1, day);
+        write_aged(&dir.path().join("week-newest"), 1, 20 * day);
+        write_aged(&dir.path().join("week-older"), 1, 20 * day + 1);
+        write_aged(&dir.path().join("month"), 1, 200 * day);
+        write_aged(&dir.path().join("ancient"), 1, 400 * day);
+
+        let policy = RetentionPolicy::new().gfs(GfsRule {
+            keep_all: Duration::from_secs(7 * day),
+            weekly: 4,
+            monthly: 12,
+        });
+        let plan = policy.plan(dir.path().to_str().unwrap()).unwrap();
+        let names: Vec<String> = plan
+            .iter()
+            .map(|v| v.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["ancient", "week-older"]);
+        assert!(plan.iter().all(|v| v.reason == RetentionReason::Gfs));
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        write_aged(&dir.path().join("old-small"), 10, 300);
+        write_aged(&dir.path().join("mid-large"), 100, 200);
+        write_aged(&dir.path().join("new"), 10, 100);
+
+        let policy = RetentionPolicy::new().max_size(100);
+        let plan = policy.plan(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(plan.len(), 2);
+        let plan = policy
+            .order(DeletionOrder::Largest)
+            .plan(dir.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(plan.len(), 1);
+        assert!(plan[0].path.ends_with("mid-large"));
+    }
+
+    #[test]
+    fn test_min_free() {
+        let dir = tempfile::tempdir().unwrap();
+        let mb = 1024 * 1024;
+        write_aged(&dir.path().join("old"), mb, 300);
+        write_aged(&dir.path().join("older"), mb, 400);
+        write_aged(&dir.path().join("new"), mb, 100);
+        let root = dir.path().to_str().unwrap();
+
+        let available = disk_space(root).unwrap().available;
+        let policy =
+            RetentionPolicy::new().min_free(FreeSpace::Bytes(available + mb as u64 * 3 / 2));
+        let plan = policy.plan(root).unwrap();
+        let names: Vec<_> = plan.iter().map(|v| v.path.file_name().unwrap()).collect();
+        assert_eq!(names, ["older", "old"]);
+        assert!(plan.iter().all(|v| v.reason == RetentionReason::FreeSpace));
+
+        let policy = RetentionPolicy::new().min_free(FreeSpace::Bytes(0));
+        assert!(policy.plan(root).unwrap().is_empty());
+    }
+}
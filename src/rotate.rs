@@ -0,0 +1,415 @@
+use flate2::read::GzDecoder;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Rotates a file using numbered suffixes.
+///
+/// `file.1` becomes `file.2`, `file.2` becomes `file.3` and so on, then `file` itself is
+/// renamed to `file.1`. Compressed segments (`file.N.gz`) are shifted the same way.
+/// Segments numbered above `keep` are removed.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to rotate.
+/// * `keep` - The number of rotated segments to keep.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. If the file does not exist, nothing is rotated and `Ok(())` is returned.
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::rotate_file("/var/log/app.log", 5).unwrap();
+/// ```
+pub fn rotate_file(file: &str, keep: usize) -> io::Result<()> {
+    let path = Path::new(file);
+    if !path.exists() {
+        return Ok(());
+    }
+    for suffix in ["", ".gz"] {
+        let oldest = PathBuf::from(format!("{}.{}{}", file, keep, suffix));
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..keep).rev() {
+            let from = PathBuf::from(format!("{}.{}{}", file, n, suffix));
+            if from.exists() {
+                fs::rename(&from, format!("{}.{}{}", file, n + 1, suffix))?;
+            }
+        }
+    }
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    fs::rename(path, format!("{}.1", file))
+}
+
+/// What to do when a capped write would grow a file beyond its maximum size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeLimitAction {
+    /// Fail with `ErrorKind::FileTooLarge` and leave the file untouched.
+    Error,
+    /// Rotate the file with [`rotate_file`], keeping `keep` segments, then write to a fresh file.
+    Rotate { keep: usize },
+}
+
+fn too_large(file: &str, max_size: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        format!(
+            "{} would exceed the maximum size of {} bytes",
+            file, max_size
+        ),
+    )
+}
+
+/// Writes binary data to a file, refusing to produce a file larger than `max_size`.
+///
+/// With [`SizeLimitAction::Rotate`], an existing file that has already grown beyond
+/// `max_size` is rotated away before it is replaced; a file within the limit is simply
+/// overwritten.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to write to.
+/// * `data` - A byte slice that contains the data to write to the file.
+/// * `max_size` - The maximum size (in bytes) the file may have.
+/// * `action` - What to do with the existing file when the limit is exceeded.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. Data larger than `max_size` can never be written and always returns an `ErrorKind::FileTooLarge` error.
+pub fn write_file_capped(
+    file: &str,
+    data: &[u8],
+    max_size: u64,
+    action: SizeLimitAction,
+) -> io::Result<()> {
+    if data.len() as u64 > max_size {
+        return Err(too_large(file, max_size));
+    }
+    if let SizeLimitAction::Rotate { keep } = action {
+        let exceeded = match fs::metadata(file) {
+            Ok(metadata) => metadata.len() > max_size,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+        if exceeded {
+            rotate_file(file, keep)?;
+        }
+    }
+    fs::write(file, data)
+}
+
+/// Appends binary data to a file, keeping the file at or below `max_size`.
+///
+/// With [`SizeLimitAction::Rotate`] the file is rotated before the append that would
+/// push it past the limit, so the data always starts a new file instead of being split.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file to append to. It is created if absent.
+/// * `data` - A byte slice that contains the data to append.
+/// * `max_size` - The maximum size (in bytes) the file may have.
+/// * `action` - What to do when the limit would be exceeded.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - A Result type. Data larger than `max_size` can never be written and always returns an `ErrorKind::FileTooLarge` error.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::SizeLimitAction;
+///
+/// bbq::append_file_capped("app.log", b"line\n", 1024 * 1024, SizeLimitAction::Rotate { keep: 5 }).unwrap();
+/// ```
+pub fn append_file_capped(
+    file: &str,
+    data: &[u8],
+    max_size: u64,
+    action: SizeLimitAction,
+) -> io::Result<()> {
+    if data.len() as u64 > max_size {
+        return Err(too_large(file, max_size));
+    }
+    let current = match fs::metadata(file) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    if current + data.len() as u64 > max_size {
+        match action {
+            SizeLimitAction::Error => return Err(too_large(file, max_size)),
+            SizeLimitAction::Rotate { keep } => rotate_file(file, keep)?,
+        }
+    }
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)?;
+    f.write_all(data)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Numbered(u64),
+    Timestamped(String),
+}
+
+#[derive(Debug, Clone)]
+struct RotatedSegment {
+    path: PathBuf,
+    kind: Segment,
+    modified: SystemTime,
+}
+
+impl RotatedSegment {
+    fn cmp_age(&self, other: &Self) -> Ordering {
+        // Oldest first: earlier mtime, then higher numbers, then earlier timestamps.
+        self.modified
+            .cmp(&other.modified)
+            .then_with(|| match (&self.kind, &other.kind) {
+                (Segment::Numbered(a), Segment::Numbered(b)) => b.cmp(a),
+                (Segment::Timestamped(a), Segment::Timestamped(b)) => a.cmp(b),
+                (Segment::Numbered(_), Segment::Timestamped(_)) => Ordering::Less,
+                (Segment::Timestamped(_), Segment::Numbered(_)) => Ordering::Greater,
+            })
+    }
+}
+
+fn parse_segment(suffix: &str) -> Option<Segment> {
+    let body = suffix.strip_suffix(".gz").unwrap_or(suffix);
+    if body.is_empty() {
+        return None;
+    }
+    if body.chars().all(|c| c.is_ascii_digit()) {
+        // Short runs of digits are rotation indexes, long ones are dates such as 20240101.
+        if body.len() < 8 {
+            return body.parse().ok().map(Segment::Numbered);
+        }
+        return Some(Segment::Timestamped(body.to_string()));
+    }
+    let is_stamp = body.starts_with(|c: char| c.is_ascii_digit())
+        && body
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | '_' | 'T' | ':' | '.'));
+    if is_stamp {
+        Some(Segment::Timestamped(body.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Lists the rotated segments of a file, oldest first.
+///
+/// Recognized suffixes are numbered (`file.1`, `file.2.gz`) and timestamped
+/// (`file.20240101`, `file.2024-01-01T00-00-00.gz`) segments.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the live file.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<PathBuf>>` - A Result containing the rotated segments ordered from oldest to newest. The live file is not included.
+pub fn rotated_files(file: &str) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(file);
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return Ok(vec![]),
+    };
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", name);
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(parent)? {
+        let entry = entry?;
+        let entry_name = entry.file_name();
+        let entry_name = match entry_name.to_str() {
+            Some(n) => n,
+            None => continue,
+        };
+        let suffix = match entry_name.strip_prefix(&prefix) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+        if let Some(kind) = parse_segment(suffix) {
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            segments.push(RotatedSegment {
+                path: entry.path(),
+                kind,
+                modified: metadata.modified()?,
+            });
+        }
+    }
+    segments.sort_by(|a, b| a.cmp_age(b));
+    Ok(segments.into_iter().map(|s| s.path).collect())
+}
+
+/// A reader that streams a file across its rotations.
+///
+/// Reading starts at the oldest rotated segment and continues through newer ones
+/// before finishing with the live file, so consumers see "the log" as one stream.
+/// Gzip-compressed segments are decompressed transparently.
+pub struct RotatedReader {
+    pending: VecDeque<PathBuf>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl RotatedReader {
+    /// Opens `file` together with all of its rotated segments.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    ///
+    /// let mut reader = bbq::RotatedReader::open("/var/log/app.log").unwrap();
+    /// let mut text = String::new();
+    /// reader.read_to_string(&mut text).unwrap();
+    /// ```
+    pub fn open(file: &str) -> io::Result<Self> {
+        let mut pending: VecDeque<PathBuf> = rotated_files(file)?.into();
+        let live = PathBuf::from(file);
+        if live.exists() {
+            pending.push_back(live);
+        }
+        Ok(RotatedReader {
+            pending,
+            current: None,
+        })
+    }
+
+    /// Returns the files that have not been read yet, in reading order.
+    pub fn remaining(&self) -> impl Iterator<Item = &Path> {
+        self.pending.iter().map(|p| p.as_path())
+    }
+
+    fn open_next(&mut self) -> io::Result<bool> {
+        while let Some(path) = self.pending.pop_front() {
+            let file = match fs::File::open(&path) {
+                Ok(file) => file,
+                // A segment may be rotated away while we are reading; skip it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let compressed = path.extension().map(|e| e == "gz").unwrap_or(false);
+            self.current = Some(if compressed {
+                Box::new(GzDecoder::new(io::BufReader::new(file)))
+            } else {
+                Box::new(file)
+            });
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl Read for RotatedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(reader) = self.current.as_mut() {
+                let n = reader.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+                self.current = None;
+            }
+            if !self.open_next()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn test_rotate_and_read_across_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        let log = log.to_str().unwrap();
+
+        fs::write(log, "one\n").unwrap();
+        rotate_file(log, 3).unwrap();
+        fs::write(log, "two\n").unwrap();
+        rotate_file(log, 3).unwrap();
+        fs::write(log, "three\n").unwrap();
+
+        let mut text = String::new();
+        RotatedReader::open(log)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn test_read_compressed_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"old\n").unwrap();
+        fs::write(dir.path().join("app.log.1.gz"), encoder.finish().unwrap()).unwrap();
+        fs::write(&log, "new\n").unwrap();
+
+        let mut text = String::new();
+        RotatedReader::open(log.to_str().unwrap())
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "old\nnew\n");
+    }
+
+    #[test]
+    fn test_append_file_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("capped.log");
+        let log = log.to_str().unwrap();
+
+        append_file_capped(log, b"12345", 8, SizeLimitAction::Error).unwrap();
+        let err = append_file_capped(log, b"6789", 8, SizeLimitAction::Error).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+
+        append_file_capped(log, b"6789", 8, SizeLimitAction::Rotate { keep: 2 }).unwrap();
+        assert_eq!(fs::read(log).unwrap(), b"6789");
+        assert_eq!(fs::read(format!("{}.1", log)).unwrap(), b"12345");
+    }
+
+    #[test]
+    fn test_write_file_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("state.json");
+        let file = file.to_str().unwrap();
+        let rotate = SizeLimitAction::Rotate { keep: 2 };
+
+        write_file_capped(file, b"first", 8, rotate).unwrap();
+        write_file_capped(file, b"second", 8, rotate).unwrap();
+        assert_eq!(fs::read(file).unwrap(), b"second");
+        assert!(!Path::new(&format!("{}.1", file)).exists());
+
+        // Grown past the limit by someone else: kept as a segment.
+        fs::write(file, b"far too large").unwrap();
+        write_file_capped(file, b"third", 8, rotate).unwrap();
+        assert_eq!(fs::read(file).unwrap(), b"third");
+        assert_eq!(fs::read(format!("{}.1", file)).unwrap(), b"far too large");
+    }
+}
@@ -0,0 +1,205 @@
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+const DAY: Duration = Duration::from_secs(24 * 3600);
+
+// How long to sleep at most before looking at the clock again, so clock changes and DST
+// transitions are picked up.
+const MAX_NAP: Duration = Duration::from_secs(60);
+
+/// A daily time window in local time, such as 02:00–05:00.
+///
+/// The window includes `start` and excludes `end`. A window whose end is before its start
+/// wraps past midnight (22:00–04:00), and one whose start equals its end is always open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Creates a window from `start` to `end`.
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        TimeWindow { start, end }
+    }
+
+    /// Parses a window written as `HH:MM-HH:MM`.
+    ///
+    /// A malformed window returns an `ErrorKind::InvalidInput` error.
+    pub fn parse(spec: &str) -> io::Result<TimeWindow> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid time window {:?}, expected HH:MM-HH:MM", spec),
+            )
+        };
+        let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+        let parse = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M").map_err(|_| invalid());
+        Ok(TimeWindow::new(parse(start)?, parse(end)?))
+    }
+
+    /// Returns true if `time` falls inside the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long after `time` the window next opens; zero if it is open.
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        if self.contains(time) {
+            return Duration::ZERO;
+        }
+        let ahead = self.start.signed_duration_since(time).to_std();
+        // A negative difference means the window opens tomorrow.
+        ahead.unwrap_or_else(|_| {
+            DAY - time
+                .signed_duration_since(self.start)
+                .to_std()
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// A set of time windows heavy I/O is allowed to run in.
+///
+/// Long jobs call [`Schedule::wait`] between units of work, or wrap their streams in a
+/// [`ScheduledReader`] / [`ScheduledWriter`], to pause outside the windows and resume
+/// when the next one opens. An empty schedule is always open.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io::Read;
+///
+/// let schedule = bbq::Schedule::new(vec![bbq::TimeWindow::parse("02:00-05:00").unwrap()]);
+/// let file = std::fs::File::open("/srv/vm/disk.img").unwrap();
+/// let mut reader = bbq::ScheduledReader::new(file, schedule);
+/// let mut data = Vec::new();
+/// reader.read_to_end(&mut data).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub windows: Vec<TimeWindow>,
+}
+
+impl Schedule {
+    /// Creates a schedule from the given windows.
+    pub fn new(windows: Vec<TimeWindow>) -> Self {
+        Schedule { windows }
+    }
+
+    /// Returns true if `time` falls inside any window.
+    pub fn is_open_at(&self, time: NaiveTime) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.contains(time))
+    }
+
+    /// Returns true if a window is open now.
+    pub fn is_open(&self) -> bool {
+        self.is_open_at(Local::now().time())
+    }
+
+    /// How long after `time` the next window opens; zero if one is open.
+    pub fn until_open(&self, time: NaiveTime) -> Duration {
+        self.windows
+            .iter()
+            .map(|w| w.until_open(time))
+            .min()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Blocks until a window is open.
+    pub fn wait(&self) {
+        loop {
+            let wait = self.until_open(Local::now().time());
+            if wait.is_zero() {
+                return;
+            }
+            thread::sleep(wait.min(MAX_NAP));
+        }
+    }
+}
+
+/// A reader that only reads while its [`Schedule`] is open.
+#[derive(Debug)]
+pub struct ScheduledReader<R> {
+    inner: R,
+    schedule: Schedule,
+}
+
+impl<R> ScheduledReader<R> {
+    pub fn new(inner: R, schedule: Schedule) -> Self {
+        ScheduledReader { inner, schedule }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ScheduledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.schedule.wait();
+        self.inner.read(buf)
+    }
+}
+
+/// A writer that only writes while its [`Schedule`] is open.
+#[derive(Debug)]
+pub struct ScheduledWriter<W> {
+    inner: W,
+    schedule: Schedule,
+}
+
+impl<W> ScheduledWriter<W> {
+    pub fn new(inner: W, schedule: Schedule) -> Self {
+        ScheduledWriter { inner, schedule }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ScheduledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.schedule.wait();
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_time_windows() {
+        let night = TimeWindow::parse("22:00-04:00").unwrap();
+        assert!(night.contains(at(23, 0)));
+        assert!(night.contains(at(3, 59)));
+        assert!(!night.contains(at(4, 0)));
+        assert_eq!(night.until_open(at(21, 30)), Duration::from_secs(1800));
+
+        let schedule = Schedule::new(vec![TimeWindow::parse("02:00-05:00").unwrap()]);
+        assert!(!schedule.is_open_at(at(12, 0)));
+        assert_eq!(
+            schedule.until_open(at(6, 0)),
+            Duration::from_secs(20 * 3600)
+        );
+        assert!(Schedule::default().is_open_at(at(12, 0)));
+        assert!(TimeWindow::parse("2am").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use fs2::FileExt;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+/// The placeholder [`archive_old_files`](crate::archive_old_files) replaces with a number
+/// from [`next_sequence`].
+pub const SEQUENCE_PLACEHOLDER: &str = "{seq}";
+
+/// The counter file kept next to archives named with [`SEQUENCE_PLACEHOLDER`].
+pub const SEQUENCE_FILE_NAME: &str = ".bbq.seq";
+
+/// Increments the counter stored in `path` and returns the new value, starting at 1.
+///
+/// The file is locked while it is read and rewritten, so concurrent callers, in this
+/// process or others, never get the same number. The counter is written as decimal text
+/// and synced before the lock is released.
+///
+/// # Arguments
+///
+/// * `path` - The counter file. It is created if it doesn't exist.
+///
+/// # Returns
+///
+/// * `std::io::Result<u64>` - A Result containing the new value. A file that doesn't hold a number returns `ErrorKind::InvalidData`.
+///
+/// # Example
+///
+/// ```no_run
+/// let n = bbq::next_sequence("/backups/.build-number").unwrap();
+/// let artifact = format!("/backups/build-{:06}.tar.gz", n);
+/// ```
+pub fn next_sequence(path: &str) -> io::Result<u64> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock_exclusive()?;
+    let result = increment(&mut file, path);
+    let _ = FileExt::unlock(&file);
+    result
+}
+
+fn increment(file: &mut fs::File, path: &str) -> io::Result<u64> {
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    let current = match text.trim() {
+        "" => 0,
+        number => number.parse::<u64>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not hold a sequence number", path),
+            )
+        })?,
+    };
+    let next = current + 1;
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", next)?;
+    file.sync_data()?;
+    Ok(next)
+}
+
+/// Replaces [`SEQUENCE_PLACEHOLDER`] in `template` with the next number from the counter
+/// kept in the same directory. Templates without the placeholder are returned unchanged.
+pub(crate) fn expand_sequence(template: &str) -> io::Result<String> {
+    if !template.contains(SEQUENCE_PLACEHOLDER) {
+        return Ok(template.to_string());
+    }
+    let dir = Path::new(template)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let counter = dir.join(SEQUENCE_FILE_NAME);
+    let n = next_sequence(&counter.to_string_lossy())?;
+    Ok(template.replace(SEQUENCE_PLACEHOLDER, &n.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_next_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("counter");
+        let path = counter.to_str().unwrap().to_string();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || next_sequence(&path).unwrap())
+            })
+            .collect();
+        let mut values: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        values.sort();
+        assert_eq!(values, (1..=8).collect::<Vec<_>>());
+
+        let template = dir.path().join("app-{seq}.tar.gz");
+        let first = expand_sequence(template.to_str().unwrap()).unwrap();
+        let second = expand_sequence(template.to_str().unwrap()).unwrap();
+        assert!(first.ends_with("app-1.tar.gz") && second.ends_with("app-2.tar.gz"));
+
+        fs::write(&counter, "oops").unwrap();
+        assert_eq!(
+            next_sequence(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::disk::ensure_writable;
+use crate::info::select_old_files;
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Overwrites the contents of `file` with `passes` rounds of pseudo-random data, syncing
+/// each round to disk, and then removes it.
+///
+/// This is a best effort: on copy-on-write or log-structured filesystems (btrfs, ZFS,
+/// APFS), on SSDs with wear levelling, or where snapshots or backups hold older copies,
+/// the original blocks may survive. Symlinks are removed without touching their target.
+/// A file with other hard links is refused with `ErrorKind::InvalidInput`, since
+/// overwriting it would destroy the data behind every other link, such as the same file
+/// in hard-linked backup snapshots.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that holds the name of the file
+/// * `passes` - The number of times to overwrite the contents; zero just removes the file
+///
+/// # Example
+///
+/// ```no_run
+/// bbq::shred_file("/srv/app/secrets/old-token", 3).unwrap();
+/// ```
+pub fn shred_file(file: &str, passes: u32) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(file)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.is_file() && metadata.nlink() > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "refusing to shred {}: it has {} other hard links",
+                    file,
+                    metadata.nlink() - 1
+                ),
+            ));
+        }
+    }
+    if metadata.is_file() && passes > 0 {
+        overwrite(file, metadata.len(), passes)?;
+    }
+    fs::remove_file(file)
+}
+
+fn overwrite(file: &str, len: u64, passes: u32) -> io::Result<()> {
+    let mut handle = fs::OpenOptions::new().write(true).open(file)?;
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+        ^ u64::from(std::process::id());
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    for _ in 0..passes {
+        handle.seek(SeekFrom::Start(0))?;
+        let mut remaining = len;
+        while remaining > 0 {
+            for chunk in buffer.chunks_mut(8) {
+                state = splitmix64(state);
+                chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+            }
+            let n = remaining.min(BUFFER_SIZE as u64) as usize;
+            handle.write_all(&buffer[..n])?;
+            remaining -= n as u64;
+        }
+        handle.sync_all()?;
+    }
+    Ok(())
+}
+
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Like [`remove_old_files`](crate::remove_old_files), but shreds each file with
+/// [`shred_file`] instead of just unlinking it.
+///
+/// # Returns
+///
+/// * `std::io::Result<Vec<String>>` - A Result containing the names of the files that were shredded, oldest first.
+pub fn shred_old_files(dir: &str, keep: u64, passes: u32) -> io::Result<Vec<String>> {
+    ensure_writable(dir)?;
+    let mut shredded = Vec::new();
+    for (file, _) in select_old_files(dir, keep)? {
+        let name = file.to_string_lossy().into_owned();
+        shred_file(&name, passes)?;
+        shredded.push(name);
+    }
+    Ok(shredded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shred_file_scrubs_data() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("secret");
+        let secret = b"hunter2 ".repeat(20_000);
+        fs::write(&file, &secret).unwrap();
+        // An open handle keeps the inode readable after the file is removed.
+        let mut handle = fs::File::open(&file).unwrap();
+
+        shred_file(file.to_str().unwrap(), 2).unwrap();
+        assert!(!file.exists());
+        let mut left = Vec::new();
+        handle.read_to_end(&mut left).unwrap();
+        assert_eq!(left.len(), secret.len());
+        assert!(!left.windows(8).any(|w| w == b"hunter2 "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shred_file_refuses_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("secret");
+        fs::write(&file, "hunter2").unwrap();
+        let link = dir.path().join("snapshot");
+        fs::hard_link(&file, &link).unwrap();
+
+        let err = shred_file(file.to_str().unwrap(), 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&link).unwrap(), "hunter2");
+        assert!(file.exists());
+    }
+}
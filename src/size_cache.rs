@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDir {
+    // The directory's modification time when it was listed.
+    modified: SystemTime,
+    computed: SystemTime,
+    // The total size of the files directly inside it.
+    files_size: u64,
+    subdirs: Vec<PathBuf>,
+}
+
+/// Remembers directory sizes between calls, so sizing a huge tree again only lists the
+/// directories that changed.
+///
+/// Every directory's listing is cached with its modification time. On the next
+/// [`get_size`](SizeCache::get_size), each directory is still checked with one `stat`,
+/// but only directories whose modification time moved (files were added, removed or
+/// renamed in them) are listed again. Files that grow in place don't change their
+/// directory's modification time, so set a [`max_age`](SizeCache::with_max_age) after
+/// which listings are redone anyway, or [`invalidate`](SizeCache::invalidate) paths you
+/// know were written. Symlinks are skipped, as with [`get_size`](crate::get_size).
+///
+/// The cache is serializable, so it can be saved between runs.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let mut cache = bbq::SizeCache::new().with_max_age(Duration::from_secs(3600));
+/// let size = cache.get_size("/srv/archive").unwrap();
+/// // Later: only the changed directories are listed again.
+/// let size = cache.get_size("/srv/archive").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SizeCache {
+    dirs: HashMap<PathBuf, CachedDir>,
+    max_age: Option<Duration>,
+}
+
+impl SizeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lists directories again once their cached listing is older than `max_age`, even if
+    /// they look unchanged.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns the total size of the files under `dir`, reusing the cached listings of
+    /// directories that haven't changed.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<u64>` - A Result containing the size in bytes. The size of a file is returned as is.
+    pub fn get_size(&mut self, dir: &str) -> io::Result<u64> {
+        let path = Path::new(dir);
+        let metadata = fs::metadata(path)?;
+        if !metadata.is_dir() {
+            return Ok(metadata.len());
+        }
+        self.size_of(path, &metadata)
+    }
+
+    fn size_of(&mut self, dir: &Path, metadata: &fs::Metadata) -> io::Result<u64> {
+        let modified = metadata.modified()?;
+        let fresh = self.dirs.get(dir).is_some_and(|cached| {
+            cached.modified == modified
+                && self.max_age.is_none_or(|max_age| {
+                    cached
+                        .computed
+                        .elapsed()
+                        .is_ok_and(|elapsed| elapsed < max_age)
+                })
+        });
+        if !fresh {
+            self.list(dir, modified)?;
+        }
+        let cached = &self.dirs[dir];
+        let mut total = cached.files_size;
+        for subdir in cached.subdirs.clone() {
+            total += match fs::symlink_metadata(&subdir) {
+                Ok(metadata) if metadata.is_dir() => self.size_of(&subdir, &metadata)?,
+                // Gone or replaced since the listing; the next listing will notice.
+                _ => 0,
+            };
+        }
+        Ok(total)
+    }
+
+    fn list(&mut self, dir: &Path, modified: SystemTime) -> io::Result<()> {
+        let mut files_size = 0;
+        let mut subdirs = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                subdirs.push(entry.path());
+            } else if file_type.is_file() {
+                files_size += entry.metadata()?.len();
+            }
+        }
+        let removed: Vec<PathBuf> = self
+            .dirs
+            .get(dir)
+            .map(|old| {
+                old.subdirs
+                    .iter()
+                    .filter(|subdir| !subdirs.contains(subdir))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        for subdir in removed {
+            self.invalidate_path(&subdir);
+        }
+        self.dirs.insert(
+            dir.to_path_buf(),
+            CachedDir {
+                modified,
+                computed: SystemTime::now(),
+                files_size,
+                subdirs,
+            },
+        );
+        Ok(())
+    }
+
+    /// Forgets `path` and everything cached under it, so the next call lists them again.
+    pub fn invalidate(&mut self, path: &str) {
+        self.invalidate_path(Path::new(path));
+    }
+
+    fn invalidate_path(&mut self, path: &Path) {
+        self.dirs.retain(|dir, _| !dir.starts_with(path));
+    }
+
+    /// Forgets every listing older than the cache's max age.
+    pub fn evict_expired(&mut self) {
+        if let Some(max_age) = self.max_age {
+            self.dirs.retain(|_, cached| {
+                cached
+                    .computed
+                    .elapsed()
+                    .is_ok_and(|elapsed| elapsed < max_age)
+            });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.dirs.clear();
+    }
+
+    /// The number of directories cached.
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info::get_size;
+
+    #[test]
+    fn test_size_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("a/b/f"), vec![0u8; 100]).unwrap();
+        fs::write(dir.path().join("g"), vec![0u8; 10]).unwrap();
+        let root = dir.path().to_str().unwrap();
+        let mut cache = SizeCache::new();
+
+        assert_eq!(cache.get_size(root).unwrap(), 110);
+        assert_eq!(cache.len(), 3);
+        // A new file changes its directory's mtime, so only that listing is redone.
+        fs::write(dir.path().join("a/b/new"), vec![0u8; 5]).unwrap();
+        assert_eq!(cache.get_size(root).unwrap(), get_size(root).unwrap());
+
+        // Growing a file in place isn't seen until the directory is invalidated.
+        fs::write(dir.path().join("g"), vec![0u8; 20]).unwrap();
+        assert_eq!(cache.get_size(root).unwrap(), 115);
+        cache.invalidate(root);
+        assert_eq!(cache.get_size(root).unwrap(), 125);
+
+        fs::remove_dir_all(dir.path().join("a")).unwrap();
+        assert_eq!(cache.get_size(root).unwrap(), 20);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Distinguishes files enqueued in the same nanosecond by one process.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A spool directory: producers drop files in, a consumer processes them in arrival order.
+///
+/// Files move through four subdirectories of the spool root: `tmp` while being written,
+/// `ready` once complete, `processing` while the consumer works on them, and `failed`
+/// when the consumer gave up. Every move is a rename, so a file is never seen half
+/// written. Delivery is at least once: a file left in `processing` by a consumer that
+/// crashed is retried by the next [`Spool::drain`], so only one consumer should drain a
+/// spool at a time.
+///
+/// # Example
+///
+/// ```no_run
+/// let spool = bbq::Spool::open("/var/spool/app").unwrap();
+/// spool.enqueue_bytes("event.json", b"{\"kind\":\"login\"}").unwrap();
+///
+/// let report = spool
+///     .drain(|file| {
+///         println!("processing {}", file.display());
+///         Ok(())
+///     })
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Spool {
+    root: PathBuf,
+}
+
+/// The outcome of [`Spool::drain`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrainReport {
+    /// The files processed successfully and removed, in the order they were processed.
+    pub processed: Vec<String>,
+    /// Files the handler failed on, with their new path under `failed` and the error.
+    pub failed: Vec<(String, String)>,
+    /// Files found in `processing` from an earlier, interrupted drain and retried.
+    pub recovered: usize,
+    pub stats: Stats,
+}
+
+impl Spool {
+    /// Opens the spool at `root`, creating its directories if needed.
+    pub fn open(root: &str) -> io::Result<Spool> {
+        let spool = Spool {
+            root: PathBuf::from(root),
+        };
+        for dir in ["tmp", "ready", "processing", "failed"] {
+            fs::create_dir_all(spool.root.join(dir))?;
+        }
+        Ok(spool)
+    }
+
+    /// The directory files waiting to be processed are in.
+    pub fn ready_dir(&self) -> PathBuf {
+        self.root.join("ready")
+    }
+
+    /// The directory files the consumer failed on are parked in.
+    pub fn failed_dir(&self) -> PathBuf {
+        self.root.join("failed")
+    }
+
+    /// Adds a file named `name` holding `data` to the spool.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<PathBuf>` - A Result containing the path of the queued file.
+    pub fn enqueue_bytes(&self, name: &str, data: &[u8]) -> io::Result<PathBuf> {
+        let queued = self.queued_name(name)?;
+        let tmp = self.root.join("tmp").join(&queued);
+        fs::write(&tmp, data)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        let dest = self.ready_dir().join(queued);
+        fs::rename(&tmp, &dest)?;
+        Ok(dest)
+    }
+
+    /// Moves the file at `path` into the spool. Files on another filesystem are copied
+    /// in and then removed.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<PathBuf>` - A Result containing the path of the queued file.
+    pub fn enqueue_file(&self, path: &str) -> io::Result<PathBuf> {
+        let source = Path::new(path);
+        let name = source
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not a file name", path),
+                )
+            })?
+            .to_string_lossy();
+        let queued = self.queued_name(&name)?;
+        let dest = self.ready_dir().join(&queued);
+        if fs::rename(source, &dest).is_err() {
+            let tmp = self.root.join("tmp").join(&queued);
+            fs::copy(source, &tmp)?;
+            fs::File::open(&tmp)?.sync_all()?;
+            fs::rename(&tmp, &dest)?;
+            fs::remove_file(source)?;
+        }
+        Ok(dest)
+    }
+
+    /// The number of files waiting to be processed.
+    pub fn len(&self) -> io::Result<usize> {
+        Ok(fs::read_dir(self.ready_dir())?.count())
+    }
+
+    /// Returns true if no files are waiting to be processed.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Processes every queued file with `handler`, oldest first.
+    ///
+    /// Each file is moved to `processing` before the handler sees it. When the handler
+    /// succeeds the file is removed; when it fails the file is parked in `failed` and the
+    /// drain carries on with the next one.
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<DrainReport>` - A Result containing the files processed and failed. Only failing to read or move spool files is an error.
+    pub fn drain<F>(&self, mut handler: F) -> io::Result<DrainReport>
+    where
+        F: FnMut(&Path) -> io::Result<()>,
+    {
+        let started = Instant::now();
+        let mut report = DrainReport::default();
+        let processing = self.root.join("processing");
+        for entry in fs::read_dir(&processing)? {
+            let entry = entry?;
+            fs::rename(entry.path(), self.ready_dir().join(entry.file_name()))?;
+            report.recovered += 1;
+            report.stats.retries += 1;
+        }
+
+        let mut queued: Vec<_> = fs::read_dir(self.ready_dir())?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect::<io::Result<_>>()?;
+        // Queued names start with the zero-padded arrival time.
+        queued.sort();
+        for name in queued {
+            let current = processing.join(&name);
+            match fs::rename(self.ready_dir().join(&name), &current) {
+                Ok(()) => {}
+                // Another consumer took it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+            report.stats.files_visited += 1;
+            report.stats.bytes_read += fs::metadata(&current).map_or(0, |m| m.len());
+            match handler(&current) {
+                Ok(()) => {
+                    fs::remove_file(&current)?;
+                    report
+                        .processed
+                        .push(current.to_string_lossy().into_owned());
+                }
+                Err(e) => {
+                    let parked = self.failed_dir().join(&name);
+                    fs::rename(&current, &parked)?;
+                    report
+                        .failed
+                        .push((parked.to_string_lossy().into_owned(), e.to_string()));
+                }
+            }
+        }
+        report.stats.duration = started.elapsed();
+        Ok(report)
+    }
+
+    // A name that sorts by arrival and keeps the original name at the end.
+    fn queued_name(&self, name: &str) -> io::Result<String> {
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid spool file name {:?}", name),
+            ));
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        Ok(format!(
+            "{:020}-{:06}-{}-{}",
+            nanos,
+            sequence % 1_000_000,
+            std::process::id(),
+            name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spool_drains_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::open(dir.path().to_str().unwrap()).unwrap();
+        spool.enqueue_bytes("first", b"1").unwrap();
+        let outside = dir.path().join("second");
+        fs::write(&outside, "2").unwrap();
+        spool.enqueue_file(outside.to_str().unwrap()).unwrap();
+        spool.enqueue_bytes("third", b"3").unwrap();
+        assert!(!outside.exists());
+        assert_eq!(spool.len().unwrap(), 3);
+
+        let mut seen = Vec::new();
+        let report = spool
+            .drain(|file| {
+                let data = fs::read_to_string(file)?;
+                seen.push(data.clone());
+                if data == "2" {
+                    return Err(io::Error::other("bad record"));
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(seen, ["1", "2", "3"]);
+        assert_eq!(report.processed.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert!(Path::new(&report.failed[0].0).exists());
+        assert!(spool.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_spool_recovers_interrupted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = Spool::open(dir.path().to_str().unwrap()).unwrap();
+        let queued = spool.enqueue_bytes("job", b"x").unwrap();
+        // A consumer crashed after claiming the file.
+        fs::rename(
+            &queued,
+            dir.path()
+                .join("processing")
+                .join(queued.file_name().unwrap()),
+        )
+        .unwrap();
+
+        let report = spool.drain(|_| Ok(())).unwrap();
+        assert_eq!(report.recovered, 1);
+        assert_eq!(report.stats.retries, 1);
+        assert_eq!(report.processed.len(), 1);
+    }
+}
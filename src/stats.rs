@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Cost counters for one run of a large operation.
+///
+/// Archiving, cleanup and the other bulk operations report the same counters, so
+/// maintenance costs can be logged and trended the same way whatever the job.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Files looked at, whether or not anything was done to them.
+    pub files_visited: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Operations that were interrupted earlier and tried again, such as resumed uploads
+    /// and files a crashed spool drain left behind.
+    pub retries: u64,
+    /// Wall-clock time the operation took.
+    pub duration: Duration,
+}
+
+impl Stats {
+    /// The bytes read and written per second, or zero for an instantaneous run.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.duration.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        (self.bytes_read + self.bytes_written) as f64 / secs
+    }
+
+    /// Adds the counters of `other` to these, e.g. to total up several runs.
+    pub fn merge(&mut self, other: &Stats) {
+        self.files_visited += other.files_visited;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.retries += other.retries;
+        self.duration += other.duration;
+    }
+}
@@ -0,0 +1,623 @@
+use crate::copy::copy_metadata;
+use crate::manifest::{EntryKind, Manifest};
+use crate::part::{is_fresh_part_file, part_path, write_via_part_file};
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Options for [`sync_dirs`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncOptions {
+    /// Remove files from the destination that no longer exist in the source. Conflict
+    /// copies (see [`is_conflict_copy`]) are always kept.
+    pub delete: bool,
+    /// Only copy files that have not been modified for this long, and whose size and
+    /// modification time are the same as on the previous run, so uploads that are still
+    /// being written are picked up on a later run instead of half-copied. A new file is
+    /// therefore never copied on the run that first sees it.
+    #[serde(default)]
+    pub stability_window: Option<Duration>,
+    /// Abort if more than this many destination files would be deleted.
+    #[serde(default)]
+    pub max_delete_count: Option<usize>,
+    /// Abort if more than this percentage of the destination files would be deleted.
+    #[serde(default)]
+    pub max_delete_percent: Option<u8>,
+    /// When a file changed on both sides since the last sync (a conflict), keep the
+    /// destination's version under a [`conflict_copy_name`] instead of overwriting it.
+    /// A destination file that changed while its source didn't is simply overwritten.
+    #[serde(default)]
+    pub keep_conflicts: bool,
+    /// Also copy ownership, access times and extended attributes, as [`copy_metadata`]
+    /// does. Permission bits and modification times are always copied.
+    #[serde(default)]
+    pub preserve: bool,
+}
+
+/// The outcome of [`sync_dirs`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Files copied to the destination, as paths relative to the source.
+    pub copied: Vec<String>,
+    /// Files removed from the destination, as paths relative to it.
+    pub deleted: Vec<String>,
+    /// Files left alone this run, with the reason.
+    pub skipped: Vec<(String, String)>,
+    /// Conflicting destination files that were kept, with the name they were moved to.
+    #[serde(default)]
+    pub conflicts: Vec<(String, String)>,
+    pub stats: Stats,
+}
+
+/// The file in the destination directory where [`sync_dirs`] records the size and
+/// modification time each file had when it was last synced, and when the last run saw
+/// it. It is never copied or deleted.
+pub const SYNC_STATE_FILE: &str = ".bbq-sync.json";
+
+// The size and modification time of a file at some point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct SyncedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+// What sync_dirs keeps in SYNC_STATE_FILE between runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncState {
+    // Each file as it was on both sides right after it was last synced.
+    #[serde(default)]
+    synced: BTreeMap<String, SyncedFile>,
+    // Each source file as the last run with a stability window saw it.
+    #[serde(default)]
+    seen: BTreeMap<String, SyncedFile>,
+}
+
+fn load_sync_state(dest: &str) -> io::Result<SyncState> {
+    match fs::read(Path::new(dest).join(SYNC_STATE_FILE)) {
+        Ok(json) => {
+            serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save_sync_state(dest: &str, state: &SyncState) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(state).map_err(io::Error::other)?;
+    let file = Path::new(dest).join(SYNC_STATE_FILE);
+    write_via_part_file(&file.to_string_lossy(), &json)
+}
+
+/// Returns true if `metadata` shows no modification within the last `window`.
+///
+/// A file being appended to keeps bumping its modification time. A writer that sets the
+/// time itself, like `cp -p`, doesn't, so [`sync_dirs`] also checks that the size and
+/// modification time haven't changed since its previous run.
+pub fn is_stable(metadata: &fs::Metadata, window: Duration) -> bool {
+    match metadata.modified() {
+        Ok(modified) => SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= window),
+        Err(_) => false,
+    }
+}
+
+/// Mirrors the files of `src` into `dest`, one way.
+///
+/// A file is copied when it is missing from `dest` or differs in size or modification
+/// time; copies keep the source's modification time, so unchanged files are skipped on
+/// the next run. Each copy is written to a `.part` file and renamed into place, and
+/// source files still being written as `.part` files are skipped. Symlinks are not
+/// followed or copied.
+///
+/// The size and modification time of every synced file is recorded in the destination's
+/// [`SYNC_STATE_FILE`]. A file that differs on both sides from what was recorded, or
+/// that was never synced and already differs at the destination, is a conflict; see
+/// `keep_conflicts`.
+///
+/// With `delete` enabled, the deletion thresholds are checked before anything is
+/// copied or removed. A run that would exceed them fails with an error and leaves the
+/// destination untouched, so an empty or mistyped source cannot wipe out a replica.
+///
+/// # Arguments
+///
+/// * `src` - A string slice that holds the name of the source directory.
+/// * `dest` - A string slice that holds the name of the destination directory. It is created if needed.
+/// * `options` - Deletion and stability options.
+///
+/// # Returns
+///
+/// * `std::io::Result<SyncReport>` - A Result containing what was copied, deleted and skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::SyncOptions;
+/// use std::time::Duration;
+///
+/// let options = SyncOptions {
+///     stability_window: Some(Duration::from_secs(30)),
+///     ..Default::default()
+/// };
+/// let report = bbq::sync_dirs("/srv/uploads", "/mnt/ingest", &options).unwrap();
+/// ```
+pub fn sync_dirs(src: &str, dest: &str, options: &SyncOptions) -> io::Result<SyncReport> {
+    let started = Instant::now();
+    let mut report = SyncReport::default();
+    fs::create_dir_all(dest)?;
+    let mut source = Manifest::scan(src)?;
+    let mut target = Manifest::scan(dest)?;
+    source.entries.retain(|e| e.path != SYNC_STATE_FILE);
+    target.entries.retain(|e| e.path != SYNC_STATE_FILE);
+    let mut state = load_sync_state(dest)?;
+    let last_seen = std::mem::take(&mut state.seen);
+
+    let extraneous: Vec<&str> = if options.delete {
+        target
+            .entries
+            .iter()
+            .filter(|e| e.kind != EntryKind::Dir && source.get(&e.path).is_none())
+            // Conflict copies only exist at the destination; deleting them would lose
+            // what keep_conflicts kept.
+            .filter(|e| !is_conflict_copy(Path::new(&e.path)))
+            .filter(|e| !is_fresh_part_file(Path::new(&e.path), e.modified))
+            .map(|e| e.path.as_str())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    check_delete_threshold(extraneous.len(), &target, options)?;
+
+    for entry in &source.entries {
+        let from = Path::new(src).join(&entry.path);
+        let to = Path::new(dest).join(&entry.path);
+        match entry.kind {
+            EntryKind::Dir => {
+                fs::create_dir_all(&to)?;
+                continue;
+            }
+            EntryKind::Symlink => {
+                report
+                    .skipped
+                    .push((entry.path.clone(), "symlink".to_string()));
+                continue;
+            }
+            EntryKind::File => {}
+        }
+        if is_fresh_part_file(&from, entry.modified) {
+            report
+                .skipped
+                .push((entry.path.clone(), "still being written".to_string()));
+            continue;
+        }
+        report.stats.files_visited += 1;
+        let metadata = fs::symlink_metadata(&from)?;
+        let current = SyncedFile {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        if let Some(window) = options.stability_window {
+            state.seen.insert(entry.path.clone(), current);
+            let reason = if !is_stable(&metadata, window) {
+                "modified too recently"
+            } else if last_seen.get(&entry.path) != Some(&current) {
+                "changed since the last run"
+            } else {
+                ""
+            };
+            if !reason.is_empty() {
+                report
+                    .skipped
+                    .push((entry.path.clone(), reason.to_string()));
+                continue;
+            }
+        }
+        let up_to_date = target.get(&entry.path).is_some_and(|existing| {
+            existing.kind == EntryKind::File
+                && existing.size == current.size
+                && existing.modified == current.modified
+        });
+        if up_to_date {
+            state.synced.insert(entry.path.clone(), current);
+            continue;
+        }
+        let synced = state.synced.get(&entry.path);
+        let changed = |file: SyncedFile| synced.is_none_or(|synced| *synced != file);
+        let conflicting = changed(current)
+            && target.get(&entry.path).is_some_and(|existing| {
+                existing.kind == EntryKind::File
+                    && changed(SyncedFile {
+                        size: existing.size,
+                        modified: existing.modified,
+                    })
+            });
+        if options.keep_conflicts && conflicting {
+            let kept = keep_both(&to, &local_hostname())?;
+            report
+                .conflicts
+                .push((entry.path.clone(), kept.to_string_lossy().into_owned()));
+        }
+        copy_into_place(&from, &to, &metadata, options.preserve)?;
+        report.stats.bytes_read += metadata.len();
+        report.stats.bytes_written += metadata.len();
+        report.copied.push(entry.path.clone());
+        state.synced.insert(entry.path.clone(), current);
+    }
+
+    if options.delete {
+        for path in extraneous {
+            fs::remove_file(Path::new(dest).join(path))?;
+            report.deleted.push(path.to_string());
+            state.synced.remove(path);
+        }
+        for entry in target.entries.iter().rev() {
+            if entry.kind == EntryKind::Dir && source.get(&entry.path).is_none() {
+                let _ = fs::remove_dir(Path::new(dest).join(&entry.path));
+            }
+        }
+    }
+    state
+        .synced
+        .retain(|path, _| source.get(path).is_some() || target.get(path).is_some());
+    save_sync_state(dest, &state)?;
+    report.stats.duration = started.elapsed();
+    Ok(report)
+}
+
+/// Builds the name a conflicting copy of `path` is kept under, like Syncthing does:
+/// `report.conflict-<host>-<YYYYMMDD-HHMMSS>.txt`, with the time in UTC.
+///
+/// # Example
+///
+/// ```
+/// use std::path::Path;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(86_400);
+/// let name = bbq::conflict_copy_name(Path::new("docs/report.txt"), "laptop", time);
+/// assert_eq!(name, Path::new("docs/report.conflict-laptop-19700102-000000.txt"));
+/// ```
+pub fn conflict_copy_name(path: &Path, host: &str, time: SystemTime) -> PathBuf {
+    let stamp = chrono::DateTime::<chrono::Utc>::from(time).format("%Y%m%d-%H%M%S");
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}.conflict-{}-{}", stem, host, stamp);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Returns true if `path` is named like a [`conflict_copy_name`]: a `.conflict-<host>-`
+/// part followed by a `YYYYMMDD-HHMMSS` time, then the end of the name or the extension.
+///
+/// [`sync_dirs`] never deletes such files from the destination.
+pub fn is_conflict_copy(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let Some(start) = name.find(".conflict-") else {
+        return false;
+    };
+    // The host may itself contain dashes and dots, so look for the stamp anywhere after it.
+    let rest = &name.as_bytes()[start + ".conflict-".len()..];
+    (0..rest.len()).any(|i| {
+        let stamp = &rest[i..];
+        stamp.len() >= 16
+            && stamp[0] == b'-'
+            && stamp[1..9].iter().all(u8::is_ascii_digit)
+            && stamp[9] == b'-'
+            && stamp[10..16].iter().all(u8::is_ascii_digit)
+            && (stamp.len() == 16 || stamp[16] == b'.')
+    })
+}
+
+/// Moves the file at `path` aside under its [`conflict_copy_name`], so a conflicting
+/// version can be written in its place without losing either.
+///
+/// # Returns
+///
+/// * `std::io::Result<PathBuf>` - A Result containing the path the existing file was moved to.
+pub fn keep_both(path: &Path, host: &str) -> io::Result<PathBuf> {
+    let kept = conflict_copy_name(path, host, SystemTime::now());
+    fs::rename(path, &kept)?;
+    Ok(kept)
+}
+
+fn local_hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+fn check_delete_threshold(
+    deletions: usize,
+    target: &Manifest,
+    options: &SyncOptions,
+) -> io::Result<()> {
+    let existing = target
+        .entries
+        .iter()
+        .filter(|e| e.kind != EntryKind::Dir)
+        .count();
+    let too_many = options.max_delete_count.is_some_and(|max| deletions > max)
+        || options
+            .max_delete_percent
+            .is_some_and(|max| deletions * 100 > existing * max as usize);
+    if too_many {
+        return Err(io::Error::other(format!(
+            "refusing to delete {} of {} files at the destination: over the safety threshold",
+            deletions, existing
+        )));
+    }
+    Ok(())
+}
+
+fn copy_into_place(
+    from: &Path,
+    to: &Path,
+    metadata: &fs::Metadata,
+    preserve: bool,
+) -> io::Result<()> {
+    let tmp = part_path(to);
+    fs::copy(from, &tmp)?;
+    let file = fs::File::options().write(true).open(&tmp)?;
+    file.set_modified(metadata.modified()?)?;
+    if preserve {
+        copy_metadata(&from.to_string_lossy(), &tmp.to_string_lossy())?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("sub/done.bin"), "done").unwrap();
+        fs::write(dest.join("stale.bin"), "old").unwrap();
+        let done = fs::File::options()
+            .append(true)
+            .open(src.join("sub/done.bin"))
+            .unwrap();
+        done.set_modified(SystemTime::now() - Duration::from_secs(600))
+            .unwrap();
+        fs::write(src.join("uploading.bin"), "partial").unwrap();
+
+        let options = SyncOptions {
+            delete: true,
+            stability_window: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        // The first run only takes note of the files.
+        let report = sync_dirs(src, dest, &options).unwrap();
+        assert!(report.copied.is_empty());
+        assert_eq!(report.deleted, vec!["stale.bin"]);
+        assert_eq!(report.skipped.len(), 2);
+
+        let report = sync_dirs(src, dest, &options).unwrap();
+        assert_eq!(report.copied, vec!["sub/done.bin"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(!Path::new(dest).join("uploading.bin").exists());
+
+        let report = sync_dirs(src, dest, &options).unwrap();
+        assert!(report.copied.is_empty());
+    }
+
+    #[test]
+    fn test_sync_waits_for_size_to_settle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        fs::create_dir(&src).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(600);
+        // A writer that keeps the original modification time, like `cp -p`.
+        let write_keeping_time = |data: &str| {
+            fs::write(src.join("upload.bin"), data).unwrap();
+            fs::File::options()
+                .append(true)
+                .open(src.join("upload.bin"))
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        };
+        let options = SyncOptions {
+            stability_window: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let (src_str, dest_str) = (src.to_str().unwrap(), dest.to_str().unwrap());
+
+        write_keeping_time("half");
+        assert!(sync_dirs(src_str, dest_str, &options)
+            .unwrap()
+            .copied
+            .is_empty());
+        write_keeping_time("half and the rest");
+        let report = sync_dirs(src_str, dest_str, &options).unwrap();
+        assert_eq!(report.skipped[0].1, "changed since the last run");
+        let report = sync_dirs(src_str, dest_str, &options).unwrap();
+        assert_eq!(report.copied, ["upload.bin"]);
+        assert_eq!(
+            fs::read_to_string(dest.join("upload.bin")).unwrap(),
+            "half and the rest"
+        );
+    }
+
+    #[test]
+    fn test_sync_delete_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("empty");
+        let dest = dir.path().join("replica");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(dest.join(name), name).unwrap();
+        }
+        let options = SyncOptions {
+            delete: true,
+            max_delete_percent: Some(50),
+            ..Default::default()
+        };
+        let (src, dest_str) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        assert!(sync_dirs(src, dest_str, &options).is_err());
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 3);
+
+        let options = SyncOptions {
+            max_delete_count: Some(3),
+            max_delete_percent: None,
+            ..options
+        };
+        assert_eq!(sync_dirs(src, dest_str, &options).unwrap().deleted.len(), 3);
+    }
+
+    #[test]
+    fn test_keep_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("notes.txt"), "source").unwrap();
+        fs::File::options()
+            .append(true)
+            .open(src.join("notes.txt"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(600))
+            .unwrap();
+        fs::write(dest.join("notes.txt"), "edited at the replica").unwrap();
+
+        let options = SyncOptions {
+            keep_conflicts: true,
+            ..Default::default()
+        };
+        let report = sync_dirs(src.to_str().unwrap(), dest.to_str().unwrap(), &options).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        let kept = Path::new(&report.conflicts[0].1);
+        assert!(kept
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("notes.conflict-"));
+        assert_eq!(fs::read_to_string(kept).unwrap(), "edited at the replica");
+        assert_eq!(
+            fs::read_to_string(dest.join("notes.txt")).unwrap(),
+            "source"
+        );
+    }
+
+    #[test]
+    fn test_conflicts_need_both_sides_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        fs::create_dir_all(&src).unwrap();
+        let set_age = |path: &Path, secs: u64| {
+            fs::File::options()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(secs))
+                .unwrap();
+        };
+        fs::write(src.join("notes.txt"), "v1").unwrap();
+        set_age(&src.join("notes.txt"), 600);
+        let options = SyncOptions {
+            keep_conflicts: true,
+            ..Default::default()
+        };
+        let (src_str, dest_str) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        sync_dirs(src_str, dest_str, &options).unwrap();
+        assert!(dest.join(SYNC_STATE_FILE).exists());
+
+        // Only the replica was touched: it is brought back in line, not kept.
+        set_age(&dest.join("notes.txt"), 10);
+        let report = sync_dirs(src_str, dest_str, &options).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.copied, ["notes.txt"]);
+
+        // Both sides changed since the last sync: the replica's version is kept.
+        fs::write(src.join("notes.txt"), "v2 at the source").unwrap();
+        set_age(&src.join("notes.txt"), 300);
+        fs::write(dest.join("notes.txt"), "v2 at the replica").unwrap();
+        set_age(&dest.join("notes.txt"), 900);
+        let report = sync_dirs(src_str, dest_str, &options).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        let kept = &report.conflicts[0].1;
+        assert_eq!(fs::read_to_string(kept).unwrap(), "v2 at the replica");
+        assert_eq!(
+            fs::read_to_string(dest.join("notes.txt")).unwrap(),
+            "v2 at the source"
+        );
+    }
+
+    #[test]
+    fn test_keep_conflicts_survive_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(src.join("notes.txt"), "source").unwrap();
+        fs::File::options()
+            .append(true)
+            .open(src.join("notes.txt"))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(600))
+            .unwrap();
+        fs::write(dest.join("notes.txt"), "edited at the replica").unwrap();
+        fs::write(dest.join("stale.txt"), "old").unwrap();
+
+        let options = SyncOptions {
+            delete: true,
+            keep_conflicts: true,
+            ..Default::default()
+        };
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+        let first = sync_dirs(src, dest, &options).unwrap();
+        assert_eq!(first.deleted, ["stale.txt"]);
+        let kept = first.conflicts[0].1.clone();
+
+        let second = sync_dirs(src, dest, &options).unwrap();
+        assert!(second.deleted.is_empty());
+        assert_eq!(fs::read_to_string(kept).unwrap(), "edited at the replica");
+        assert!(is_conflict_copy(Path::new(
+            "report.conflict-host.lan-19700102-000000.txt"
+        )));
+        assert!(!is_conflict_copy(Path::new("report.conflict-notes.txt")));
+    }
+
+    #[test]
+    fn test_sync_skips_part_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("done.csv"), "a,b").unwrap();
+        fs::write(src.join("next.csv.part"), "a,").unwrap();
+        let (src, dest) = (src.to_str().unwrap(), dest.to_str().unwrap());
+
+        let report = sync_dirs(src, dest, &SyncOptions::default()).unwrap();
+        assert_eq!(report.copied, ["done.csv"]);
+        assert_eq!(report.skipped[0].0, "next.csv.part");
+        let names: Vec<_> = fs::read_dir(dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .filter(|name| name != SYNC_STATE_FILE)
+            .collect();
+        assert_eq!(names, ["done.csv"]);
+    }
+}
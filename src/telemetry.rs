@@ -0,0 +1,74 @@
+//! `tracing` instrumentation, compiled to nothing without the `tracing` feature.
+//!
+//! Operations enter a span named `bbq` with an `operation` field, and emit an event for
+//! every file they remove, move, archive or skip, so they can be followed in whatever
+//! subscriber the application has installed.
+
+use crate::stats::Stats;
+use std::path::Path;
+
+/// An entered span, exited when dropped.
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::EnteredSpan,
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn enter(operation: &'static str, dir: &Path) -> Span {
+    Span {
+        _entered: tracing::info_span!("bbq", operation, dir = %dir.display()).entered(),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn enter(_operation: &'static str, _dir: &Path) -> Span {
+    Span {}
+}
+
+pub(crate) fn file_removed(path: &Path, size: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(path = %path.display(), size, "removed");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, size);
+}
+
+pub(crate) fn file_moved(path: &Path, dest: &Path, size: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(path = %path.display(), dest = %dest.display(), size, "moved");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, dest, size);
+}
+
+pub(crate) fn file_archived(path: &Path, size: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(path = %path.display(), size, "archived");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, size);
+}
+
+pub(crate) fn file_skipped(path: &Path, reason: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(path = %path.display(), reason, "skipped");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, reason);
+}
+
+pub(crate) fn file_failed(path: &Path, error: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path.display(), error, "failed");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, error);
+}
+
+pub(crate) fn finished(stats: &Stats) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        files_visited = stats.files_visited,
+        bytes_read = stats.bytes_read,
+        bytes_written = stats.bytes_written,
+        duration_ms = stats.duration.as_millis() as u64,
+        "finished"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = stats;
+}
@@ -0,0 +1,302 @@
+use crate::find::{find_with, FindOptions};
+use crate::info::{get_dir_info, FileInfo};
+use crate::managed::ManagedDir;
+use crate::part::write_via_part_file;
+use crate::walk::{walk_with, SymlinkPolicy, Visit, WalkOptions};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Component, Path, PathBuf};
+
+/// Limits on what one tenant may store. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantQuota {
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_files: Option<u64>,
+}
+
+impl TenantQuota {
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_files(mut self, files: u64) -> Self {
+        self.max_files = Some(files);
+        self
+    }
+}
+
+/// What a tenant currently stores; see [`TenantRoot::usage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantUsage {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// A handle scoped to one tenant's directory, `base/tenant_id`, for multi-tenant services.
+///
+/// Every path given to a `TenantRoot` is relative to the tenant's directory and is
+/// checked to stay inside it: absolute paths and `..` are rejected, and so are paths
+/// that leave the directory through a symlink. Writes are checked against the tenant's
+/// [`TenantQuota`]. Hand a `TenantRoot` to a request handler instead of a raw path, and
+/// it can't reach another tenant's files. For operations without a method here, use
+/// [`resolve`](TenantRoot::resolve) to get a checked path.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{TenantQuota, TenantRoot};
+///
+/// let tenant = TenantRoot::open("/srv/tenants", "acme")
+///     .unwrap()
+///     .with_quota(TenantQuota::default().max_bytes(1024 * 1024 * 1024));
+/// tenant.write_file("reports/2024-06.json", b"{}").unwrap();
+/// assert!(tenant.read_file("../other/secrets").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantRoot {
+    tenant_id: String,
+    root: PathBuf,
+    quota: TenantQuota,
+}
+
+impl TenantRoot {
+    /// Opens the directory of `tenant_id` under `base`, creating it if needed.
+    ///
+    /// A tenant id that is empty, `.`, `..` or contains a path separator returns
+    /// `ErrorKind::InvalidInput`.
+    pub fn open(base: &str, tenant_id: &str) -> io::Result<TenantRoot> {
+        let valid = !tenant_id.is_empty()
+            && tenant_id != "."
+            && tenant_id != ".."
+            && !tenant_id.contains(['/', '\\', '\0']);
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid tenant id {:?}", tenant_id),
+            ));
+        }
+        let dir = Path::new(base).join(tenant_id);
+        fs::create_dir_all(&dir)?;
+        Ok(TenantRoot {
+            tenant_id: tenant_id.to_string(),
+            root: fs::canonicalize(dir)?,
+            quota: TenantQuota::default(),
+        })
+    }
+
+    /// Enforces `quota` on the writes made through this handle.
+    pub fn with_quota(mut self, quota: TenantQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn tenant_id(&self) -> &str {
+        &self.tenant_id
+    }
+
+    /// The absolute path of the tenant's directory.
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn quota(&self) -> TenantQuota {
+        self.quota
+    }
+
+    /// Resolves `relative` to a path inside the tenant's directory.
+    ///
+    /// An absolute path or one containing `..` returns `ErrorKind::InvalidInput`; a path
+    /// that resolves outside the directory through a symlink returns
+    /// `ErrorKind::PermissionDenied`. The path doesn't have to exist.
+    pub fn resolve(&self, relative: &str) -> io::Result<PathBuf> {
+        let mut path = self.root.clone();
+        for component in Path::new(relative).components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {}
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} is not a relative path inside the tenant", relative),
+                    ))
+                }
+            }
+        }
+        // Symlinks can only be resolved for the part of the path that exists.
+        let existing = path
+            .ancestors()
+            .find_map(|ancestor| fs::canonicalize(ancestor).ok())
+            .unwrap_or_else(|| self.root.clone());
+        if !existing.starts_with(&self.root) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} escapes the directory of tenant {}",
+                    relative, self.tenant_id
+                ),
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Counts the bytes and files the tenant stores. Symlinks are not followed.
+    pub fn usage(&self) -> io::Result<TenantUsage> {
+        let mut usage = TenantUsage::default();
+        let options = WalkOptions {
+            symlinks: SymlinkPolicy::Skip,
+            stop_on_error: true,
+            ..Default::default()
+        };
+        walk_with::<(), _>(path_str(&self.root)?, &options, |info| {
+            if info.file_type == "File" {
+                usage.bytes += info.size;
+                usage.files += 1;
+            }
+            ControlFlow::Continue(Visit::Descend)
+        })?;
+        Ok(usage)
+    }
+
+    /// Writes `data` to `relative`, creating parent directories, through a `.part` file
+    /// so readers never see it half-written.
+    ///
+    /// Returns `ErrorKind::QuotaExceeded` without writing if the tenant would go over its
+    /// quota. Overwriting a file only counts the difference in size.
+    pub fn write_file(&self, relative: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.resolve(relative)?;
+        if self.quota != TenantQuota::default() {
+            let usage = self.usage()?;
+            let replaced = fs::symlink_metadata(&path)
+                .ok()
+                .filter(|metadata| metadata.is_file());
+            let bytes = usage
+                .bytes
+                .saturating_sub(replaced.as_ref().map_or(0, |m| m.len()))
+                + data.len() as u64;
+            let files = usage.files + u64::from(replaced.is_none());
+            if self.quota.max_bytes.is_some_and(|max| bytes > max)
+                || self.quota.max_files.is_some_and(|max| files > max)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::QuotaExceeded,
+                    format!(
+                        "writing {} would exceed the quota of tenant {}",
+                        relative, self.tenant_id
+                    ),
+                ));
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_via_part_file(path_str(&path)?, data)
+    }
+
+    pub fn read_file(&self, relative: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.resolve(relative)?)
+    }
+
+    pub fn remove_file(&self, relative: &str) -> io::Result<()> {
+        fs::remove_file(self.resolve(relative)?)
+    }
+
+    pub fn create_dir_all(&self, relative: &str) -> io::Result<()> {
+        fs::create_dir_all(self.resolve(relative)?)
+    }
+
+    /// Removes the directory at `relative` and everything in it. The tenant's own
+    /// directory can't be removed this way.
+    pub fn remove_dir_all(&self, relative: &str) -> io::Result<()> {
+        let path = self.resolve(relative)?;
+        if path == self.root {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "refusing to remove the tenant's root directory",
+            ));
+        }
+        fs::remove_dir_all(path)
+    }
+
+    /// Lists the directory at `relative`; see [`get_dir_info`].
+    pub fn dir_info(&self, relative: &str) -> io::Result<Vec<FileInfo>> {
+        get_dir_info(path_str(&self.resolve(relative)?)?)
+    }
+
+    /// Finds the tenant's files matching `pattern`; see [`find_with`].
+    pub fn find(&self, pattern: &str, options: &FindOptions) -> io::Result<Vec<PathBuf>> {
+        find_with(path_str(&self.root)?, pattern, options)
+    }
+
+    /// The tenant's directory as a [`ManagedDir`], for running cleanups and retention
+    /// policies on it.
+    pub fn managed(&self) -> io::Result<ManagedDir> {
+        ManagedDir::new(path_str(&self.root)?)
+    }
+}
+
+impl AsRef<Path> for TenantRoot {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+impl fmt::Display for TenantRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root.display())
+    }
+}
+
+fn path_str(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().to_str().unwrap();
+        let acme = TenantRoot::open(base, "acme")
+            .unwrap()
+            .with_quota(TenantQuota::default().max_bytes(10));
+        let other = TenantRoot::open(base, "other").unwrap();
+        other.write_file("secret", b"s").unwrap();
+
+        acme.write_file("a/b.txt", b"hello").unwrap();
+        assert_eq!(acme.read_file("a/b.txt").unwrap(), b"hello");
+        assert_eq!(acme.usage().unwrap(), TenantUsage { bytes: 5, files: 1 });
+        // Overwriting only counts the difference.
+        acme.write_file("a/b.txt", b"0123456789").unwrap();
+        let err = acme.write_file("c.txt", b"x").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::QuotaExceeded);
+
+        for escape in ["../other/secret", "/etc/passwd"] {
+            assert_eq!(
+                acme.read_file(escape).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput
+            );
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(other.path(), acme.path().join("link")).unwrap();
+            assert_eq!(
+                acme.read_file("link/secret").unwrap_err().kind(),
+                io::ErrorKind::PermissionDenied
+            );
+        }
+        assert!(TenantRoot::open(base, "..").is_err());
+    }
+}
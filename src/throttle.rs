@@ -0,0 +1,157 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct ThrottleState {
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+/// A bytes-per-second rate limiter.
+///
+/// Clones share the same budget, so one `Throttle` handed to several readers or writers
+/// limits their combined bandwidth.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::io::Read;
+///
+/// let throttle = bbq::Throttle::new(10 * 1024 * 1024);
+/// let file = std::fs::File::open("/var/log/big.log").unwrap();
+/// let mut reader = bbq::ThrottledReader::new(file, throttle);
+/// let mut data = Vec::new();
+/// reader.read_to_end(&mut data).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    bytes_per_second: u64,
+    state: Arc<Mutex<ThrottleState>>,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `bytes_per_second` bytes per second. Zero disables limiting.
+    pub fn new(bytes_per_second: u64) -> Self {
+        Throttle {
+            bytes_per_second,
+            state: Arc::new(Mutex::new(ThrottleState {
+                window_start: Instant::now(),
+                window_bytes: 0,
+            })),
+        }
+    }
+
+    /// The configured rate in bytes per second.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Records `bytes` of I/O, sleeping as long as needed to stay under the rate.
+    pub fn consume(&self, bytes: u64) {
+        if self.bytes_per_second == 0 || bytes == 0 {
+            return;
+        }
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let elapsed = state.window_start.elapsed();
+            // Start a new window after an idle second so a pause doesn't earn a burst.
+            if elapsed > Duration::from_secs(1)
+                && state.window_bytes as f64 / self.bytes_per_second as f64 <= elapsed.as_secs_f64()
+            {
+                state.window_start = Instant::now();
+                state.window_bytes = 0;
+            }
+            state.window_bytes += bytes;
+            let expected =
+                Duration::from_secs_f64(state.window_bytes as f64 / self.bytes_per_second as f64);
+            expected.saturating_sub(state.window_start.elapsed())
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// A reader whose throughput is limited by a [`Throttle`].
+#[derive(Debug)]
+pub struct ThrottledReader<R> {
+    inner: R,
+    throttle: Throttle,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, throttle: Throttle) -> Self {
+        ThrottledReader { inner, throttle }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.throttle.consume(n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ThrottledReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// A writer whose throughput is limited by a [`Throttle`].
+#[derive(Debug)]
+pub struct ThrottledWriter<W> {
+    inner: W,
+    throttle: Throttle,
+}
+
+impl<W> ThrottledWriter<W> {
+    pub fn new(inner: W, throttle: Throttle) -> Self {
+        ThrottledWriter { inner, throttle }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.throttle.consume(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for ThrottledWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttled_reader_limits_rate() {
+        let data = vec![0u8; 64 * 1024];
+        let mut reader = ThrottledReader::new(&data[..], Throttle::new(256 * 1024));
+        let start = Instant::now();
+        let mut sink = Vec::new();
+        reader.read_to_end(&mut sink).unwrap();
+        assert_eq!(sink.len(), data.len());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
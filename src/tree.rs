@@ -0,0 +1,133 @@
+use crate::info::{file_info, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A directory tree with every entry's [`FileInfo`], as built by [`scan_tree`].
+///
+/// Serializes to nested JSON objects, ready for tree views and size treemaps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirTree {
+    pub name: String,
+    pub info: FileInfo,
+    /// The entries of a directory, sorted by name. Empty for files, and for directories
+    /// below the depth limit.
+    #[serde(default)]
+    pub children: Vec<DirTree>,
+    /// The size of the entry plus everything under it that was scanned.
+    pub total_size: u64,
+}
+
+impl DirTree {
+    /// Calls `visit` on this node and every node under it, parents first.
+    pub fn visit<F: FnMut(&DirTree)>(&self, visit: &mut F) {
+        visit(self);
+        for child in &self.children {
+            child.visit(visit);
+        }
+    }
+
+    /// The number of nodes in the tree, including this one.
+    pub fn len(&self) -> usize {
+        1 + self.children.iter().map(DirTree::len).sum::<usize>()
+    }
+
+    /// Always false: a tree holds at least its root.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Scans `dir` into a [`DirTree`].
+///
+/// Symlinks appear as leaves and are not followed. Subdirectories that can't be read
+/// appear without children.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `max_depth` - How many levels below `dir` to include, `None` for the whole tree. `Some(1)` includes only the entries of `dir`. Sizes only cover the levels scanned.
+///
+/// # Returns
+///
+/// * `std::io::Result<DirTree>` - A Result containing the tree rooted at `dir`.
+///
+/// # Example
+///
+/// ```no_run
+/// let tree = bbq::scan_tree("/srv/data", Some(3)).unwrap();
+/// std::fs::write("tree.json", serde_json::to_string(&tree).unwrap()).unwrap();
+/// ```
+pub fn scan_tree(dir: &str, max_depth: Option<usize>) -> io::Result<DirTree> {
+    let path = Path::new(dir);
+    let metadata = fs::symlink_metadata(path)?;
+    let mut tree = node(path, &metadata)?;
+    fs::read_dir(path)?;
+    fill(&mut tree, path, 1, max_depth);
+    Ok(tree)
+}
+
+fn node(path: &Path, metadata: &fs::Metadata) -> io::Result<DirTree> {
+    let info = file_info(path, metadata)?;
+    let size = if metadata.is_dir() { 0 } else { info.size };
+    Ok(DirTree {
+        name: info.file_name.clone(),
+        info,
+        children: Vec::new(),
+        total_size: size,
+    })
+}
+
+fn fill(tree: &mut DirTree, dir: &Path, depth: usize, max_depth: Option<usize>) {
+    if max_depth.is_some_and(|max| depth > max) {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let Ok(mut child) = node(&path, &metadata) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            fill(&mut child, &path, depth + 1, max_depth);
+        }
+        tree.total_size += child.total_size;
+        tree.children.push(child);
+    }
+    tree.children.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("b/c")).unwrap();
+        fs::write(dir.path().join("a.txt"), "12345").unwrap();
+        fs::write(dir.path().join("b/c/deep"), "123").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let tree = scan_tree(root, None).unwrap();
+        assert_eq!(tree.total_size, 8);
+        assert_eq!(tree.len(), 5);
+        let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "b"]);
+        assert_eq!(tree.children[1].total_size, 3);
+
+        let shallow = scan_tree(root, Some(1)).unwrap();
+        assert_eq!(shallow.len(), 3);
+        assert!(shallow.children[1].children.is_empty());
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let back: DirTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.len(), tree.len());
+    }
+}
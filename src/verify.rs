@@ -0,0 +1,255 @@
+use crate::hash::{hash_file, sha256_hex};
+use crate::manifest::{EntryKind, Manifest};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+/// The result of checking one file against its expected checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyOutcome {
+    Match,
+    /// The file hashed to `actual` instead.
+    Mismatch {
+        actual: String,
+    },
+    /// The file could not be read, e.g. because it is missing.
+    Error(String),
+}
+
+/// One file checked by [`verify_checksums`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCheck {
+    /// The path, relative to the verified root, as given in the checksums.
+    pub path: String,
+    pub outcome: VerifyOutcome,
+}
+
+impl FileCheck {
+    /// Returns true if the file matched its checksum.
+    pub fn is_ok(&self) -> bool {
+        self.outcome == VerifyOutcome::Match
+    }
+}
+
+/// Verifies the files under `root` against their expected SHA-256 checksums, hashing up
+/// to `concurrency` files at a time.
+///
+/// Results arrive on the returned channel as each file finishes, not in the order of
+/// `checksums`, and the channel closes once every file has been checked. Dropping the
+/// receiver stops the workers after the files they are hashing.
+///
+/// # Arguments
+///
+/// * `root` - A string slice that holds the name of the directory the paths are relative to
+/// * `checksums` - Expected hex SHA-256 digests, keyed by relative path
+/// * `concurrency` - The maximum number of files hashed at once (at least one)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::collections::BTreeMap;
+///
+/// let checksums: BTreeMap<String, String> =
+///     serde_json::from_str(&std::fs::read_to_string("/backups/checksums.json").unwrap()).unwrap();
+/// for check in bbq::verify_checksums("/backups/2024", &checksums, 8) {
+///     if !check.is_ok() {
+///         println!("{}: {:?}", check.path, check.outcome);
+///     }
+/// }
+/// ```
+pub fn verify_checksums(
+    root: &str,
+    checksums: &BTreeMap<String, String>,
+    concurrency: usize,
+) -> Receiver<FileCheck> {
+    let (sender, receiver) = mpsc::channel();
+    let work: Vec<(String, String)> = checksums
+        .iter()
+        .map(|(path, sum)| (path.clone(), sum.clone()))
+        .collect();
+    let workers = concurrency.max(1).min(work.len());
+    let work = Arc::new(Mutex::new(work.into_iter()));
+    for _ in 0..workers {
+        let work = Arc::clone(&work);
+        let sender = sender.clone();
+        let root = Path::new(root).to_path_buf();
+        thread::spawn(move || loop {
+            let next = work.lock().unwrap_or_else(|e| e.into_inner()).next();
+            let Some((path, expected)) = next else {
+                return;
+            };
+            let outcome = match hash_file(&root.join(&path).to_string_lossy()) {
+                Ok(actual) if actual.eq_ignore_ascii_case(&expected) => VerifyOutcome::Match,
+                Ok(actual) => VerifyOutcome::Mismatch { actual },
+                Err(e) => VerifyOutcome::Error(e.to_string()),
+            };
+            if sender.send(FileCheck { path, outcome }).is_err() {
+                return;
+            }
+        });
+    }
+    receiver
+}
+
+/// A cheap stand-in for a full checksum: the size, modification time, and a SHA-256 of
+/// just the start and end of a file.
+///
+/// Comparing fingerprints catches truncation, appends, replaced files and most bit rot
+/// near the ends of a file at a tiny fraction of the cost of hashing it, which makes it
+/// suitable for frequent drift checks between full [`verify_checksums`] runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuickFingerprint {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// The hex SHA-256 of the first and last `sample_size` bytes (the whole file if it
+    /// is at most twice that).
+    pub sample: String,
+}
+
+/// Computes the [`QuickFingerprint`] of `file`, reading at most `sample_size` bytes from
+/// each end.
+pub fn quick_fingerprint(file: &str, sample_size: u64) -> io::Result<QuickFingerprint> {
+    let mut handle = fs::File::open(file)?;
+    let metadata = handle.metadata()?;
+    let size = metadata.len();
+    let mut data = Vec::new();
+    if size <= sample_size.saturating_mul(2) {
+        handle.read_to_end(&mut data)?;
+    } else {
+        (&mut handle).take(sample_size).read_to_end(&mut data)?;
+        handle.seek(SeekFrom::Start(size - sample_size))?;
+        handle.take(sample_size).read_to_end(&mut data)?;
+    }
+    Ok(QuickFingerprint {
+        size,
+        modified: metadata.modified().ok(),
+        sample: sha256_hex(&data),
+    })
+}
+
+/// Fingerprints every file under `dir`, keyed by path relative to `dir`, as a baseline
+/// for [`quick_verify`].
+///
+/// # Example
+///
+/// ```no_run
+/// let baseline = bbq::quick_fingerprints("/srv/data", 64 * 1024).unwrap();
+/// std::fs::write("/var/lib/app/baseline.json", serde_json::to_vec(&baseline).unwrap()).unwrap();
+/// ```
+pub fn quick_fingerprints(
+    dir: &str,
+    sample_size: u64,
+) -> io::Result<BTreeMap<String, QuickFingerprint>> {
+    let manifest = Manifest::scan(dir)?;
+    let mut fingerprints = BTreeMap::new();
+    for entry in manifest.entries {
+        if entry.kind != EntryKind::File {
+            continue;
+        }
+        let path = Path::new(dir).join(&entry.path);
+        let fingerprint = quick_fingerprint(&path.to_string_lossy(), sample_size)?;
+        fingerprints.insert(entry.path, fingerprint);
+    }
+    Ok(fingerprints)
+}
+
+/// Checks the files under `dir` against a baseline from [`quick_fingerprints`], taken
+/// with the same `sample_size`.
+///
+/// Files added since the baseline are not reported.
+///
+/// # Returns
+///
+/// * `Vec<FileCheck>` - One check per file in the baseline, in path order. A changed file is a `Mismatch` whose `actual` is its new sample hash.
+pub fn quick_verify(
+    dir: &str,
+    baseline: &BTreeMap<String, QuickFingerprint>,
+    sample_size: u64,
+) -> Vec<FileCheck> {
+    baseline
+        .iter()
+        .map(|(path, expected)| {
+            let file = Path::new(dir).join(path);
+            let outcome = match quick_fingerprint(&file.to_string_lossy(), sample_size) {
+                Ok(actual) if actual == *expected => VerifyOutcome::Match,
+                Ok(actual) => VerifyOutcome::Mismatch {
+                    actual: actual.sample,
+                },
+                Err(e) => VerifyOutcome::Error(e.to_string()),
+            };
+            FileCheck {
+                path: path.clone(),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::sha256_hex;
+    use std::fs;
+
+    #[test]
+    fn test_verify_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut checksums = BTreeMap::new();
+        for i in 0..20 {
+            let name = format!("file-{}", i);
+            let data = name.repeat(i + 1);
+            fs::write(dir.path().join(&name), &data).unwrap();
+            checksums.insert(name, sha256_hex(data.as_bytes()));
+        }
+        fs::write(dir.path().join("file-3"), "tampered").unwrap();
+        checksums.insert("missing".to_string(), sha256_hex(b""));
+
+        let mut checks: Vec<FileCheck> =
+            verify_checksums(dir.path().to_str().unwrap(), &checksums, 4)
+                .iter()
+                .collect();
+        checks.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(checks.len(), 21);
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|c| !c.is_ok())
+            .map(|c| c.path.as_str())
+            .collect();
+        assert_eq!(failed, ["file-3", "missing"]);
+        assert!(matches!(checks[20].outcome, VerifyOutcome::Error(_)));
+    }
+
+    #[test]
+    fn test_quick_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let big: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        fs::write(dir.path().join("big"), &big).unwrap();
+        fs::write(dir.path().join("small"), "small").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let baseline = quick_fingerprints(root, 1024).unwrap();
+        assert_eq!(baseline.len(), 2);
+        assert!(quick_verify(root, &baseline, 1024)
+            .iter()
+            .all(|c| c.is_ok()));
+
+        let mut tail_changed = big.clone();
+        tail_changed[9_999] ^= 1;
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.path().join("big"))
+            .unwrap();
+        let modified = file.metadata().unwrap().modified().unwrap();
+        std::io::Write::write_all(&mut &file, &tail_changed).unwrap();
+        // Keep the mtime, so only the sample can tell.
+        file.set_modified(modified).unwrap();
+        let checks = quick_verify(root, &baseline, 1024);
+        assert!(matches!(checks[0].outcome, VerifyOutcome::Mismatch { .. }));
+        assert!(checks[1].is_ok());
+    }
+}
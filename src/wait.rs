@@ -0,0 +1,105 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Waits until `path` exists, checking every `poll_interval`.
+///
+/// Useful for handing work between processes through the filesystem, for example waiting
+/// for a `.done` marker next to an upload. The check is a plain poll, so it works the
+/// same on network filesystems, where change notifications are often missing.
+///
+/// # Arguments
+///
+/// * `path` - The file (or directory) to wait for.
+/// * `timeout` - How long to wait before giving up.
+/// * `poll_interval` - How long to sleep between checks.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - `Ok` as soon as the path exists, or an `ErrorKind::TimedOut` error if it still doesn't after `timeout`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// bbq::wait_for_file("/srv/inbox/batch-42.done", Duration::from_secs(600), Duration::from_secs(1)).unwrap();
+/// ```
+pub fn wait_for_file(path: &str, timeout: Duration, poll_interval: Duration) -> io::Result<()> {
+    wait_until(path, true, timeout, poll_interval)
+}
+
+/// Waits until `path` no longer exists, checking every `poll_interval`.
+///
+/// The counterpart of [`wait_for_file`], for example to wait until a consumer has picked
+/// up a file.
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - `Ok` as soon as the path is gone, or an `ErrorKind::TimedOut` error if it is still there after `timeout`.
+pub fn wait_for_absence(path: &str, timeout: Duration, poll_interval: Duration) -> io::Result<()> {
+    wait_until(path, false, timeout, poll_interval)
+}
+
+fn wait_until(
+    path: &str,
+    present: bool,
+    timeout: Duration,
+    poll_interval: Duration,
+) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // symlink_metadata, so a dangling link still counts as present.
+        let exists = match Path::new(path).symlink_metadata() {
+            Ok(_) => true,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+        if exists == present {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "timed out waiting for {} to {}",
+                    path,
+                    if present { "appear" } else { "disappear" }
+                ),
+            ));
+        }
+        thread::sleep(poll_interval.min(deadline - now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_wait_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("ready");
+        let path = marker.to_str().unwrap().to_string();
+        let poll = Duration::from_millis(5);
+
+        let err = wait_for_file(&path, Duration::from_millis(20), poll).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let writer = {
+            let marker = marker.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                fs::write(marker, "").unwrap();
+            })
+        };
+        wait_for_file(&path, Duration::from_secs(10), poll).unwrap();
+        writer.join().unwrap();
+
+        fs::remove_file(&marker).unwrap();
+        wait_for_absence(&path, Duration::ZERO, poll).unwrap();
+    }
+}
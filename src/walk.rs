@@ -0,0 +1,457 @@
+use crate::info::{content_type, file_info, FileInfo};
+use crate::part::is_fresh_part_file;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+/// Options for [`walk_with`] and [`Walk::with_options`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalkOptions {
+    /// How many levels to descend: `Some(1)` visits only the entries of the directory
+    /// itself. `None` visits the whole tree.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Stop at the first entry that can't be read, instead of skipping it.
+    #[serde(default)]
+    pub stop_on_error: bool,
+    /// What to do with symlinks.
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    /// Visit [hidden](is_hidden) entries. Defaults to `true`; when `false`, hidden
+    /// directories are skipped along with everything in them.
+    #[serde(default = "include_hidden_by_default")]
+    pub include_hidden: bool,
+    /// Fill in [`FileInfo::content_type`] by reading the start of every file. Off by
+    /// default, since it costs a read per file and updates access times. Needs the
+    /// `mime` feature; without it the content type stays `None`.
+    #[serde(default)]
+    pub detect_content_type: bool,
+}
+
+pub(crate) fn include_hidden_by_default() -> bool {
+    true
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            max_depth: None,
+            stop_on_error: false,
+            symlinks: SymlinkPolicy::default(),
+            include_hidden: include_hidden_by_default(),
+            detect_content_type: false,
+        }
+    }
+}
+
+/// Returns true if the entry at `path` is hidden: its name starts with a dot on Unix, or
+/// it has the hidden attribute on Windows. Names starting with a dot count on Windows too,
+/// since tools ported from Unix create them as hidden files.
+///
+/// `metadata` should come from `symlink_metadata`, so a link's own attribute is used.
+pub fn is_hidden(path: &Path, metadata: &fs::Metadata) -> bool {
+    let dotted = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        dotted || metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = metadata;
+        dotted
+    }
+}
+
+/// How traversals treat symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SymlinkPolicy {
+    /// Leave links out, as if they weren't there.
+    Skip,
+    /// Treat links as what they point to, descending into linked directories. Each
+    /// directory is visited once, so links pointing back up the tree can't make a walk
+    /// loop. Dangling links are errors.
+    Follow,
+    /// List links as entries of their own, with a `file_type` of `"Symlink"` and the size
+    /// of the link itself, without following them.
+    #[default]
+    Report,
+}
+
+/// What [`walk_with`] should do after visiting a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Visit {
+    /// Go on into the directory's contents.
+    #[default]
+    Descend,
+    /// Skip the directory's contents. Has no effect on files.
+    Prune,
+}
+
+/// A lazy, depth-first iterator over the entries of a directory tree, made by [`walk`].
+///
+/// Only one open directory handle per level is held, so memory stays bounded by the depth
+/// of the tree rather than the number of files in it.
+#[derive(Debug)]
+pub struct Walk {
+    max_depth: Option<usize>,
+    symlinks: SymlinkPolicy,
+    include_hidden: bool,
+    detect_content_type: bool,
+    // Whether to yield `.part` files that are still being written.
+    include_part_files: bool,
+    // The directories entered so far, when following links.
+    visited: HashSet<DirId>,
+    pending: Option<(PathBuf, usize)>,
+    // Open directories, with the depth of their entries.
+    stack: Vec<(fs::ReadDir, usize)>,
+}
+
+/// Walks `dir` lazily, yielding every file and directory below it.
+///
+/// Directories are yielded before their contents. Symlinks are yielded as links and not
+/// followed ([`SymlinkPolicy::Report`]), so links can't make the walk loop. Files still
+/// being written, `.part` files modified within [`PART_FILE_GRACE`](crate::PART_FILE_GRACE),
+/// are left out. An entry or subdirectory that can't be read yields an error, and the
+/// walk carries on with the next one; stopping early is just a matter of dropping the
+/// iterator.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+///
+/// # Returns
+///
+/// * `Walk` - An iterator of `std::io::Result<FileInfo>`. If `dir` itself can't be read, the first item is the error.
+///
+/// # Example
+///
+/// ```no_run
+/// // Is there a core dump anywhere under /srv?
+/// let found = bbq::walk("/srv")
+///     .filter_map(Result::ok)
+///     .any(|info| info.file_name.starts_with("core."));
+/// ```
+pub fn walk(dir: &str) -> Walk {
+    Walk::with_options(dir, &WalkOptions::default())
+}
+
+/// Walks `dir` once, calling `visit` with every file and directory below it.
+///
+/// `visit` can fold whatever it needs into its own state, return
+/// `ControlFlow::Continue(Visit::Prune)` to skip a directory's contents, or
+/// `ControlFlow::Break(value)` to stop the walk early. Entries that can't be read are
+/// skipped unless `options.stop_on_error` is set.
+///
+/// # Arguments
+///
+/// * `dir` - A string slice that holds the name of the directory.
+/// * `options` - How deep to go and what to do about errors.
+/// * `visit` - Called with every entry, directories before their contents.
+///
+/// # Returns
+///
+/// * `std::io::Result<Option<B>>` - A Result containing the value `visit` stopped the walk with, or `None` if it visited everything. If `dir` can't be read, it will contain the error.
+///
+/// # Example
+///
+/// ```no_run
+/// use bbq::{walk_with, Visit, WalkOptions};
+/// use std::ops::ControlFlow;
+///
+/// // Total size of the .log files, leaving out any node_modules.
+/// let mut logs = 0;
+/// walk_with::<(), _>("/srv/app", &WalkOptions::default(), |info| {
+///     if info.file_name == "node_modules" {
+///         return ControlFlow::Continue(Visit::Prune);
+///     }
+///     if info.file_name.ends_with(".log") {
+///         logs += info.size;
+///     }
+///     ControlFlow::Continue(Visit::Descend)
+/// })
+/// .unwrap();
+/// ```
+pub fn walk_with<B, F>(dir: &str, options: &WalkOptions, mut visit: F) -> io::Result<Option<B>>
+where
+    F: FnMut(&FileInfo) -> ControlFlow<B, Visit>,
+{
+    fs::read_dir(dir)?;
+    let mut entries = Walk::with_options(dir, options);
+    while let Some(entry) = entries.next() {
+        let info = match entry {
+            Ok(info) => info,
+            Err(e) if options.stop_on_error => return Err(e),
+            Err(_) => continue,
+        };
+        match visit(&info) {
+            ControlFlow::Break(value) => return Ok(Some(value)),
+            ControlFlow::Continue(Visit::Prune) => entries.skip_current_dir(),
+            ControlFlow::Continue(Visit::Descend) => {}
+        }
+    }
+    Ok(None)
+}
+
+impl Iterator for Walk {
+    type Item = io::Result<FileInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some((dir, depth)) = self.pending.take() {
+            match fs::read_dir(&dir) {
+                Ok(entries) => self.stack.push((entries, depth)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        loop {
+            let (entries, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            let entry = match entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let path = entry.path();
+            match self.visit(&path, depth) {
+                Ok(Some(info)) => return Some(Ok(info)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl Walk {
+    /// Walks `dir` like [`walk`], going no deeper than `options.max_depth` and treating
+    /// symlinks as `options.symlinks` says.
+    pub fn with_options(dir: &str, options: &WalkOptions) -> Walk {
+        let mut visited = HashSet::new();
+        if options.symlinks == SymlinkPolicy::Follow {
+            if let Some(id) = fs::metadata(dir)
+                .ok()
+                .and_then(|m| dir_id(Path::new(dir), &m))
+            {
+                visited.insert(id);
+            }
+        }
+        Walk {
+            max_depth: options.max_depth,
+            symlinks: options.symlinks,
+            include_hidden: options.include_hidden,
+            detect_content_type: options.detect_content_type,
+            include_part_files: false,
+            visited,
+            pending: Some((PathBuf::from(dir), 1)),
+            stack: Vec::new(),
+        }
+    }
+
+    // Also yields fresh `.part` files, for the cleanups that deal with them.
+    pub(crate) fn including_part_files(mut self) -> Walk {
+        self.include_part_files = true;
+        self
+    }
+
+    /// Skips the contents of the directory just yielded. Has no effect after a file.
+    pub fn skip_current_dir(&mut self) {
+        self.pending = None;
+    }
+
+    fn visit(&mut self, path: &Path, depth: usize) -> io::Result<Option<FileInfo>> {
+        let mut metadata = fs::symlink_metadata(path)?;
+        if !self.include_hidden && is_hidden(path, &metadata) {
+            return Ok(None);
+        }
+        if !self.include_part_files
+            && !metadata.is_dir()
+            && is_fresh_part_file(path, metadata.modified().ok())
+        {
+            return Ok(None);
+        }
+        if metadata.file_type().is_symlink() {
+            match self.symlinks {
+                SymlinkPolicy::Skip => return Ok(None),
+                SymlinkPolicy::Report => return file_info(path, &metadata).map(Some),
+                SymlinkPolicy::Follow => metadata = fs::metadata(path)?,
+            }
+        }
+        let mut info = file_info(path, &metadata)?;
+        if self.detect_content_type {
+            info.content_type = content_type(path, &metadata);
+        }
+        let descend = self.max_depth.is_none_or(|max| depth < max);
+        if descend && metadata.is_dir() && self.first_visit(path, &metadata) {
+            self.pending = Some((path.to_path_buf(), depth + 1));
+        }
+        Ok(Some(info))
+    }
+
+    fn first_visit(&mut self, path: &Path, metadata: &fs::Metadata) -> bool {
+        if self.symlinks != SymlinkPolicy::Follow {
+            return true;
+        }
+        match dir_id(path, metadata) {
+            Some(id) => self.visited.insert(id),
+            None => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(_path: &Path, metadata: &fs::Metadata) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path, _metadata: &fs::Metadata) -> Option<DirId> {
+    fs::canonicalize(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::write(dir.path().join("a/one"), "1").unwrap();
+        fs::write(dir.path().join("a/b/two"), "22").unwrap();
+
+        let entries: Vec<FileInfo> = walk(dir.path().to_str().unwrap())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+        let size: u64 = entries
+            .iter()
+            .filter(|info| info.file_type == "File")
+            .map(|info| info.size)
+            .sum();
+        assert_eq!(size, 3);
+
+        let shallow = Walk::with_options(
+            dir.path().to_str().unwrap(),
+            &WalkOptions {
+                max_depth: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(shallow.count(), 3);
+
+        let mut missing = walk(dir.path().join("missing").to_str().unwrap());
+        assert!(missing.next().unwrap().is_err());
+        assert!(missing.next().is_none());
+    }
+
+    #[test]
+    fn test_walk_with() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("skip/deep")).unwrap();
+        fs::write(dir.path().join("skip/deep/big"), "x".repeat(100)).unwrap();
+        fs::write(dir.path().join("small"), "x").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let mut total = 0;
+        let stopped: Option<()> = walk_with(root, &WalkOptions::default(), |info| {
+            total += info.size * (info.file_type == "File") as u64;
+            if info.file_name == "skip" {
+                ControlFlow::Continue(Visit::Prune)
+            } else {
+                ControlFlow::Continue(Visit::Descend)
+            }
+        })
+        .unwrap();
+        assert_eq!((stopped, total), (None, 1));
+
+        let found = walk_with(root, &WalkOptions::default(), |info| {
+            if info.size == 100 {
+                ControlFlow::Break(info.file_name.clone())
+            } else {
+                ControlFlow::Continue(Visit::Descend)
+            }
+        })
+        .unwrap();
+        assert_eq!(found.as_deref(), Some("big"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy() {
+        use crate::info::get_size_with;
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("real")).unwrap();
+        fs::write(dir.path().join("real/data"), "12345").unwrap();
+        symlink(dir.path().join("real"), dir.path().join("alias")).unwrap();
+        // A link back to the root would loop forever without loop detection.
+        symlink(dir.path(), dir.path().join("real/up")).unwrap();
+        let root = dir.path().to_str().unwrap();
+        let kinds = |symlinks| -> Vec<String> {
+            let options = WalkOptions {
+                symlinks,
+                ..Default::default()
+            };
+            Walk::with_options(root, &options)
+                .map(|info| info.unwrap().file_type)
+                .collect()
+        };
+
+        assert_eq!(kinds(SymlinkPolicy::Skip).len(), 2);
+        assert_eq!(
+            kinds(SymlinkPolicy::Report)
+                .iter()
+                .filter(|kind| *kind == "Symlink")
+                .count(),
+            2
+        );
+        assert!(!kinds(SymlinkPolicy::Follow).contains(&"Symlink".to_string()));
+        assert_eq!(get_size_with(root, SymlinkPolicy::Skip).unwrap(), 5);
+        assert_eq!(get_size_with(root, SymlinkPolicy::Follow).unwrap(), 5);
+    }
+
+    #[cfg(feature = "mime")]
+    #[test]
+    fn test_walk_detects_content_type_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "plain text").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let plain: Vec<FileInfo> = walk(root).collect::<io::Result<_>>().unwrap();
+        assert_eq!(plain[0].content_type, None);
+        let options = WalkOptions {
+            detect_content_type: true,
+            ..Default::default()
+        };
+        let sniffed: Vec<FileInfo> = Walk::with_options(root, &options)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(sniffed[0].content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_walk_skips_part_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("done.csv"), "a,b").unwrap();
+        fs::write(dir.path().join("next.csv.part"), "a,").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let names: Vec<_> = walk(root).map(|info| info.unwrap().file_name).collect();
+        assert_eq!(names, ["done.csv"]);
+        assert_eq!(walk(root).including_part_files().count(), 2);
+    }
+}